@@ -0,0 +1,617 @@
+/*what belongs here:
+- The background agent (`pw agent start`) that keeps a vault unlocked, similar to ssh-agent
+- The versioned request/response protocol spoken over its Unix domain socket
+- The client side used by one-shot commands
+
+Every connection carries exactly one request and one response, each a single JSON line.
+The socket directory is only accessible by the current user (0700), the socket itself is 0600.
+The agent owns the Session and its auto-lock timer, the master password never leaves the agent.
+*/
+
+use crate::config;
+use crate::errors::{SessionError, VaultError};
+use crate::keyfile::KeyFile;
+use crate::session::{Session, active_session, spawn_autolock};
+use crate::vault_entry_manager::{Entry, Vault, VaultSettings};
+use crate::vault_file_manager::{register_vault_file, registered_vault_file};
+
+use directories::ProjectDirs;
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const AGENT_SOCKET_ENV: &str = "PW_AGENT_SOCK";
+pub const EXTENSION_TOKEN_ENV: &str = "PW_EXTENSION_TOKEN";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AgentAction {
+    Status,
+    Unlock {
+        vault: String,
+        password: String,
+        // Absolute path of the key file, if the vault requires one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_file: Option<String>,
+        timeout: Option<u64>,
+        // Absolute path of the vault file, if it is not in one of the vault directories
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        file: Option<String>,
+    },
+    Lock,
+    Verify {
+        password: String,
+    },
+    List,
+    Add {
+        entry: Entry,
+    },
+    Update {
+        name: String,
+        entry: Entry,
+    },
+    // Several updates that are saved at once, e.g. when a folder is moved
+    UpdateMany {
+        updates: Vec<EntryUpdate>,
+    },
+    Delete {
+        name: String,
+    },
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryUpdate {
+    // Name of the entry before the update
+    pub name: String,
+    pub entry: Entry,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentRequest {
+    pub version: u32,
+    #[serde(flatten)]
+    pub action: AgentAction,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AgentResponse {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Name of the unlocked vault, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<Entry>,
+    // Settings of the vault, sent along with the entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<VaultSettings>,
+}
+
+impl AgentResponse {
+    fn ok(vault: Option<String>) -> AgentResponse {
+        AgentResponse {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            vault,
+            ..Default::default()
+        }
+    }
+
+    fn error(message: impl ToString) -> AgentResponse {
+        AgentResponse {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            error: Some(message.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Location of the agent socket: `$PW_AGENT_SOCK`, else the runtime dir (or data dir) of the app.
+pub fn socket_path() -> Result<PathBuf, VaultError> {
+    if let Ok(path) = std::env::var(AGENT_SOCKET_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let proj_dirs = ProjectDirs::from("", "", "password_manager").ok_or_else(|| {
+        VaultError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Home directory not found",
+        ))
+    })?;
+    let dir = match proj_dirs.runtime_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => proj_dirs.data_dir().join("agent"),
+    };
+    Ok(dir.join("agent.sock"))
+}
+
+/// Sends one request to the agent listening on the default socket.
+pub fn request(action: AgentAction) -> Result<AgentResponse, VaultError> {
+    request_at(&socket_path()?, action)
+}
+
+/// Name of the vault the agent currently holds unlocked, `None` if no agent or no vault.
+pub fn unlocked_vault() -> Option<String> {
+    request(AgentAction::Status).ok().and_then(|r| r.vault)
+}
+
+pub fn unlock(
+    vault: &str,
+    master: &SecretString,
+    key_file: Option<&Path>,
+    timeout: Option<u64>,
+) -> Result<(), VaultError> {
+    use secrecy::ExposeSecret;
+
+    request(AgentAction::Unlock {
+        vault: vault.to_string(),
+        password: master.expose_secret().to_string(),
+        key_file: key_file.map(|path| path.to_string_lossy().into_owned()),
+        timeout,
+        file: registered_vault_file(vault).map(|path| path.to_string_lossy().into_owned()),
+    })?;
+    Ok(())
+}
+
+/// Handles a single request against the agent's session.
+/// Returns the response and whether the agent should shut down afterwards.
+pub fn dispatch(
+    action: AgentAction,
+    current_session: &mut Option<Session>,
+) -> (AgentResponse, bool) {
+    // status probes and locking must not keep the vault from locking itself
+    let uses_vault = matches!(
+        action,
+        AgentAction::Verify { .. }
+            | AgentAction::List
+            | AgentAction::Add { .. }
+            | AgentAction::Update { .. }
+            | AgentAction::UpdateMany { .. }
+            | AgentAction::Delete { .. }
+    );
+
+    let result = match action {
+        AgentAction::Status => Ok(AgentResponse::ok(unlocked_name(current_session))),
+        AgentAction::Unlock {
+            vault,
+            password,
+            key_file,
+            timeout,
+            file,
+        } => unlock_vault(
+            current_session,
+            vault,
+            password.into(),
+            key_file,
+            timeout,
+            file,
+        ),
+        AgentAction::Lock => lock_vault(current_session).map(|()| AgentResponse::ok(None)),
+        AgentAction::Verify { password } => current_session
+            .as_ref()
+            .ok_or(SessionError::SessionInactive)
+            .and_then(|session| session.verify_master_pw(password.into()))
+            .map(|()| AgentResponse::ok(unlocked_name(current_session))),
+        AgentAction::List => list_entries(current_session),
+        AgentAction::Add { entry } => modify_vault(current_session, |session| {
+            vault_of(session)?
+                .add_entry(entry)
+                .map_err(SessionError::VaultError)
+        }),
+        AgentAction::Update { name, entry } => modify_vault(current_session, |session| {
+            update_entry(vault_of(session)?, name, entry)
+        }),
+        AgentAction::UpdateMany { updates } => modify_vault(current_session, |session| {
+            let vault = vault_of(session)?;
+            for update in updates {
+                update_entry(vault, update.name, update.entry)?;
+            }
+            Ok(())
+        }),
+        AgentAction::Delete { name } => modify_vault(current_session, |session| {
+            let vault = vault_of(session)?;
+            if !vault.entryname_exists(&name) {
+                return Err(SessionError::VaultError(VaultError::EntryNotFound));
+            }
+            vault.remove_entry_by_name(&name);
+            Ok(())
+        }),
+        AgentAction::Shutdown => {
+            let _ = lock_vault(current_session);
+            return (AgentResponse::ok(None), true);
+        }
+    };
+
+    match result {
+        Ok(response) => {
+            if uses_vault && let Some(session) = current_session.as_mut() {
+                session.update_activity();
+            }
+            (response, false)
+        }
+        Err(e) => (AgentResponse::error(e), false),
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{request_at, serve, spawn_background};
+
+#[cfg(not(unix))]
+pub fn request_at(_path: &Path, _action: AgentAction) -> Result<AgentResponse, VaultError> {
+    Err(VaultError::AgentNotRunning)
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _extension_token: Option<String>) -> Result<(), VaultError> {
+    Err(VaultError::AgentError(
+        "the agent is only supported on Unix systems".to_string(),
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn spawn_background(_extension_token: Option<String>) -> Result<(), VaultError> {
+    serve(Path::new(""), None)
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn unlocked_name(current_session: &Option<Session>) -> Option<String> {
+    if active_session(current_session) {
+        current_session.as_ref().map(|s| s.vault_name.clone())
+    } else {
+        None
+    }
+}
+
+fn vault_of(session: &mut Session) -> Result<&mut Vault, SessionError> {
+    session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))
+}
+
+fn unlock_vault(
+    current_session: &mut Option<Session>,
+    vault: String,
+    master: SecretString,
+    key_file: Option<String>,
+    timeout: Option<u64>,
+    file: Option<String>,
+) -> Result<AgentResponse, SessionError> {
+    if let Some(path) = file {
+        register_vault_file(Path::new(&path)).map_err(SessionError::VaultError)?;
+    }
+    if unlocked_name(current_session).as_ref() == Some(&vault) {
+        return Ok(AgentResponse::ok(Some(vault)));
+    }
+
+    let mut new_session = Session::new(vault.clone());
+    if let Some(minutes) = timeout {
+        new_session.wished_timeout = minutes * 60;
+    }
+    let key_file = key_file
+        .map(|path| KeyFile::load(Path::new(&path)))
+        .transpose()
+        .map_err(SessionError::VaultError)?;
+    new_session.start_session_with_key_file(master, key_file)?;
+
+    // Only replace the old vault once the new one is unlocked
+    lock_vault(current_session)?;
+    *current_session = Some(new_session);
+    Ok(AgentResponse::ok(Some(vault)))
+}
+
+fn lock_vault(current_session: &mut Option<Session>) -> Result<(), SessionError> {
+    if let Some(session) = current_session.as_mut() {
+        match session.end_session() {
+            Ok(()) | Err(SessionError::SessionInactive) => { /* Do nothing */ }
+            Err(e) => return Err(e),
+        }
+    }
+    *current_session = None;
+    Ok(())
+}
+
+fn list_entries(current_session: &mut Option<Session>) -> Result<AgentResponse, SessionError> {
+    let session = current_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = vault_of(session)?;
+    let entries = vault.get_entries().clone();
+    let settings = vault.settings.clone();
+    let mut response = AgentResponse::ok(Some(session.vault_name.clone()));
+    response.entries = entries;
+    response.settings = Some(settings);
+    Ok(response)
+}
+
+// Applies `change` to the unlocked vault and saves it, so the file on disk is never behind the agent.
+fn update_entry(vault: &mut Vault, name: String, entry: Entry) -> Result<(), SessionError> {
+    if *entry.get_entry_name() != name && vault.entryname_exists(entry.get_entry_name()) {
+        return Err(SessionError::VaultError(VaultError::NameExists));
+    }
+    // the client trims with the limit it was sent, trimming here keeps the vault's limit even if that changed since
+    let history_limit = vault.settings.history_count;
+    let existing = vault
+        .get_entry_by_name(&name)
+        .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+    *existing = entry;
+    existing.truncate_history(history_limit);
+    Ok(())
+}
+
+fn modify_vault<F>(
+    current_session: &mut Option<Session>,
+    change: F,
+) -> Result<AgentResponse, SessionError>
+where
+    F: FnOnce(&mut Session) -> Result<(), SessionError>,
+{
+    if !active_session(current_session) {
+        return Err(SessionError::SessionInactive);
+    }
+    let session = current_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    change(session)?;
+    session.save()?;
+    Ok(AgentResponse::ok(Some(session.vault_name.clone())))
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use crate::extension_server;
+
+    use std::fs::{self, Permissions};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Runs the agent in the foreground until it receives a `shutdown` request.
+    pub fn serve(path: &Path, extension_token: Option<String>) -> Result<(), VaultError> {
+        let listener = bind_socket(path)?;
+
+        let current_session = Arc::new(Mutex::new(None::<Session>));
+        spawn_autolock(current_session.clone(), |name, _| {
+            eprintln!("Agent: vault '{}' locked after inactivity.", name);
+        });
+
+        if let Some(token) = extension_token {
+            let server_session = current_session.clone();
+            std::thread::spawn(move || extension_server::run(server_session, token));
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Agent: failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+            match handle_connection(stream, &current_session) {
+                Ok(true) => break,
+                Ok(false) => { /* Do nothing */ }
+                Err(e) => eprintln!("Agent: error handling request: {}", e),
+            }
+        }
+
+        let _ = fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Starts `pw agent serve` as a detached background process and waits until it answers.
+    pub fn spawn_background(extension_token: Option<String>) -> Result<(), VaultError> {
+        let path = socket_path()?;
+        if request_at(&path, AgentAction::Status).is_ok() {
+            return Err(VaultError::AgentError(
+                "agent is already running".to_string(),
+            ));
+        }
+
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .args(["agent", "serve"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(token) = extension_token {
+            command.env(EXTENSION_TOKEN_ENV, token);
+        }
+        // the agent has to use the same config, --config and --vault-dir are passed on
+        let overrides = config::overrides();
+        if let Some(path) = overrides.config_file {
+            command.env(config::CONFIG_ENV, path);
+        }
+        if let Some(dir) = overrides.vault_dir {
+            command.env(config::VAULT_DIR_ENV, dir);
+        }
+        command.spawn()?;
+
+        for _ in 0..50 {
+            std::thread::sleep(Duration::from_millis(100));
+            if request_at(&path, AgentAction::Status).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(VaultError::AgentNotRunning)
+    }
+
+    pub fn request_at(path: &Path, action: AgentAction) -> Result<AgentResponse, VaultError> {
+        let mut stream = UnixStream::connect(path).map_err(|_| VaultError::AgentNotRunning)?;
+
+        let request = AgentRequest {
+            version: PROTOCOL_VERSION,
+            action,
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+
+        let mut answer = String::new();
+        BufReader::new(stream).read_line(&mut answer)?;
+        let response: AgentResponse = serde_json::from_str(&answer)?;
+
+        if response.version != PROTOCOL_VERSION {
+            return Err(VaultError::AgentError(format!(
+                "protocol version {} is not supported (expected {})",
+                response.version, PROTOCOL_VERSION
+            )));
+        }
+        if !response.ok {
+            return Err(VaultError::AgentError(
+                response
+                    .error
+                    .unwrap_or_else(|| "unknown error".to_string()),
+            ));
+        }
+        Ok(response)
+    }
+
+    fn bind_socket(path: &Path) -> Result<UnixListener, VaultError> {
+        if let Some(dir) = path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(VaultError::AgentError(
+                    "agent is already running".to_string(),
+                ));
+            }
+            // Stale socket of an agent that did not shut down cleanly
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    // Returns true if the agent should shut down.
+    fn handle_connection(
+        stream: UnixStream,
+        current_session: &Arc<Mutex<Option<Session>>>,
+    ) -> Result<bool, VaultError> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let (response, shutdown) = match serde_json::from_str::<AgentRequest>(&line) {
+            Ok(request) if request.version != PROTOCOL_VERSION => (
+                AgentResponse::error(format!(
+                    "protocol version {} is not supported (expected {})",
+                    request.version, PROTOCOL_VERSION
+                )),
+                false,
+            ),
+            Ok(request) => match current_session.lock() {
+                Ok(mut session_guard) => dispatch(request.action, &mut session_guard),
+                Err(_) => (AgentResponse::error("session state unavailable"), false),
+            },
+            Err(e) => (
+                AgentResponse::error(format!("invalid request: {}", e)),
+                false,
+            ),
+        };
+
+        let mut answer = serde_json::to_string(&response)?;
+        answer.push('\n');
+        reader.get_mut().write_all(answer.as_bytes())?;
+        Ok(shutdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_without_session() {
+        let mut current_session = None;
+        let (response, shutdown) = dispatch(AgentAction::Status, &mut current_session);
+        assert!(response.ok);
+        assert!(response.vault.is_none());
+        assert!(!shutdown);
+    }
+
+    #[test]
+    fn test_status_keeps_idle_time() {
+        let idle_since = std::time::Instant::now() - std::time::Duration::from_secs(60);
+        let mut session = Session::new("agent_idle".to_string());
+        session.last_activity = idle_since;
+        let mut current_session = Some(session);
+
+        dispatch(AgentAction::Status, &mut current_session);
+        // fails, the session has no vault
+        dispatch(AgentAction::List, &mut current_session);
+        assert_eq!(current_session.unwrap().last_activity, idle_since);
+    }
+
+    #[test]
+    fn test_list_without_session_fails() {
+        let mut current_session = None;
+        let (response, _) = dispatch(AgentAction::List, &mut current_session);
+        assert!(!response.ok);
+    }
+
+    #[test]
+    fn test_request_format() {
+        let request = AgentRequest {
+            version: PROTOCOL_VERSION,
+            action: AgentAction::Delete {
+                name: "github".to_string(),
+            },
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["action"], "delete");
+        assert_eq!(json["name"], "github");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.sock");
+
+        let server_path = path.clone();
+        let handle = std::thread::spawn(move || serve(&server_path, None));
+
+        let mut status = Err(VaultError::AgentNotRunning);
+        for _ in 0..50 {
+            status = request_at(&path, AgentAction::Status);
+            if status.is_ok() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert!(status.unwrap().vault.is_none());
+
+        let list = request_at(&path, AgentAction::List);
+        assert!(matches!(list, Err(VaultError::AgentError(_))));
+
+        assert!(request_at(&path, AgentAction::Shutdown).is_ok());
+        assert!(handle.join().unwrap().is_ok());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,286 @@
+/*what belongs here:
+- Checking every entry of a vault for weak, reused and old passwords, missing values and
+  insecure URLs, optionally for breached passwords as well (see breach.rs)
+
+The report only names entries, it never contains a password. It is built once and printed
+either as tables or as JSON by the `audit` command.
+*/
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use zxcvbn::zxcvbn;
+
+use crate::breach::{BreachDb, BreachReport, check_entries};
+use crate::errors::VaultError;
+use crate::vault_entry_manager::{Entry, Vault};
+
+// zxcvbn scores from 0 (too guessable) to 4 (very unguessable)
+pub const DEFAULT_MIN_SCORE: u8 = 3;
+pub const DEFAULT_MAX_AGE_DAYS: i64 = 365;
+
+#[derive(Debug, Clone)]
+pub struct AuditOptions {
+    // passwords with a lower zxcvbn score are weak
+    pub min_score: u8,
+    // passwords not changed for more days are old
+    pub max_age_days: i64,
+    // local copy of the Pwned Passwords list
+    pub breach_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditReport {
+    pub vault: String,
+    pub checked: usize,
+    pub weak: Vec<WeakPassword>,
+    // entries sharing the same password, one group per password
+    pub reused: Vec<Vec<String>>,
+    pub old: Vec<OldPassword>,
+    pub no_password: Vec<String>,
+    pub no_url: Vec<String>,
+    pub insecure_urls: Vec<InsecureUrl>,
+    // only checked with a breach database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaches: Option<BreachReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeakPassword {
+    pub entry: String,
+    pub score: u8,
+    // e.g. "This is a top-10 common password"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OldPassword {
+    pub entry: String,
+    pub changed: DateTime<Utc>,
+    pub days: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InsecureUrl {
+    pub entry: String,
+    pub url: String,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions {
+            min_score: DEFAULT_MIN_SCORE,
+            max_age_days: DEFAULT_MAX_AGE_DAYS,
+            breach_db: None,
+        }
+    }
+}
+
+impl AuditReport {
+    pub fn issue_count(&self) -> usize {
+        self.weak.len()
+            + self.reused.iter().map(|group| group.len()).sum::<usize>()
+            + self.old.len()
+            + self.no_password.len()
+            + self.no_url.len()
+            + self.insecure_urls.len()
+            + self
+                .breaches
+                .as_ref()
+                .map_or(0, |breaches| breaches.breached.len())
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Checks every entry of the vault, `now` is the reference for the age of passwords.
+/// Only fails if the breach database cannot be read.
+pub fn audit(
+    vault: &Vault,
+    options: &AuditOptions,
+    now: DateTime<Utc>,
+) -> Result<AuditReport, VaultError> {
+    let entries = vault.get_entries();
+    let mut report = AuditReport {
+        vault: vault.get_name().clone(),
+        checked: entries.len(),
+        weak: Vec::new(),
+        reused: reused_passwords(entries),
+        old: Vec::new(),
+        no_password: Vec::new(),
+        no_url: Vec::new(),
+        insecure_urls: Vec::new(),
+        breaches: None,
+    };
+
+    for entry in entries {
+        let name = entry.get_entry_name().clone();
+
+        match entry.password() {
+            Some(password) => {
+                if let Some(weak) = check_strength(entry, password, options.min_score) {
+                    report.weak.push(weak);
+                }
+                // entries from before timestamps were recorded have no known age
+                if let Some(changed) = entry.password_changed().or(entry.created()) {
+                    let days = (now - changed).num_days();
+                    if days > options.max_age_days {
+                        report.old.push(OldPassword {
+                            entry: name.clone(),
+                            changed,
+                            days,
+                        });
+                    }
+                }
+            }
+            None => report.no_password.push(name.clone()),
+        }
+
+        match entry.url() {
+            Some(url) if url.trim().to_ascii_lowercase().starts_with("http://") => {
+                report.insecure_urls.push(InsecureUrl {
+                    entry: name,
+                    url: url.to_string(),
+                })
+            }
+            Some(_) => {}
+            None => report.no_url.push(name),
+        }
+    }
+
+    report.weak.sort_by_key(|weak| weak.score);
+    report.old.sort_by_key(|old| std::cmp::Reverse(old.days));
+
+    if let Some(path) = &options.breach_db {
+        let db = BreachDb::open(path)?;
+        report.breaches = Some(check_entries(entries, &db)?);
+    }
+    Ok(report)
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+// Name and username of the entry count as known to an attacker
+fn check_strength(entry: &Entry, password: &str, min_score: u8) -> Option<WeakPassword> {
+    let mut user_inputs = vec![entry.get_entry_name().as_str()];
+    user_inputs.extend(entry.username());
+
+    let estimate = zxcvbn(password, &user_inputs).ok()?;
+    if estimate.score() >= min_score {
+        return None;
+    }
+    Some(WeakPassword {
+        entry: entry.get_entry_name().clone(),
+        score: estimate.score(),
+        warning: estimate
+            .feedback()
+            .as_ref()
+            .and_then(|feedback| feedback.warning())
+            .map(|warning| warning.to_string()),
+    })
+}
+
+fn reused_passwords(entries: &[Entry]) -> Vec<Vec<String>> {
+    let mut by_password: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in entries {
+        if let Some(password) = entry.password() {
+            by_password
+                .entry(password)
+                .or_default()
+                .push(entry.get_entry_name().clone());
+        }
+    }
+
+    let mut groups: Vec<Vec<String>> = by_password
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .collect();
+    // biggest groups first, otherwise the order would change with every run
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn entry(name: &str, password: Option<&str>, url: Option<&str>) -> Entry {
+        Entry::new(
+            name.to_string(),
+            Some("alice".to_string()),
+            password.map(str::to_string),
+            url.map(str::to_string),
+            None,
+        )
+    }
+
+    fn vault() -> Vault {
+        let mut vault = Vault::new("audit".to_string());
+        let strong = "correct-Horse-battery-Staple-93";
+        for entry in [
+            entry("GitHub", Some(strong), Some("https://github.com")),
+            entry("GitLab", Some(strong), Some("http://gitlab.example.com")),
+            entry(
+                "Forum",
+                Some("password1"),
+                Some("https://forum.example.com"),
+            ),
+            entry("Router", Some("Xk4!pq9#Lm2$vW7z"), None),
+            entry("Recovery codes", None, None),
+        ] {
+            vault.add_entry(entry).unwrap();
+        }
+        vault
+    }
+
+    #[test]
+    fn test_audit_finds_every_issue() {
+        let report = audit(&vault(), &AuditOptions::default(), Utc::now()).unwrap();
+        assert_eq!(report.checked, 5);
+
+        let weak: Vec<&str> = report.weak.iter().map(|w| w.entry.as_str()).collect();
+        assert_eq!(weak, vec!["Forum"]);
+        assert!(report.weak[0].score < DEFAULT_MIN_SCORE);
+        assert_eq!(report.reused, vec![vec!["GitHub", "GitLab"]]);
+        assert!(report.old.is_empty());
+        assert_eq!(report.no_password, vec!["Recovery codes"]);
+        assert_eq!(report.no_url, vec!["Router", "Recovery codes"]);
+        assert_eq!(report.insecure_urls[0].entry, "GitLab");
+        assert_eq!(report.issue_count(), 7);
+    }
+
+    #[test]
+    fn test_old_passwords() {
+        let vault = vault();
+        let in_two_years = Utc::now() + TimeDelta::days(730);
+        let report = audit(&vault, &AuditOptions::default(), in_two_years).unwrap();
+        assert_eq!(report.old.len(), 4);
+        assert!(report.old.iter().all(|old| old.days >= 729));
+
+        let options = AuditOptions {
+            max_age_days: 1000,
+            ..Default::default()
+        };
+        assert!(
+            audit(&vault, &options, in_two_years)
+                .unwrap()
+                .old
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_report_contains_no_passwords() {
+        let report = audit(&vault(), &AuditOptions::default(), Utc::now()).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(!json.contains("password1"));
+        assert!(!json.contains("correct-Horse"));
+    }
+}
@@ -0,0 +1,740 @@
+/*what belongs here:
+- The unencrypted JSON export format of Bitwarden as serde structs
+- Converting Bitwarden items into entries and entries into Bitwarden items
+
+Both directions return a report of everything that could not be represented on the other side,
+e.g. additional URIs or linked fields of Bitwarden, or tags and expiry dates of entries.
+Encrypted Bitwarden exports cannot be read, they are tied to the Bitwarden account.
+*/
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::errors::VaultError;
+use crate::import::name_from_url;
+use crate::totp::Totp;
+use crate::vault_entry_manager::{
+    CustomField, Entry, EntryVersion, FieldType, Vault, VaultSettings, normalize_folder,
+};
+
+// Item types
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+const CARD: u8 = 3;
+const IDENTITY: u8 = 4;
+const SSH_KEY: u8 = 5;
+
+// Custom field types
+const FIELD_TEXT: u8 = 0;
+const FIELD_HIDDEN: u8 = 1;
+const FIELD_BOOLEAN: u8 = 2;
+const FIELD_LINKED: u8 = 3;
+
+// Favorites become entries with this tag and the other way round
+const FAVORITE_TAG: &str = "favorite";
+// Values of cards, identities and SSH keys that are imported as hidden fields
+const SECRET_VALUES: [&str; 5] = ["number", "code", "ssn", "passportNumber", "privateKey"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitwardenExport {
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    pub items: Vec<BitwardenItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BitwardenFolder {
+    pub id: String,
+    pub name: String,
+}
+
+// Lists are `null` instead of empty in some exports, hence the options
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitwardenItem {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub organization_id: Option<String>,
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    #[serde(rename = "type")]
+    pub item_type: u8,
+    #[serde(default)]
+    pub reprompt: u8,
+    pub name: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub fields: Option<Vec<BitwardenField>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login: Option<BitwardenLogin>,
+    // cards, identities and SSH keys are only read, their values become custom fields
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub card: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<Map<String, Value>>,
+    #[serde(default)]
+    pub collection_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub password_history: Option<Vec<BitwardenPasswordHistory>>,
+    #[serde(default)]
+    pub revision_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub creation_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitwardenLogin {
+    #[serde(default)]
+    pub uris: Option<Vec<BitwardenUri>>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub totp: Option<String>,
+    #[serde(default)]
+    pub password_revision_date: Option<DateTime<Utc>>,
+    // passkeys
+    #[serde(default)]
+    pub fido2_credentials: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BitwardenUri {
+    // URL match detection, null is the default of the account
+    #[serde(rename = "match", default)]
+    pub match_type: Option<u8>,
+    #[serde(default)]
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitwardenField {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(rename = "type")]
+    pub field_type: u8,
+    #[serde(default)]
+    pub linked_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitwardenPasswordHistory {
+    pub last_used_date: DateTime<Utc>,
+    pub password: String,
+}
+
+#[derive(Debug)]
+pub struct BitwardenImport {
+    pub entries: Vec<Entry>,
+    // values that could not be represented in an entry
+    pub report: Vec<String>,
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Reads an unencrypted Bitwarden JSON export. Items in the trash are left out.
+pub fn read(json: &str) -> Result<BitwardenImport, VaultError> {
+    let export: BitwardenExport = serde_json::from_str(json)
+        .map_err(|e| VaultError::InvalidImport(format!("not a Bitwarden JSON export: {}", e)))?;
+    if export.encrypted {
+        return Err(VaultError::InvalidImport(
+            "encrypted Bitwarden exports cannot be read, export the vault as unencrypted JSON"
+                .to_string(),
+        ));
+    }
+
+    let folders: HashMap<&str, &str> = export
+        .folders
+        .iter()
+        .map(|folder| (folder.id.as_str(), folder.name.as_str()))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut report = Vec::new();
+    for item in &export.items {
+        if item.deleted_date.is_some() {
+            report.push(format!("'{}' is in the trash and was skipped", item.name));
+            continue;
+        }
+        if let Some(entry) = entry_from_item(item, &folders, &mut report) {
+            entries.push(entry);
+        }
+    }
+    Ok(BitwardenImport { entries, report })
+}
+
+/// The entries as an unencrypted Bitwarden JSON export, and what could not be represented in it.
+pub fn write(vault: &Vault) -> Result<(String, Vec<String>), VaultError> {
+    let mut report = Vec::new();
+
+    let folder_names: BTreeSet<&str> = vault
+        .get_entries()
+        .iter()
+        .filter_map(|entry| entry.get_folder())
+        .collect();
+    let folders: Vec<BitwardenFolder> = folder_names
+        .into_iter()
+        .map(|name| BitwardenFolder {
+            id: random_id(),
+            name: name.to_string(),
+        })
+        .collect();
+
+    let items = vault
+        .get_entries()
+        .iter()
+        .map(|entry| {
+            let folder_id = entry.get_folder().and_then(|name| {
+                folders
+                    .iter()
+                    .find(|folder| folder.name == name)
+                    .map(|folder| folder.id.clone())
+            });
+            item_from_entry(entry, folder_id, &mut report)
+        })
+        .collect();
+
+    let export = BitwardenExport {
+        encrypted: false,
+        folders,
+        items,
+    };
+    Ok((serde_json::to_string_pretty(&export)?, report))
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn entry_from_item(
+    item: &BitwardenItem,
+    folders: &HashMap<&str, &str>,
+    report: &mut Vec<String>,
+) -> Option<Entry> {
+    let login = item.login.as_ref();
+    let uris: Vec<&BitwardenUri> = login
+        .and_then(|login| login.uris.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|uri| uri.uri.as_deref().is_some_and(|u| !u.trim().is_empty()))
+        .collect();
+    let url = uris.first().and_then(|uri| uri.uri.clone());
+    let text = |value: Option<&String>| {
+        value
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.to_string())
+    };
+    let username = text(login.and_then(|login| login.username.as_ref()));
+    let password = text(login.and_then(|login| login.password.as_ref()));
+
+    let name = match item.name.trim() {
+        "" => url.as_deref().and_then(name_from_url)?,
+        name => name.to_string(),
+    };
+    let mut entry = Entry::new(
+        name.clone(),
+        username,
+        password,
+        url,
+        text(item.notes.as_ref()),
+    );
+
+    match item.item_type {
+        LOGIN | SECURE_NOTE => {}
+        CARD | IDENTITY | SSH_KEY => {
+            let (kind, values) = match item.item_type {
+                CARD => ("card", &item.card),
+                IDENTITY => ("identity", &item.identity),
+                _ => ("SSH key", &item.ssh_key),
+            };
+            for (key, value) in values.iter().flatten() {
+                let Some(value) = value.as_str().filter(|v| !v.trim().is_empty()) else {
+                    continue;
+                };
+                add_field(
+                    &mut entry,
+                    &field_name(key),
+                    value,
+                    SECRET_VALUES.contains(&key.as_str()),
+                    report,
+                );
+            }
+            report.push(format!(
+                "'{}' is a {}, its values were imported as custom fields",
+                name, kind
+            ));
+        }
+        other => report.push(format!(
+            "'{}' has the unknown item type {}, only name and notes were imported",
+            name, other
+        )),
+    }
+
+    for (i, uri) in uris.iter().enumerate().skip(1) {
+        let field = format!("URL {}", i + 1);
+        add_field(
+            &mut entry,
+            &field,
+            uri.uri.as_deref().unwrap_or_default(),
+            false,
+            report,
+        );
+        report.push(format!(
+            "'{}' has more than one URI, '{}' was imported as field '{}'",
+            name,
+            uri.uri.as_deref().unwrap_or_default(),
+            field
+        ));
+    }
+    if uris.iter().any(|uri| uri.match_type.is_some()) {
+        report.push(format!(
+            "URI match detection of '{}' was not imported",
+            name
+        ));
+    }
+
+    if let Some(secret) = text(login.and_then(|login| login.totp.as_ref())) {
+        match Totp::parse(&secret) {
+            Ok(totp) => entry.set_totp(totp),
+            Err(e) => report.push(format!("TOTP of '{}' was not imported: {}", name, e)),
+        }
+    }
+
+    for field in item.fields.iter().flatten() {
+        let Some(field_name) = text(field.name.as_ref()) else {
+            report.push(format!(
+                "a custom field of '{}' has no name and was skipped",
+                name
+            ));
+            continue;
+        };
+        let value = field.value.clone().unwrap_or_default();
+        match field.field_type {
+            FIELD_TEXT | FIELD_HIDDEN => add_field(
+                &mut entry,
+                &field_name,
+                &value,
+                field.field_type == FIELD_HIDDEN,
+                report,
+            ),
+            FIELD_BOOLEAN => {
+                add_field(&mut entry, &field_name, &value, false, report);
+                report.push(format!(
+                    "field '{}' of '{}' is a checkbox and was imported as text",
+                    field_name, name
+                ));
+            }
+            FIELD_LINKED => report.push(format!(
+                "field '{}' of '{}' is linked to another value and was skipped",
+                field_name, name
+            )),
+            other => report.push(format!(
+                "field '{}' of '{}' has the unknown type {} and was skipped",
+                field_name, name, other
+            )),
+        }
+    }
+
+    if let Some(folder) = item.folder_id.as_deref().and_then(|id| folders.get(id))
+        && normalize_folder(folder).is_some()
+    {
+        entry.set_folder(folder);
+    }
+    if item.favorite {
+        entry.add_tag(FAVORITE_TAG);
+    }
+
+    if item.reprompt != 0 {
+        report.push(format!(
+            "master password re-prompt of '{}' was not imported",
+            name
+        ));
+    }
+    if item.organization_id.is_some()
+        || item
+            .collection_ids
+            .as_ref()
+            .is_some_and(|ids| !ids.is_empty())
+    {
+        report.push(format!(
+            "'{}' belongs to an organization, its collections were not imported",
+            name
+        ));
+    }
+    if login
+        .and_then(|login| login.fido2_credentials.as_ref())
+        .is_some_and(|passkeys| !passkeys.is_empty())
+    {
+        report.push(format!("the passkey of '{}' was not imported", name));
+    }
+
+    // every version keeps the other values of today, Bitwarden only remembers passwords
+    let mut history: Vec<&BitwardenPasswordHistory> =
+        item.password_history.iter().flatten().collect();
+    history.sort_by_key(|version| std::cmp::Reverse(version.last_used_date));
+    let current = entry.snapshot();
+    let versions = history
+        .into_iter()
+        .map(|version| EntryVersion {
+            replaced: version.last_used_date,
+            password: Some(version.password.clone()),
+            ..current.clone()
+        })
+        .collect();
+    entry.set_history(versions, VaultSettings::default().history_count);
+
+    entry.set_dates(
+        item.creation_date,
+        item.revision_date,
+        login.and_then(|login| login.password_revision_date),
+        None,
+    );
+    Some(entry)
+}
+
+fn item_from_entry(
+    entry: &Entry,
+    folder_id: Option<String>,
+    report: &mut Vec<String>,
+) -> BitwardenItem {
+    let name = entry.get_entry_name();
+
+    let fields = entry
+        .get_fields()
+        .iter()
+        .map(|field| {
+            if !matches!(field.field_type(), FieldType::Text | FieldType::Hidden) {
+                report.push(format!(
+                    "field '{}' of '{}' was exported as a text field",
+                    field.name(),
+                    name
+                ));
+            }
+            BitwardenField {
+                name: Some(field.name().to_string()),
+                value: Some(field.value().to_string()),
+                field_type: if field.is_hidden() {
+                    FIELD_HIDDEN
+                } else {
+                    FIELD_TEXT
+                },
+                linked_id: None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let other_tags: Vec<&str> = entry
+        .get_tags()
+        .iter()
+        .map(|tag| tag.as_str())
+        .filter(|tag| *tag != FAVORITE_TAG)
+        .collect();
+    if !other_tags.is_empty() {
+        report.push(format!(
+            "tags of '{}' were not exported: {}",
+            name,
+            other_tags.join(", ")
+        ));
+    }
+    if let Some(expires) = entry.expires() {
+        report.push(format!(
+            "expiry date {} of '{}' was not exported",
+            expires, name
+        ));
+    }
+
+    // Bitwarden only keeps earlier passwords
+    let mut password_history = Vec::new();
+    let mut newer = entry.snapshot();
+    let mut lost_values = false;
+    for version in entry.get_history() {
+        let changed = version.changed_values(&newer);
+        lost_values |= changed.iter().any(|value| *value != "password");
+        if changed.contains(&"password")
+            && let Some(password) = &version.password
+        {
+            password_history.push(BitwardenPasswordHistory {
+                last_used_date: version.replaced,
+                password: password.clone(),
+            });
+        }
+        newer = version.clone();
+    }
+    if lost_values {
+        report.push(format!(
+            "earlier versions of '{}' were exported as password history only",
+            name
+        ));
+    }
+
+    BitwardenItem {
+        id: Some(random_id()),
+        organization_id: None,
+        folder_id,
+        item_type: LOGIN,
+        reprompt: 0,
+        name: name.clone(),
+        notes: entry.get_notes().clone(),
+        favorite: entry.has_tag(FAVORITE_TAG),
+        fields: Some(fields),
+        login: Some(BitwardenLogin {
+            uris: Some(
+                entry
+                    .url()
+                    .map(|url| BitwardenUri {
+                        match_type: None,
+                        uri: Some(url.to_string()),
+                    })
+                    .into_iter()
+                    .collect(),
+            ),
+            username: entry.username().map(str::to_string),
+            password: entry.password().map(str::to_string),
+            totp: entry.get_totp().as_ref().map(|totp| totp.to_uri(name)),
+            password_revision_date: entry.password_changed(),
+            fido2_credentials: Some(Vec::new()),
+        }),
+        card: None,
+        identity: None,
+        ssh_key: None,
+        collection_ids: None,
+        password_history: Some(password_history),
+        revision_date: entry.modified(),
+        creation_date: entry.created(),
+        deleted_date: None,
+    }
+}
+
+fn add_field(entry: &mut Entry, name: &str, value: &str, hidden: bool, report: &mut Vec<String>) {
+    let field_type = match (hidden, value.contains('\n')) {
+        (true, _) => FieldType::Hidden,
+        (false, true) => FieldType::Multiline,
+        (false, false) => FieldType::Text,
+    };
+    match CustomField::new(name.to_string(), field_type, value.to_string()) {
+        Ok(field) => entry.set_field(field),
+        Err(e) => report.push(format!(
+            "field '{}' of '{}' was not imported: {}",
+            name,
+            entry.get_entry_name(),
+            e
+        )),
+    }
+}
+
+// e.g. cardholderName -> Cardholder name
+fn field_name(key: &str) -> String {
+    let mut name = String::new();
+    for (i, c) in key.chars().enumerate() {
+        if i == 0 {
+            name.extend(c.to_uppercase());
+        } else if c.is_uppercase() {
+            name.push(' ');
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+// Random UUID (version 4) as used by Bitwarden for folders and items
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rng().fill(&mut bytes[..]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+      "encrypted": false,
+      "folders": [{ "id": "f1", "name": "Work/Dev" }],
+      "items": [
+        {
+          "id": "i1", "organizationId": null, "folderId": "f1", "type": 1, "reprompt": 1,
+          "name": "GitHub", "notes": "two\nlines", "favorite": true,
+          "fields": [
+            { "name": "PIN", "value": "1234", "type": 1, "linkedId": null },
+            { "name": "Admin", "value": "true", "type": 2, "linkedId": null },
+            { "name": "User", "value": null, "type": 3, "linkedId": 100 }
+          ],
+          "login": {
+            "uris": [
+              { "match": null, "uri": "https://github.com" },
+              { "match": 3, "uri": "https://gist.github.com" }
+            ],
+            "username": "alice", "password": "current",
+            "totp": "otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP&period=60",
+            "passwordRevisionDate": "2024-03-01T10:00:00.000Z",
+            "fido2Credentials": []
+          },
+          "collectionIds": null,
+          "passwordHistory": [
+            { "lastUsedDate": "2023-01-01T10:00:00.000Z", "password": "first" },
+            { "lastUsedDate": "2024-03-01T10:00:00.000Z", "password": "second" }
+          ],
+          "revisionDate": "2024-03-01T10:00:00.000Z",
+          "creationDate": "2022-01-01T10:00:00.000Z",
+          "deletedDate": null
+        },
+        {
+          "id": "i2", "type": 3, "name": "Visa", "notes": null, "favorite": false,
+          "card": { "cardholderName": "Alice", "brand": "Visa", "number": "4111111111111111",
+                    "expMonth": "1", "expYear": "2030", "code": "123" }
+        },
+        {
+          "id": "i3", "type": 1, "name": "Old", "deletedDate": "2024-01-01T00:00:00.000Z",
+          "login": { "username": "old" }
+        }
+      ]
+    }"#;
+
+    fn report_mentions(report: &[String], text: &str) -> bool {
+        report.iter().any(|line| line.contains(text))
+    }
+
+    #[test]
+    fn test_read_bitwarden_export() {
+        let imported = read(EXPORT).unwrap();
+        assert_eq!(imported.entries.len(), 2);
+
+        let github = &imported.entries[0];
+        assert_eq!(github.username(), Some("alice"));
+        assert_eq!(github.password(), Some("current"));
+        assert_eq!(github.url(), Some("https://github.com"));
+        assert_eq!(github.get_notes().as_deref(), Some("two\nlines"));
+        assert_eq!(github.get_folder(), Some("Work/Dev"));
+        assert!(github.has_tag("favorite"));
+        assert_eq!(github.get_totp().as_ref().unwrap().period(), 60);
+        assert!(github.get_field("PIN").unwrap().is_hidden());
+        assert_eq!(github.get_field("Admin").unwrap().value(), "true");
+        assert_eq!(
+            github.get_field("URL 2").unwrap().value(),
+            "https://gist.github.com"
+        );
+        assert!(github.get_field("User").is_none());
+        let history: Vec<_> = github
+            .get_history()
+            .iter()
+            .map(|version| version.password.as_deref().unwrap())
+            .collect();
+        assert_eq!(history, vec!["second", "first"]);
+        assert_eq!(
+            github.created().unwrap().to_rfc3339(),
+            "2022-01-01T10:00:00+00:00"
+        );
+
+        let visa = &imported.entries[1];
+        assert_eq!(visa.get_field("Cardholder name").unwrap().value(), "Alice");
+        assert!(visa.get_field("Number").unwrap().is_hidden());
+        assert!(visa.get_field("Code").unwrap().is_hidden());
+
+        let report = &imported.report;
+        assert!(report_mentions(report, "'Old' is in the trash"));
+        assert!(report_mentions(report, "'Visa' is a card"));
+        assert!(report_mentions(report, "imported as field 'URL 2'"));
+        assert!(report_mentions(report, "URI match detection of 'GitHub'"));
+        assert!(report_mentions(report, "'Admin' of 'GitHub' is a checkbox"));
+        assert!(report_mentions(report, "'User' of 'GitHub' is linked"));
+        assert!(report_mentions(report, "re-prompt of 'GitHub'"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut vault = Vault::new("bitwarden".to_string());
+        let mut entry = Entry::new(
+            "GitHub".to_string(),
+            Some("alice".to_string()),
+            Some("first".to_string()),
+            Some("https://github.com".to_string()),
+            Some("notes".to_string()),
+        );
+        entry.set_folder("work/dev");
+        entry.add_tag("favorite");
+        entry.add_tag("dev");
+        entry.set_totp(Totp::parse("JBSWY3DPEHPK3PXP").unwrap());
+        entry.set_field(
+            CustomField::new("PIN".to_string(), FieldType::Hidden, "1234".to_string()).unwrap(),
+        );
+        entry.set_field(
+            CustomField::new(
+                "Codes".to_string(),
+                FieldType::Multiline,
+                "a\nb".to_string(),
+            )
+            .unwrap(),
+        );
+        let previous = entry.snapshot();
+        entry.set_password("second".to_string());
+        entry.keep_version(previous, 10);
+        vault.add_entry(entry.clone()).unwrap();
+
+        let (json, report) = write(&vault).unwrap();
+        assert!(report_mentions(
+            &report,
+            "tags of 'GitHub' were not exported: dev"
+        ));
+        assert!(report_mentions(
+            &report,
+            "'Codes' of 'GitHub' was exported as a text"
+        ));
+
+        let imported = read(&json).unwrap();
+        assert!(imported.report.is_empty());
+        let copy = &imported.entries[0];
+        assert_eq!(copy.get_entry_name(), entry.get_entry_name());
+        assert_eq!(copy.username(), entry.username());
+        assert_eq!(copy.password(), Some("second"));
+        assert_eq!(copy.url(), entry.url());
+        assert_eq!(copy.get_notes(), entry.get_notes());
+        assert_eq!(copy.get_folder(), Some("work/dev"));
+        assert_eq!(copy.get_tags(), &vec!["favorite".to_string()]);
+        assert_eq!(copy.get_totp(), entry.get_totp());
+        assert_eq!(copy.get_fields(), entry.get_fields());
+        assert_eq!(copy.get_history().len(), 1);
+        assert_eq!(copy.get_history()[0].password.as_deref(), Some("first"));
+        assert_eq!(copy.created(), entry.created());
+    }
+
+    #[test]
+    fn test_encrypted_exports_are_rejected() {
+        assert!(read(r#"{ "encrypted": true, "items": [] }"#).is_err());
+        assert!(read("name,password\n").is_err());
+    }
+}
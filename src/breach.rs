@@ -0,0 +1,347 @@
+/*what belongs here:
+- Looking up passwords in a local copy of the Pwned Passwords list of haveibeenpwned.com
+
+Two layouts of the list are supported, both with upper case SHA-1 hashes:
+  sorted file      one `<40 hex digits>:<count>` line per hash, ordered by hash
+                   (pwned-passwords-sha1-ordered-by-hash-*.txt)
+  range directory  one file per 5 digit prefix (`00000` ... `FFFFF`, optionally `.txt`)
+                   with `<35 hex digits>:<count>` lines, as served by the range API
+
+Both are searched with binary search, the sorted file directly on disk. Nothing is ever sent
+anywhere, and neither passwords nor hashes are written to disk or printed. Only the entry
+names and how often their password was seen end up in the report.
+*/
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::errors::VaultError;
+use crate::vault_entry_manager::Entry;
+
+const HASH_DIGITS: usize = 40;
+const PREFIX_DIGITS: usize = 5;
+
+#[derive(Debug)]
+pub enum BreachDb {
+    SortedFile(PathBuf),
+    RangeDirectory(PathBuf),
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BreachReport {
+    pub breached: Vec<BreachedPassword>,
+    // entries whose range file is missing in the range directory
+    pub unchecked: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreachedPassword {
+    pub entry: String,
+    // how often the password appears in breaches
+    pub count: u64,
+}
+
+// Upper case hex digits of a SHA-1 hash, only kept in memory
+type HexHash = [u8; HASH_DIGITS];
+
+impl BreachDb {
+    /// A directory is read as range directory, a file as sorted hash file.
+    pub fn open(path: &Path) -> Result<BreachDb, VaultError> {
+        let metadata = fs::metadata(path).map_err(|e| {
+            VaultError::AnyhowError(anyhow::anyhow!(
+                "Could not open the breach database {:?}: {}",
+                path,
+                e
+            ))
+        })?;
+        if metadata.is_dir() {
+            return Ok(BreachDb::RangeDirectory(path.to_path_buf()));
+        }
+
+        let mut first_line = String::new();
+        BufReader::new(File::open(path)?).read_line(&mut first_line)?;
+        if parse_line(first_line.as_bytes(), HASH_DIGITS).is_none() {
+            return Err(VaultError::AnyhowError(anyhow::anyhow!(
+                "{:?} is not a Pwned Passwords hash file (SHA-1, ordered by hash)",
+                path
+            )));
+        }
+        Ok(BreachDb::SortedFile(path.to_path_buf()))
+    }
+
+    // None if the range file of the hash is missing
+    fn lookup(&self, hash: &HexHash) -> Result<Option<u64>, VaultError> {
+        match self {
+            BreachDb::SortedFile(path) => search_sorted_file(path, hash).map(Some),
+            BreachDb::RangeDirectory(dir) => search_range_directory(dir, hash),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Looks up the password of every entry, every distinct password only once.
+pub fn check_entries(entries: &[Entry], db: &BreachDb) -> Result<BreachReport, VaultError> {
+    let mut counts: HashMap<&str, Option<u64>> = HashMap::new();
+    let mut report = BreachReport::default();
+
+    for entry in entries {
+        let Some(password) = entry.password() else {
+            continue;
+        };
+        let count = match counts.get(password) {
+            Some(count) => *count,
+            None => {
+                let count = db.lookup(&hex_hash(password))?;
+                counts.insert(password, count);
+                count
+            }
+        };
+
+        match count {
+            // padding lines of the range API have a count of 0
+            Some(0) => {}
+            Some(count) => report.breached.push(BreachedPassword {
+                entry: entry.get_entry_name().clone(),
+                count,
+            }),
+            None => report.unchecked.push(entry.get_entry_name().clone()),
+        }
+    }
+
+    report
+        .breached
+        .sort_by_key(|breached| std::cmp::Reverse(breached.count));
+    Ok(report)
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn hex_hash(password: &str) -> HexHash {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let digest = Sha1::digest(password.as_bytes());
+    let mut hex = [0u8; HASH_DIGITS];
+    for (i, byte) in digest.iter().enumerate() {
+        hex[2 * i] = DIGITS[(byte >> 4) as usize];
+        hex[2 * i + 1] = DIGITS[(byte & 0x0f) as usize];
+    }
+    hex
+}
+
+// `<digits hex digits>:<count>`, the hash upper case
+fn parse_line(line: &[u8], digits: usize) -> Option<([u8; HASH_DIGITS], u64)> {
+    let line = line.trim_ascii();
+    if line.len() < digits + 2 || line[digits] != b':' {
+        return None;
+    }
+    let mut hash = [0u8; HASH_DIGITS];
+    for (i, digit) in line[..digits].iter().enumerate() {
+        if !digit.is_ascii_hexdigit() {
+            return None;
+        }
+        hash[i] = digit.to_ascii_uppercase();
+    }
+    let count = std::str::from_utf8(&line[digits + 1..])
+        .ok()?
+        .parse()
+        .ok()?;
+    Some((hash, count))
+}
+
+// Binary search over byte offsets, a probe reads the first complete line after the offset.
+// The searched line always starts within lo..hi.
+fn search_sorted_file(path: &Path, hash: &HexHash) -> Result<u64, VaultError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut lo = 0;
+    let mut hi = reader.get_ref().metadata()?.len();
+    let mut line = Vec::new();
+
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let start = if mid == lo {
+            lo
+        } else {
+            reader.seek(SeekFrom::Start(mid - 1))?;
+            line.clear();
+            mid - 1 + reader.read_until(b'\n', &mut line)? as u64
+        };
+        if start >= hi {
+            hi = mid;
+            continue;
+        }
+
+        reader.seek(SeekFrom::Start(start))?;
+        line.clear();
+        let length = reader.read_until(b'\n', &mut line)? as u64;
+        let Some((line_hash, count)) = parse_line(&line, HASH_DIGITS) else {
+            return Err(VaultError::AnyhowError(anyhow::anyhow!(
+                "{:?} contains an invalid line at byte {}",
+                path,
+                start
+            )));
+        };
+
+        match line_hash.cmp(hash) {
+            std::cmp::Ordering::Equal => return Ok(count),
+            std::cmp::Ordering::Less => lo = start + length,
+            std::cmp::Ordering::Greater => hi = mid,
+        }
+    }
+    Ok(0)
+}
+
+fn search_range_directory(dir: &Path, hash: &HexHash) -> Result<Option<u64>, VaultError> {
+    // the prefix is what the range API itself sends over the network, it is not secret
+    let prefix = std::str::from_utf8(&hash[..PREFIX_DIGITS]).unwrap_or_default();
+    let candidates = [
+        prefix.to_string(),
+        format!("{}.txt", prefix),
+        prefix.to_ascii_lowercase(),
+        format!("{}.txt", prefix.to_ascii_lowercase()),
+    ];
+    let Some(path) = candidates
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+    else {
+        return Ok(None);
+    };
+
+    let contents = fs::read(&path)?;
+    let lines: Vec<([u8; HASH_DIGITS], u64)> = contents
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(|line| parse_line(line, HASH_DIGITS - PREFIX_DIGITS))
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            VaultError::AnyhowError(anyhow::anyhow!("{:?} is not a range file", path))
+        })?;
+
+    let suffix = &hash[PREFIX_DIGITS..];
+    let count = lines
+        .binary_search_by(|(line_suffix, _)| line_suffix[..suffix.len()].cmp(suffix))
+        .map(|i| lines[i].1)
+        .unwrap_or(0);
+    Ok(Some(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // SHA-1 of "password1"
+    const PASSWORD1: &str = "E38AD214943DAAD1D64C102FAEC29DE4AFE9DA3D";
+
+    fn hashes() -> Vec<String> {
+        let mut hashes: Vec<String> = (0..2000)
+            .map(|i| String::from_utf8(hex_hash(&format!("other{}", i)).to_vec()).unwrap())
+            .collect();
+        hashes.push(PASSWORD1.to_string());
+        hashes.sort();
+        hashes
+    }
+
+    fn entries() -> Vec<Entry> {
+        ["password1", "password1", "not-in-the-list", "other1999"]
+            .iter()
+            .enumerate()
+            .map(|(i, password)| {
+                Entry::new(
+                    format!("entry{}", i),
+                    None,
+                    Some(password.to_string()),
+                    None,
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    fn names(report: &BreachReport) -> Vec<(&str, u64)> {
+        let mut names: Vec<(&str, u64)> = report
+            .breached
+            .iter()
+            .map(|b| (b.entry.as_str(), b.count))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_sorted_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for (i, hash) in hashes().iter().enumerate() {
+            write!(file, "{}:{}\r\n", hash, i + 1).unwrap();
+        }
+        file.flush().unwrap();
+
+        let db = BreachDb::open(file.path()).unwrap();
+        assert!(matches!(db, BreachDb::SortedFile(_)));
+        let report = check_entries(&entries(), &db).unwrap();
+
+        let position = |hash: &str| hashes().iter().position(|h| h == hash).unwrap() as u64 + 1;
+        let other = String::from_utf8(hex_hash("other1999").to_vec()).unwrap();
+        let mut expected = vec![
+            ("entry0", position(PASSWORD1)),
+            ("entry1", position(PASSWORD1)),
+            ("entry3", position(&other)),
+        ];
+        expected.sort();
+        assert_eq!(names(&report), expected);
+        assert!(report.unchecked.is_empty());
+
+        // the first and last line are found as well
+        for hash in [hashes().first().unwrap(), hashes().last().unwrap()] {
+            let mut hex = [0u8; HASH_DIGITS];
+            hex.copy_from_slice(hash.as_bytes());
+            assert!(search_sorted_file(file.path(), &hex).unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn test_range_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ranges: HashMap<String, Vec<String>> = HashMap::new();
+        for hash in hashes() {
+            ranges
+                .entry(hash[..PREFIX_DIGITS].to_string())
+                .or_default()
+                .push(format!("{}:3", &hash[PREFIX_DIGITS..]));
+        }
+        // the range of "not-in-the-list" stays missing
+        let missing = String::from_utf8(hex_hash("not-in-the-list")[..5].to_vec()).unwrap();
+        ranges.remove(&missing);
+        for (prefix, lines) in ranges {
+            fs::write(
+                dir.path().join(format!("{}.txt", prefix)),
+                lines.join("\r\n"),
+            )
+            .unwrap();
+        }
+
+        let db = BreachDb::open(dir.path()).unwrap();
+        let report = check_entries(&entries(), &db).unwrap();
+        assert_eq!(
+            names(&report),
+            vec![("entry0", 3), ("entry1", 3), ("entry3", 3)]
+        );
+        assert_eq!(report.unchecked, vec!["entry2"]);
+    }
+
+    #[test]
+    fn test_invalid_database() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "password1").unwrap();
+        assert!(BreachDb::open(file.path()).is_err());
+        assert!(BreachDb::open(Path::new("/does/not/exist")).is_err());
+    }
+}
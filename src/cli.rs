@@ -1,19 +1,44 @@
+use crate::agent::AgentAction;
+use crate::audit::{AuditOptions, AuditReport, DEFAULT_MAX_AGE_DAYS, DEFAULT_MIN_SCORE, audit};
+use crate::bitwarden;
+use crate::config;
 use crate::errors::*;
+use crate::export::{ExportFormat, ExportSelection, exported_vault, to_json, write_csv};
+use crate::generator::{GeneratorArgs, GeneratorMode, PasswordPolicy, generate, policy_for_url};
+use crate::import::{
+    ConflictPolicy, ImportAction, ImportFormat, Imported, PlannedEntry, apply_import, is_json_file,
+    plan_import, read_bitwarden_json_file, read_csv_file,
+};
+use crate::keyfile::KeyFile;
+use crate::merge::{MergeConflict, MergeItem, MergePlan, Side, apply_merge, plan_merge};
+use crate::search::{SearchQuery, search};
 use crate::session::*;
+use crate::totp::{Totp, TotpAlgorithm};
 use crate::vault_entry_manager::*;
-use crate::vault_file_manager::{list_vaults, vault_exists};
+use crate::vault_file_manager::{
+    check_vault_name, forget_vault_file, is_encrypted_export, is_kdbx_file, list_backups,
+    list_vaults_by_dir, read_encrypted_export, read_kdbx_file, read_vault_file,
+    register_vault_file, restore_backup, vault_exists, write_encrypted_export, write_export_file,
+    write_kdbx_file,
+};
+use crate::vault_format::KdfSettings;
 
 use anyhow::anyhow;
 use arboard::Clipboard;
-use clap::{Parser, Subcommand, command};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+use clap::{Args, Parser, Subcommand};
 use indicatif::{self, ProgressBar, ProgressStyle};
-use passgenr::charsets;
-use passgenr::random_password;
 use rpassword;
 use secrecy::ExposeSecret;
 use secrecy::SecretString;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
 use std::io::stdout;
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use zxcvbn::zxcvbn;
 
@@ -24,6 +49,30 @@ pub struct CLI {
     pub command: CommandCLI,
 }
 
+/// Arguments accepted when `pw` is called directly from a shell, e.g. `pw get github --copy`.
+#[derive(Parser)]
+#[command(name = "pw")]
+pub struct OneShotCLI {
+    /// Vault to run the command on (default: $PW_VAULT or the only existing vault).
+    #[arg(short = 'V', long = "vault", global = true)]
+    pub vault: Option<String>,
+
+    /// Read the master password from this file descriptor instead of prompting.
+    #[arg(long = "password-fd", global = true)]
+    pub password_fd: Option<i32>,
+
+    /// Config file to use instead of $PW_CONFIG or the one in the config dir.
+    #[arg(long = "config", value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory of the vaults, overrides vault_dir of the config.
+    #[arg(long = "vault-dir", value_name = "DIR", global = true)]
+    pub vault_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: CommandCLI,
+}
+
 pub enum LoopCommand {
     Continue,
     Cancel,
@@ -34,6 +83,16 @@ pub enum CommandCLI {
     /// Initializes a new PasswordManager.
     Init {
         name: Option<String>,
+
+        // Directory to create the vault in instead of the vault directory, e.g. on a USB stick.
+        #[arg(long = "dir", value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        kdf: KdfArgs,
+
+        #[command(flatten)]
+        key_file: KeyFileArgs,
     },
 
     /// Adds a new password to database.
@@ -52,6 +111,9 @@ pub enum CommandCLI {
 
         #[arg(short, long)]
         notes: Option<String>,
+
+        #[command(flatten)]
+        extra: EntryExtraArgs,
     },
 
     /// Get an Entry of the Database.
@@ -65,8 +127,27 @@ pub enum CommandCLI {
         show: bool,
 
         // Copy credentials to clipboard instead of displaying
-        #[arg(short = 'c', long)]
-        copy: bool,
+        #[command(flatten)]
+        clipboard: ClipboardArgs,
+
+        // Only look for the entry in this folder and its subfolders
+        #[arg(short = 'F', long = "folder")]
+        folder: Option<String>,
+    },
+
+    /// Searches entries by name, username, URL, notes, tags and folder.
+    Search {
+        // e.g. 'github' or 'user:alice url:github'
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+
+        // Show the password of the picked entry
+        #[arg(short = 's', long)]
+        show: bool,
+
+        // Copy the credentials of the picked entry instead of displaying them
+        #[command(flatten)]
+        clipboard: ClipboardArgs,
     },
 
     /// Gets all Entries from the current vault.
@@ -75,20 +156,56 @@ pub enum CommandCLI {
         // Show passwords or not.
         #[arg(short = 's', long)]
         show: bool,
+
+        #[command(flatten)]
+        list: ListArgs,
+    },
+
+    /// Shows the current one-time code (TOTP) of an entry.
+    Totp {
+        name: String,
+
+        // Copy the code to the clipboard instead of displaying it
+        #[command(flatten)]
+        clipboard: ClipboardArgs,
+
+        #[command(flatten)]
+        setup: TotpSetupArgs,
     },
 
-    /// Generate a password.
-    // maybe implement interaction (abfrage) if with special cases, numbers etc.
+    /// Generate a password, a passphrase or a pronounceable password.
     Generate {
-        length: u32,
+        #[command(flatten)]
+        options: GeneratorArgs,
 
-        #[arg(short = 'f', long = "no_symbols")]
-        no_symbols: bool,
+        // Start from this password policy of the current vault
+        #[arg(short = 'p', long = "policy")]
+        policy: Option<String>,
+
+        // Seconds until the password is cleared from the clipboard
+        #[arg(long = "clear-after", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        clear_after: Option<u64>,
     },
 
     /// Remove an entry from Database.
     Delete {
         name: String,
+
+        // Only delete the entry if it is in this folder or one of its subfolders
+        #[arg(short = 'F', long = "folder")]
+        folder: Option<String>,
+    },
+
+    /// Moves an entry, or a whole folder with --folder, to another folder.
+    Move {
+        // Entry name, or a folder path with --folder
+        source: String,
+
+        // Target folder, '/' is the top level of the vault
+        destination: String,
+
+        #[arg(short = 'F', long = "folder")]
+        folder: bool,
     },
 
     /// Delete a vault completely.
@@ -97,23 +214,172 @@ pub enum CommandCLI {
 
     /// Change the Masterpassword.
     // implement not visible, old password required. Verschlüsselt Vault sofort
-    ChangeMaster {},
+    ChangeMaster {
+        #[command(flatten)]
+        key_file: KeyFileArgs,
+
+        // The vault no longer needs a key file afterwards.
+        #[arg(long = "remove-key-file", conflicts_with_all = ["key_file", "new_key_file"])]
+        remove_key_file: bool,
+    },
 
     Vaults {},
 
+    /// Changes the key derivation (Argon2id) parameters of the current vault.
+    Rekdf {
+        #[command(flatten)]
+        kdf: KdfArgs,
+    },
+
+    /// Restores a vault from one of its automatic backups.
+    RestoreBackup {
+        // Defaults to the currently opened vault.
+        name: Option<String>,
+    },
+
+    /// Imports entries from another password manager (CSV, Bitwarden JSON, KeePass) or an export.
+    Import {
+        file: PathBuf,
+
+        // Password manager that wrote the file, detected from the file by default
+        #[arg(short = 'f', long, value_enum, default_value_t = ImportFormat::Auto)]
+        format: ImportFormat,
+
+        // KeePass key file of the database
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+
+        // What happens to entries whose name already exists
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Rename)]
+        on_conflict: ConflictPolicy,
+
+        // Only print the summary, nothing is imported
+        #[arg(long)]
+        dry_run: bool,
+
+        // Import without asking for confirmation after the summary
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+
+    /// Exports the current vault, encrypted with a separate passphrase or as plaintext.
+    Export {
+        file: PathBuf,
+
+        #[arg(short = 'f', long, value_enum, default_value_t = ExportFormat::Encrypted)]
+        format: ExportFormat,
+
+        // KeePass key file the database will require in addition to its password
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        selection: ExportSelection,
+    },
+
+    /// Merges another copy of the current vault, e.g. from another laptop, into it.
+    Merge {
+        file: PathBuf,
+
+        // Key file of the other copy, if it differs from the one of the current vault
+        #[arg(short = 'k', long = "key-file")]
+        key_file: Option<PathBuf>,
+
+        // Show passwords and hidden fields of conflicting entries
+        #[arg(short = 's', long)]
+        show: bool,
+
+        // Only print what would change, nothing is merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Checks every entry for weak, reused and old passwords, missing values and http:// URLs.
+    Audit {
+        // Passwords not changed for more days are reported as old
+        #[arg(long, value_name = "DAYS", default_value_t = DEFAULT_MAX_AGE_DAYS)]
+        max_age: i64,
+
+        // Passwords with a lower zxcvbn score (0-4) are reported as weak
+        #[arg(long, default_value_t = DEFAULT_MIN_SCORE, value_parser = clap::value_parser!(u8).range(0..=4))]
+        min_score: u8,
+
+        // Also look up every password in a local copy of the Pwned Passwords list
+        // (a file ordered by SHA-1 hash or a directory of range files)
+        #[arg(long, value_name = "PATH")]
+        breach_db: Option<PathBuf>,
+
+        // Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Shows or changes the settings of the current vault.
+    Settings {
+        // Number of backups to keep, 0 disables backups.
+        #[arg(short = 'b', long = "backups")]
+        backups: Option<usize>,
+
+        // Number of earlier versions kept per entry, 0 disables the history.
+        #[arg(long = "history")]
+        history: Option<usize>,
+
+        // Seconds until a copied password is cleared from the clipboard.
+        #[arg(long = "clipboard-timeout", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        clipboard_timeout: Option<u64>,
+    },
+
+    /// Manages the password policies of the current vault, used by generate, add and edit.
+    Policy {
+        #[command(subcommand)]
+        action: PolicyCommand,
+    },
+
+    /// Shows or changes the defaults in the config file, e.g. the session timeout.
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+
     /// Modify a given password
     //
     Edit {
         name: String,
     },
 
+    /// Lists the earlier versions of an entry.
+    History {
+        name: String,
+
+        // Show passwords and hidden fields of the versions
+        #[arg(short = 's', long)]
+        show: bool,
+    },
+
+    /// Rolls an entry back to an earlier version.
+    Restore {
+        name: String,
+
+        // Number of the version as listed by 'history', asked for if missing
+        version: Option<usize>,
+    },
+
     /// Opens given vault.
     Open {
-        name: String,
+        #[arg(required_unless_present = "file")]
+        name: Option<String>,
+
+        // Vault file to open from anywhere, e.g. `open --file /mnt/usb/work.psdb`.
+        #[arg(long = "file", value_name = "PATH", conflicts_with = "name")]
+        file: Option<PathBuf>,
 
         #[arg(short = 't', long = "timeout")]
         timeout: Option<u64>,
         // check timeout von dem Mutex in main erwartet u64
+
+        // Key file, if the vault requires one.
+        #[arg(short = 'k', long = "key-file")]
+        key_file: Option<PathBuf>,
     },
 
     /// Closes the current vault and ends the session.
@@ -122,6 +388,46 @@ pub enum CommandCLI {
         force: bool,
     },
 
+    /// Switches the current vault to another unlocked one.
+    Use {
+        name: String,
+    },
+
+    /// Copies entries of the current vault to another unlocked vault.
+    CopyEntry {
+        #[arg(required = true, num_args = 1..)]
+        names: Vec<String>,
+
+        // Unlocked vault the entries are copied to
+        #[arg(short = 't', long = "to", value_name = "VAULT")]
+        to: String,
+
+        // What happens to entries whose name already exists in the target vault
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Rename)]
+        on_conflict: ConflictPolicy,
+    },
+
+    /// Moves entries of the current vault to another unlocked vault.
+    MoveEntry {
+        #[arg(required = true, num_args = 1..)]
+        names: Vec<String>,
+
+        // Unlocked vault the entries are moved to
+        #[arg(short = 't', long = "to", value_name = "VAULT")]
+        to: String,
+
+        // What happens to entries whose name already exists in the target vault,
+        // skipped entries stay in the current vault
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Rename)]
+        on_conflict: ConflictPolicy,
+    },
+
+    /// Controls the background agent that keeps a vault unlocked.
+    Agent {
+        #[command(subcommand)]
+        action: AgentCommand,
+    },
+
     /// Clears terminal window.
     Clear {},
 
@@ -133,7 +439,269 @@ pub enum CommandCLI {
     },
 }
 
+#[derive(Subcommand)]
+pub enum AgentCommand {
+    /// Starts the agent in the background.
+    Start {
+        // Also serve the browser extension from the agent.
+        #[arg(short = 'e', long = "extension")]
+        extension: bool,
+    },
+
+    /// Runs the agent in the foreground (used by start).
+    #[command(hide = true)]
+    Serve {},
+
+    /// Stops the agent and locks its vault.
+    Stop {},
+
+    /// Shows whether the agent runs and which vault it holds.
+    Status {},
+
+    /// Unlocks a vault inside the agent.
+    Unlock {
+        name: String,
+
+        #[arg(short = 't', long = "timeout")]
+        timeout: Option<u64>,
+
+        #[arg(short = 'k', long = "key-file")]
+        key_file: Option<PathBuf>,
+    },
+
+    /// Locks the vault held by the agent.
+    Lock {},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PolicyCommand {
+    /// Lists the password policies of the vault.
+    List {},
+
+    /// Creates or replaces a policy, a policy named after a domain applies to entries with a URL in it.
+    Set {
+        name: String,
+
+        #[command(flatten)]
+        options: GeneratorArgs,
+    },
+
+    /// Removes a policy.
+    Remove { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Lists every key with its value and where the value comes from.
+    List {},
+
+    /// Shows the value of a key, e.g. `config get generator.mode`.
+    Get { key: String },
+
+    /// Writes a key to the config file, e.g. `config set session_timeout 600`.
+    Set { key: String, value: String },
+}
+
+/// `--copy` of the commands that can copy a secret to the clipboard.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct ClipboardArgs {
+    #[arg(short = 'c', long = "copy")]
+    pub copy: bool,
+
+    // Seconds until the clipboard is cleared (default: the clipboard timeout of the vault)
+    #[arg(long = "clear-after", value_name = "SECONDS", requires = "copy", value_parser = clap::value_parser!(u64).range(1..))]
+    pub clear_after: Option<u64>,
+}
+
+/// Argon2id parameters for `init` and `rekdf`, anything not given keeps its current value.
+#[derive(Args, Debug, Clone, Default)]
+pub struct KdfArgs {
+    // Memory used by Argon2id in MiB.
+    #[arg(long = "kdf-memory")]
+    pub memory_mib: Option<u32>,
+
+    #[arg(long = "kdf-iterations", conflicts_with = "benchmark")]
+    pub iterations: Option<u32>,
+
+    #[arg(long = "kdf-parallelism")]
+    pub parallelism: Option<u32>,
+
+    // Picks the iterations so that unlocking takes about this many milliseconds (default 1000).
+    #[arg(long = "benchmark", num_args = 0..=1, default_missing_value = "1000")]
+    pub benchmark: Option<u64>,
+}
+
+impl KdfArgs {
+    pub fn is_empty(&self) -> bool {
+        self.memory_mib.is_none()
+            && self.iterations.is_none()
+            && self.parallelism.is_none()
+            && self.benchmark.is_none()
+    }
+}
+
+/// Optional parts of an entry for `add`, besides name, username, URL, password and notes.
+#[derive(Args, Debug, Clone, Default)]
+pub struct EntryExtraArgs {
+    // TOTP secret or otpauth:// URI
+    #[arg(short = 'o', long = "totp")]
+    pub totp: Option<String>,
+
+    // Custom field as [TYPE:]NAME=VALUE, e.g. hidden:PIN=1234, can be given multiple times
+    #[arg(short = 'f', long = "field", value_parser = parse_field_arg)]
+    pub fields: Vec<CustomField>,
+
+    // Day after which the credentials count as expired, YYYY-MM-DD
+    #[arg(short = 'e', long = "expires", value_parser = parse_date_arg)]
+    pub expires: Option<NaiveDate>,
+
+    // Folder path like work/aws/prod
+    #[arg(short = 'F', long = "folder")]
+    pub folder: Option<String>,
+
+    // Can be given multiple times
+    #[arg(short = 't', long = "tag")]
+    pub tags: Vec<String>,
+}
+
+/// Sorting and filtering for `getall`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ListArgs {
+    #[arg(long = "sort", value_enum, default_value_t)]
+    pub sort: EntrySort,
+
+    #[arg(short = 'r', long = "reverse")]
+    pub reverse: bool,
+
+    // Only entries whose password was not changed for this many days
+    #[arg(long = "password-older-than", value_name = "DAYS")]
+    pub password_older_than: Option<i64>,
+
+    // Only entries that were not used for this many days, including never used ones
+    #[arg(long = "unused-for", value_name = "DAYS")]
+    pub unused_for: Option<i64>,
+
+    // Only entries changed within the last DAYS days
+    #[arg(long = "modified-within", value_name = "DAYS")]
+    pub modified_within: Option<i64>,
+
+    // Only expired entries
+    #[arg(long = "expired")]
+    pub expired: bool,
+
+    // Only entries in this folder and its subfolders
+    #[arg(short = 'F', long = "folder")]
+    pub folder: Option<String>,
+
+    // Only entries with this tag, can be given multiple times (all have to match)
+    #[arg(short = 't', long = "tag")]
+    pub tags: Vec<String>,
+
+    // Shows the entry names as a tree of folders
+    #[arg(long = "tree")]
+    pub tree: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum EntrySort {
+    #[default]
+    Name,
+    Created,
+    Modified,
+    PasswordChanged,
+    LastUsed,
+    Expires,
+}
+
+/// Stores or removes the TOTP secret of an entry instead of showing a code.
+#[derive(Args, Debug, Clone, Default)]
+pub struct TotpSetupArgs {
+    // TOTP secret (base32) or otpauth:// URI
+    #[arg(long = "set", conflicts_with_all = ["copy", "remove"])]
+    pub set: Option<String>,
+
+    #[arg(long = "digits", requires = "set")]
+    pub digits: Option<u32>,
+
+    // Seconds a code stays valid
+    #[arg(long = "period", requires = "set")]
+    pub period: Option<u64>,
+
+    #[arg(long = "algorithm", value_enum, ignore_case = true, requires = "set")]
+    pub algorithm: Option<TotpAlgorithm>,
+
+    #[arg(long = "remove", conflicts_with = "copy")]
+    pub remove: bool,
+}
+
+impl TotpSetupArgs {
+    pub fn modifies_entry(&self) -> bool {
+        self.set.is_some() || self.remove
+    }
+}
+
+/// Key file for `init` and `change-master`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct KeyFileArgs {
+    // Existing file to use as key file.
+    #[arg(short = 'k', long = "key-file", conflicts_with = "new_key_file")]
+    pub key_file: Option<PathBuf>,
+
+    // Creates a new random key file at this path.
+    #[arg(long = "new-key-file")]
+    pub new_key_file: Option<PathBuf>,
+}
+
+impl KeyFileArgs {
+    pub fn is_empty(&self) -> bool {
+        self.key_file.is_none() && self.new_key_file.is_none()
+    }
+
+    /// Loads the given key file or creates the new one.
+    pub fn resolve(&self) -> Result<Option<KeyFile>, VaultError> {
+        if let Some(path) = &self.key_file {
+            return KeyFile::load(path).map(Some);
+        }
+        if let Some(path) = &self.new_key_file {
+            let key_file = KeyFile::generate(path)?;
+            println!("New key file created at {:?}.", path);
+            println!("Keep a copy in a safe place, the vault cannot be opened without it!");
+            return Ok(Some(key_file));
+        }
+        Ok(None)
+    }
+}
+
+impl CommandCLI {
+    /// Names of the entries the command works on, they may be given as `vault:entry`.
+    pub fn entry_names_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            CommandCLI::Add { name, .. } => name.iter_mut().collect(),
+            CommandCLI::Get { name, .. }
+            | CommandCLI::Totp { name, .. }
+            | CommandCLI::Delete { name, .. }
+            | CommandCLI::Edit { name }
+            | CommandCLI::History { name, .. }
+            | CommandCLI::Restore { name, .. } => vec![name],
+            CommandCLI::Move {
+                source,
+                folder: false,
+                ..
+            } => vec![source],
+            CommandCLI::CopyEntry { names, .. } | CommandCLI::MoveEntry { names, .. } => {
+                names.iter_mut().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
 static CANCEL_ARG: &str = "--CANCEL";
+// Results beyond this are only counted, the query should be refined instead
+const MAX_SEARCH_RESULTS: usize = 20;
+
+// Clipboard clears that are still pending, so a one-shot call can wait for them before exiting.
+static PENDING_CLIPBOARD_CLEARS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
 
 pub fn clear_terminal() {
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
@@ -160,9 +728,25 @@ pub fn spinner() -> ProgressBar {
     spinner
 }
 
-pub fn handle_command_init(option_name: Option<String>) -> Result<(), VaultError> {
+pub fn handle_command_init(
+    option_name: Option<String>,
+    dir: Option<PathBuf>,
+    kdf: KdfArgs,
+    key_file: KeyFileArgs,
+) -> Result<(), VaultError> {
     println!("\nInitializing new vault: ");
 
+    // checked first, so a typo does not cost the user the whole dialogue
+    let kdf_settings = resolve_kdf_settings(KdfSettings::default(), &kdf)?;
+    if let Some(path) = &key_file.key_file {
+        KeyFile::load(path)?;
+    }
+    if let Some(path) = &key_file.new_key_file
+        && path.exists()
+    {
+        return Err(VaultError::FileExists);
+    }
+
     let vault_name: String = 'define_vault_name: loop {
         if let Some(option_vault_name) = option_name.clone() {
             match check_vault_name(&option_vault_name) {
@@ -203,9 +787,23 @@ pub fn handle_command_init(option_name: Option<String>) -> Result<(), VaultError
         };
     };
 
-    if crate::vault_file_manager::vault_exists(&vault_name)? {
-        return Err(VaultError::NameExists);
-    }
+    // the vault directories are not searched for a vault created in a directory of its own
+    let file = match &dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            let file = std::path::absolute(dir.join(format!("{}.psdb", vault_name)))?;
+            if file.exists() {
+                return Err(VaultError::NameExists);
+            }
+            Some(file)
+        }
+        None => {
+            if crate::vault_file_manager::vault_exists(&vault_name)? {
+                return Err(VaultError::NameExists);
+            }
+            None
+        }
+    };
 
     println!("\nDefine the Master-Password for {}:", vault_name);
 
@@ -235,23 +833,37 @@ pub fn handle_command_init(option_name: Option<String>) -> Result<(), VaultError
         }
     };
 
+    let key_file = key_file.resolve()?;
+
     let spinner = spinner();
     spinner.enable_steady_tick(Duration::from_millis(80));
     println!();
     spinner.set_message(" Creating vault...");
-    match create_new_vault(vault_name.clone(), key) {
+    if let Some(file) = &file {
+        register_vault_file(file)?;
+    }
+    match create_new_vault_with_options(vault_name.clone(), key, kdf_settings, key_file.as_ref()) {
         Ok(()) => {
             spinner.finish_and_clear();
             println!("Vault '{}' created successfully! \n", vault_name);
-            println!(
-                "Hint: Use 'open {}' to open it for the first time!",
-                vault_name
-            );
+            match &file {
+                Some(file) => println!(
+                    "Hint: Use 'open --file {}' to open it for the first time!",
+                    file.display()
+                ),
+                None => println!(
+                    "Hint: Use 'open {}' to open it for the first time!",
+                    vault_name
+                ),
+            }
 
             Ok(())
         }
         Err(e) => {
             spinner.finish_and_clear();
+            if file.is_some() {
+                forget_vault_file(&vault_name);
+            }
             Err(e)
         }
     }
@@ -264,6 +876,7 @@ pub fn handle_command_add(
     url: Option<String>,
     notes: Option<String>,
     password: Option<String>,
+    extra: EntryExtraArgs,
 ) -> Result<(), SessionError> {
     let session = option_session
         .as_mut()
@@ -380,19 +993,55 @@ pub fn handle_command_add(
     };
 
     // Password
+    let password_prompted = password.is_none();
     let final_pw = if let Some(p) = password {
         Some(p)
     } else {
-        add_password_to_entry()?
+        let site_policy = final_url
+            .as_deref()
+            .and_then(|url| policy_for_url(&vault.settings.policies, url))
+            .map(|(name, policy)| (name.clone(), policy.clone()));
+        add_password_to_entry(site_policy, vault.settings.effective_clipboard_timeout())?
+    };
+
+    // TOTP, only asked for if the password was asked for as well, so scripted calls do not block
+    let final_totp = if let Some(t) = extra.totp {
+        Some(Totp::parse(&t).map_err(SessionError::VaultError)?)
+    } else if password_prompted {
+        read_totp("TOTP secret or otpauth:// URI (enter skips it): ", false)?.flatten()
+    } else {
+        None
     };
 
-    let entry = Entry::new(
+    let mut entry = Entry::new(
         final_name.clone(),
         final_username,
         final_pw,
         final_url,
         final_notes,
     );
+    if let Some(totp) = final_totp {
+        entry.set_totp(totp);
+    }
+    if let Some(day) = extra.expires {
+        entry.set_expiry(day);
+    }
+    if let Some(folder) = extra.folder {
+        entry.set_folder(&folder);
+    }
+    for tag in extra.tags {
+        entry.add_tag(&tag);
+    }
+
+    // Custom fields, prompted for under the same condition as the TOTP secret
+    for field in extra.fields {
+        entry.set_field(field);
+    }
+    if password_prompted {
+        while let Some(field) = read_new_field(&entry)? {
+            entry.set_field(field);
+        }
+    }
 
     let spinner = spinner();
     spinner.enable_steady_tick(Duration::from_millis(80));
@@ -419,7 +1068,8 @@ pub fn handle_command_get(
     option_session: &mut Option<Session>,
     entry_name_or_url: String,
     show: bool,
-    copy: bool,
+    clipboard: ClipboardArgs,
+    folder: Option<String>,
 ) -> Result<(), SessionError> {
     let session = option_session
         .as_mut()
@@ -443,24 +1093,44 @@ pub fn handle_command_get(
         .as_mut()
         .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
 
+    display_entry(vault, entry_name_or_url, show, clipboard, folder.as_deref())
+}
+
+/// Looks up an entry by name or URL and prints it (or copies its credentials).
+// Split from handle_command_get, so callers that already re-authenticated (e.g. via the agent) can use it.
+pub fn display_entry(
+    vault: &mut Vault,
+    entry_name_or_url: String,
+    show: bool,
+    clipboard: ClipboardArgs,
+    folder: Option<&str>,
+) -> Result<(), SessionError> {
+    let in_scope = |entry: &Entry| folder.is_none_or(|folder| entry.in_folder(folder));
+
     // First, try to find by exact entry name
-    let entry_opt = vault.get_entry_by_name(&entry_name_or_url);
+    let position = vault
+        .entries
+        .iter()
+        .position(|entry| *entry.get_entry_name() == entry_name_or_url && in_scope(entry));
 
-    let entry = if let Some(e) = entry_opt {
+    let index = if let Some(i) = position {
         // Found by name
-        e
+        i
     } else {
         // Not found by name, try URL-based lookup
-        let matches: Vec<&crate::vault_entry_manager::Entry> = vault
+        let matches: Vec<usize> = vault
             .entries
             .iter()
-            .filter(|entry| {
+            .enumerate()
+            .filter(|(_, entry)| in_scope(entry))
+            .filter(|(_, entry)| {
                 if let Some(entry_url) = entry.get_url() {
                     url_matches(entry_url, &entry_name_or_url)
                 } else {
                     false
                 }
             })
+            .map(|(i, _)| i)
             .collect();
 
         match matches.len() {
@@ -475,7 +1145,8 @@ pub fn handle_command_get(
             _ => {
                 // Multiple matches found
                 println!("\nMultiple entries found for '{}':", entry_name_or_url);
-                for (i, e) in matches.iter().enumerate() {
+                for (i, index) in matches.iter().enumerate() {
+                    let e = &vault.entries[*index];
                     println!(
                         "  {}. {} ({})",
                         i + 1,
@@ -488,10 +1159,13 @@ pub fn handle_command_get(
             }
         }
     };
+    let clipboard_timeout = clipboard
+        .clear_after
+        .unwrap_or_else(|| vault.settings.effective_clipboard_timeout());
+    let entry = &mut vault.entries[index];
 
     // Handle --copy flag
-    if copy {
-        use arboard::Clipboard;
+    if clipboard.copy {
         let username = entry.get_user_name().as_deref().unwrap_or("");
         let password = entry.get_password().as_deref().unwrap_or("");
 
@@ -503,60 +1177,36 @@ pub fn handle_command_get(
         // Format: username\npassword
         let clipboard_content = format!("{}\n{}", username, password);
 
-        match Clipboard::new() {
-            Ok(mut clipboard) => match clipboard.set_text(clipboard_content) {
-                Ok(_) => {
-                    let duration = 30;
-                    println!(
-                        "✓ Credentials copied to clipboard for '{}'",
-                        entry.get_entry_name()
-                    );
-                    println!("  (Clipboard will be cleared in {} seconds)", &duration);
-
-                    clear_clipboard_after(duration);
-                }
-                Err(e) => {
-                    println!("Failed to copy to clipboard: {}", e);
-                }
-            },
+        match copy_secret(&clipboard_content, clipboard_timeout) {
+            Ok(()) => {
+                entry.mark_used();
+                println!(
+                    "✓ Credentials copied to clipboard for '{}'",
+                    entry.get_entry_name()
+                );
+                println!(
+                    "  (Clipboard will be cleared in {} seconds)",
+                    &clipboard_timeout
+                );
+            }
             Err(e) => {
-                println!("Failed to access clipboard: {}", e);
+                println!("Failed to copy to clipboard: {}", e);
             }
         }
 
         return Ok(());
     }
 
-    println!("\n==== Entry: {} ====", entry.get_entry_name());
-    println!(
-        "Username: {}",
-        entry.get_user_name().as_deref().unwrap_or("--EMPTY--")
-    );
-    println!(
-        "URL:      {}",
-        entry.get_url().as_deref().unwrap_or("--EMPTY--")
-    );
-    println!(
-        "Notes:    {}",
-        entry.get_notes().as_deref().unwrap_or("--EMPTY--")
-    );
-
-    if show {
-        println!(
-            "Password: {}",
-            entry.get_password().as_deref().unwrap_or("--EMPTY--")
-        );
-    } else {
-        println!("Password: *****");
-    }
-    println!();
+    print_entry(entry, show);
 
     Ok(())
 }
 
-pub fn handle_command_getall(
+pub fn handle_command_search(
     option_session: &mut Option<Session>,
+    query: String,
     show: bool,
+    clipboard: ClipboardArgs,
 ) -> Result<(), SessionError> {
     let session = option_session
         .as_mut()
@@ -580,44 +1230,358 @@ pub fn handle_command_getall(
         .as_mut()
         .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
 
-    let entries = vault.get_entries();
+    search_entries(vault, &query, show, clipboard)
+}
 
-    if entries.is_empty() {
+/// Lists the ranked search results and shows (or copies) the one the user picks.
+// Split from handle_command_search like display_entry, callers re-authenticate for `show` themselves.
+pub fn search_entries(
+    vault: &mut Vault,
+    query: &str,
+    show: bool,
+    clipboard: ClipboardArgs,
+) -> Result<(), SessionError> {
+    let parsed = SearchQuery::parse(query).map_err(SessionError::VaultError)?;
+    let results: Vec<String> = search(vault.get_entries(), &parsed)
+        .into_iter()
+        .map(|(entry, _)| entry.get_entry_name().clone())
+        .collect();
+
+    if results.is_empty() {
+        println!("No entries match '{}'.", query);
+        return Ok(());
+    }
+
+    println!("\n=== Results for '{}' ===", query);
+    for (i, name) in results.iter().take(MAX_SEARCH_RESULTS).enumerate() {
+        if let Some(entry) = vault.get_entry_by_name(name) {
+            let mut details: Vec<&str> = Vec::new();
+            details.extend(entry.username());
+            details.extend(entry.url());
+            details.extend(entry.get_folder());
+            println!("  [{}] {} ({})", i + 1, name, details.join(", "));
+        }
+    }
+    if results.len() > MAX_SEARCH_RESULTS {
+        println!(
+            "  ... and {} more, refine the query to see them",
+            results.len() - MAX_SEARCH_RESULTS
+        );
+    }
+    println!();
+
+    let shown = results.len().min(MAX_SEARCH_RESULTS);
+    let picked = 'input: loop {
+        print!(
+            "Pick an entry to {} (1-{}, press Enter to finish): ",
+            if clipboard.copy { "copy" } else { "show" },
+            shown
+        );
+        stdout().flush().unwrap();
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let trimmed = input.trim();
+
+        if trimmed.is_empty() || trimmed == CANCEL_ARG {
+            return Ok(());
+        }
+        match trimmed.parse::<usize>() {
+            Ok(n) if (1..=shown).contains(&n) => break 'input results[n - 1].clone(),
+            _ => {
+                println!("Invalid choice! Try again or press Enter.");
+                continue 'input;
+            }
+        }
+    };
+
+    display_entry(vault, picked, show, clipboard, None)
+}
+
+pub fn handle_command_getall(
+    option_session: &mut Option<Session>,
+    show: bool,
+    list: &ListArgs,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+
+    if show {
+        let name_of_vault: &String = match &session.opened_vault {
+            Some(vault) => vault.get_name(),
+            None => {
+                return Err(SessionError::VaultError(VaultError::NoVaultOpen));
+            }
+        };
+        let master_input: SecretString =
+            rpassword::prompt_password(format!("Enter master password for '{}': ", name_of_vault))?
+                .into();
+        session.verify_master_pw(master_input)?;
+    }
+
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    display_entries(vault, show, list)
+}
+
+/// Prints the entries of the vault matching the filters, fails with CouldNotGetEntry if there are none at all.
+pub fn display_entries(vault: &Vault, show: bool, list: &ListArgs) -> Result<(), SessionError> {
+    let entries = vault.get_entries();
+
+    if entries.is_empty() {
         return Err(SessionError::VaultError(VaultError::CouldNotGetEntry));
     }
 
-    for entry in entries {
-        println!("\n==== Entry: {} ====", entry.get_entry_name());
+    let selected = select_entries(entries, list, Utc::now());
+    if selected.is_empty() {
+        println!("No entries match the given filters.");
+        return Ok(());
+    }
+
+    let expired: Vec<&str> = selected
+        .iter()
+        .filter(|entry| entry.is_expired())
+        .map(|entry| entry.get_entry_name().as_str())
+        .collect();
+    if !expired.is_empty() {
         println!(
-            "Username: {}",
-            entry.get_user_name().as_deref().unwrap_or("--EMPTY--")
+            "\nWarning: {} of the listed entries expired and should be changed: {}",
+            expired.len(),
+            expired.join(", ")
         );
+    }
+
+    if list.tree {
+        print_tree(&selected);
+        return Ok(());
+    }
+
+    for entry in selected {
+        print_entry(entry, show);
+    }
+
+    Ok(())
+}
+
+// Prints the entry names grouped by folder, subfolders below their parents.
+fn print_tree(entries: &[&Entry]) {
+    let mut by_folder: BTreeMap<Vec<&str>, Vec<&str>> = BTreeMap::new();
+    for entry in entries {
+        let path: Vec<&str> = entry
+            .get_folder()
+            .map(|folder| folder.split('/').collect())
+            .unwrap_or_default();
+        by_folder
+            .entry(path)
+            .or_default()
+            .push(entry.get_entry_name());
+    }
+
+    println!();
+    let mut previous: Vec<&str> = Vec::new();
+    for (path, names) in by_folder {
+        let common = previous
+            .iter()
+            .zip(&path)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (depth, segment) in path.iter().enumerate().skip(common) {
+            println!("{}{}/", "  ".repeat(depth), segment);
+        }
+        for name in names {
+            println!("{}{}", "  ".repeat(path.len()), name);
+        }
+        previous = path;
+    }
+    println!();
+}
+
+// Applies the filters and the sort order of `getall`, entries without a recorded date count as old.
+fn select_entries<'a>(entries: &'a [Entry], list: &ListArgs, now: DateTime<Utc>) -> Vec<&'a Entry> {
+    let older_than = |time: Option<DateTime<Utc>>, days: i64| {
+        time.is_none_or(|time| now - time >= TimeDelta::days(days))
+    };
+
+    let mut selected: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| {
+            list.password_older_than
+                .is_none_or(|days| older_than(entry.password_changed(), days))
+        })
+        .filter(|entry| {
+            list.unused_for
+                .is_none_or(|days| older_than(entry.last_used(), days))
+        })
+        .filter(|entry| {
+            list.modified_within.is_none_or(|days| {
+                entry
+                    .modified()
+                    .is_some_and(|time| now - time < TimeDelta::days(days))
+            })
+        })
+        .filter(|entry| !list.expired || entry.is_expired())
+        .filter(|entry| {
+            list.folder
+                .as_deref()
+                .is_none_or(|folder| entry.in_folder(folder))
+        })
+        .filter(|entry| list.tags.iter().all(|tag| entry.has_tag(tag)))
+        .collect();
+
+    match list.sort {
+        EntrySort::Name => selected.sort_by_key(|entry| entry.get_entry_name().to_lowercase()),
+        EntrySort::Created => selected.sort_by_key(|entry| entry.created()),
+        EntrySort::Modified => selected.sort_by_key(|entry| entry.modified()),
+        EntrySort::PasswordChanged => selected.sort_by_key(|entry| entry.password_changed()),
+        EntrySort::LastUsed => selected.sort_by_key(|entry| entry.last_used()),
+        // entries that never expire last
+        EntrySort::Expires => {
+            selected.sort_by_key(|entry| (entry.expires().is_none(), entry.expires()))
+        }
+    }
+    if list.reverse {
+        selected.reverse();
+    }
+    selected
+}
+
+fn print_entry(entry: &Entry, show: bool) {
+    println!("\n==== Entry: {} ====", entry.get_entry_name());
+    println!(
+        "Username: {}",
+        entry.get_user_name().as_deref().unwrap_or("--EMPTY--")
+    );
+    println!(
+        "URL:      {}",
+        entry.get_url().as_deref().unwrap_or("--EMPTY--")
+    );
+    println!(
+        "Notes:    {}",
+        entry.get_notes().as_deref().unwrap_or("--EMPTY--")
+    );
+    if let Some(folder) = entry.get_folder() {
+        println!("Folder:   {}", folder);
+    }
+    if !entry.get_tags().is_empty() {
+        println!("Tags:     {}", entry.get_tags().join(", "));
+    }
+
+    if show {
         println!(
-            "URL:      {}",
-            entry.get_url().as_deref().unwrap_or("--EMPTY--")
+            "Password: {}",
+            entry.get_password().as_deref().unwrap_or("--EMPTY--")
         );
+    } else {
+        println!("Password: *****");
+    }
+
+    print_fields(entry.get_fields(), show);
+
+    let date = |time: Option<DateTime<Utc>>| match time {
+        Some(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "unknown".to_string(),
+    };
+    println!(
+        "Dates:    created {}, modified {}, password changed {}, last used {}",
+        date(entry.created()),
+        date(entry.modified()),
+        date(entry.password_changed()),
+        match entry.last_used() {
+            Some(_) => date(entry.last_used()),
+            None => "never".to_string(),
+        }
+    );
+    if let Some(day) = entry.expires() {
         println!(
-            "Notes:    {}",
-            entry.get_notes().as_deref().unwrap_or("--EMPTY--")
+            "Expires:  {}{}",
+            day,
+            if entry.is_expired() { " (EXPIRED)" } else { "" }
         );
+    }
 
-        if show {
-            println!(
-                "Password: {}",
-                entry.get_password().as_deref().unwrap_or("--EMPTY--")
-            );
-        } else {
-            println!("Password: *****");
+    if let Some(totp) = entry.get_totp() {
+        match totp.current_code() {
+            Ok((code, remaining)) if show => {
+                println!("TOTP:     {} (valid for {} s)", code, remaining)
+            }
+            Ok(_) => println!("TOTP:     ***** (use 'totp {}')", entry.get_entry_name()),
+            Err(e) => println!("TOTP:     {}", e),
         }
-        println!();
+    }
+    println!();
+}
+
+pub fn handle_command_totp(
+    option_session: &mut Option<Session>,
+    entry_name: String,
+    clipboard: ClipboardArgs,
+    setup: TotpSetupArgs,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+    let entry = vault.get_entry_by_name(&entry_name).ok_or_else(|| {
+        println!("'{}' not found in current vault!", entry_name);
+        SessionError::VaultError(VaultError::EntryNotFound)
+    })?;
+
+    if let Some(input) = setup.set {
+        let totp = Totp::parse(&input)
+            .and_then(|totp| totp.with_options(setup.digits, setup.period, setup.algorithm))
+            .map_err(SessionError::VaultError)?;
+        entry.set_totp(totp);
+        println!("TOTP secret of '{}' saved.", entry_name);
+        return Ok(());
+    }
+    if setup.remove {
+        entry.remove_totp();
+        println!("TOTP secret of '{}' removed.", entry_name);
+        return Ok(());
     }
 
+    let totp = entry.get_totp().as_ref().ok_or_else(|| {
+        SessionError::VaultError(VaultError::InvalidTotp(format!(
+            "'{}' has no TOTP secret, add one with 'totp {} --set <secret>'",
+            entry_name, entry_name
+        )))
+    })?;
+    let (code, remaining) = totp.current_code().map_err(SessionError::VaultError)?;
+
+    if clipboard.copy {
+        // no need to keep it longer than it is valid
+        copy_secret(&code, clipboard.clear_after.unwrap_or(remaining))
+            .map_err(|_| SessionError::VaultError(VaultError::ClipboardError))?;
+        println!(
+            "✓ One-time code for '{}' copied to clipboard (valid for {} s)",
+            entry_name, remaining
+        );
+    } else {
+        println!(
+            "\n{} (valid for {} s, {} digits, {})\n",
+            code,
+            remaining,
+            totp.digits(),
+            totp.algorithm()
+        );
+    }
     Ok(())
 }
 
 pub fn handle_command_delete(
     option_session: &mut Option<Session>,
     entry_to_delete: String,
+    folder: Option<String>,
 ) -> Result<(), SessionError> {
     let session = option_session
         .as_mut()
@@ -626,15 +1590,24 @@ pub fn handle_command_delete(
         .opened_vault
         .as_mut()
         .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
-    let entry = if let Some(entry) = vault.get_entry_by_name(&entry_to_delete) {
+    let vault_name = vault.get_name().clone();
+    let entry = if let Some(entry) = vault
+        .get_entry_by_name(&entry_to_delete)
+        .filter(|entry| folder.as_deref().is_none_or(|f| entry.in_folder(f)))
+    {
         entry
     } else {
         println!();
-        println!(
-            "'{}' not found in current vault \"{}\"!",
-            entry_to_delete,
-            vault.get_name()
-        );
+        match &folder {
+            Some(folder) => println!(
+                "'{}' not found in folder '{}' of vault \"{}\"!",
+                entry_to_delete, folder, vault_name
+            ),
+            None => println!(
+                "'{}' not found in current vault \"{}\"!",
+                entry_to_delete, vault_name
+            ),
+        }
         return Err(SessionError::VaultError(VaultError::EntryNotFound));
     };
 
@@ -664,6 +1637,73 @@ pub fn handle_command_delete(
     Ok(())
 }
 
+pub fn handle_command_move(
+    option_session: &mut Option<Session>,
+    source: String,
+    destination: String,
+    whole_folder: bool,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+    let destination = normalize_folder(&destination);
+    let target = destination.as_deref().unwrap_or("the top level");
+
+    if !whole_folder {
+        let entry = vault
+            .get_entry_by_name(&source)
+            .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+        entry.set_folder(destination.as_deref().unwrap_or(""));
+        println!("\nEntry '{}' moved to {}.", source, target);
+        return Ok(());
+    }
+
+    // Moving a folder keeps its subfolders: work/old/db moved to archive becomes archive/old/db
+    let source_folder = normalize_folder(&source).ok_or_else(|| {
+        SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+            "The top level cannot be moved, move single entries instead"
+        )))
+    })?;
+    let parent_len = source_folder.rfind('/').map(|i| i + 1).unwrap_or(0);
+    if let Some(destination) = &destination
+        && (*destination == source_folder
+            || destination.starts_with(&format!("{}/", source_folder)))
+    {
+        return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+            "A folder cannot be moved into itself"
+        ))));
+    }
+
+    let mut moved = 0;
+    for entry in vault.entries.iter_mut() {
+        if !entry.in_folder(&source_folder) {
+            continue;
+        }
+        let own = entry.get_folder().unwrap_or_default().to_string();
+        let relative = &own[parent_len..];
+        let new_folder = match &destination {
+            Some(destination) => format!("{}/{}", destination, relative),
+            None => relative.to_string(),
+        };
+        entry.set_folder(&new_folder);
+        moved += 1;
+    }
+
+    if moved == 0 {
+        println!("There are no entries in folder '{}'.", source_folder);
+        return Err(SessionError::VaultError(VaultError::EntryNotFound));
+    }
+    println!(
+        "\nMoved '{}' with {} entries to {}.",
+        source_folder, moved, target
+    );
+    Ok(())
+}
+
 pub fn handle_command_deletevault(
     option_session: &mut Option<Session>,
 ) -> Result<(), SessionError> {
@@ -746,39 +1786,43 @@ pub fn handle_command_deletevault(
     Ok(())
 }
 
-pub fn handle_command_generate(length: u32, no_symbols: bool) -> Result<String, SessionError> {
-    if length <= 1 || length > 200 {
-        return Err(SessionError::VaultError(VaultError::InvalidLength));
-    }
-
-    let charset = if no_symbols {
-        charsets::ALPHANUMERIC
-    } else {
-        charsets::ASCII
+pub fn handle_command_generate(
+    option_session: &Option<Session>,
+    options: GeneratorArgs,
+    policy_name: Option<String>,
+    clear_after: Option<u64>,
+) -> Result<String, SessionError> {
+    let opened_vault = option_session
+        .as_ref()
+        .and_then(|session| session.opened_vault.as_ref());
+    let clipboard_timeout = clear_after
+        .or(opened_vault.and_then(|vault| vault.settings.clipboard_timeout))
+        .unwrap_or_else(|| config::current().clipboard_timeout);
+
+    let base = match &policy_name {
+        Some(name) => {
+            let vault = opened_vault.ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+            vault.settings.policies.get(name).cloned().ok_or_else(|| {
+                SessionError::VaultError(VaultError::InvalidPolicy(format!(
+                    "there is no policy '{}'",
+                    name
+                )))
+            })?
+        }
+        None => config::current().generator,
     };
 
-    let password = random_password(charset, length as usize, "")?;
-
-    println!("\n┌─────────────────────────────────────────┐");
-    println!("│ Generated Password                      │");
-    println!("├─────────────────────────────────────────┤");
-    println!("│ {: <40}│", password);
-    println!("├─────────────────────────────────────────┤");
-
-    let length_line = format!("Length: {} characters", length);
-    println!("│ {: <40}│", length_line);
-
-    let symbols_line = format!("Symbols: {}", if no_symbols { "No" } else { "Yes" });
-    println!("│ {: <40}│", symbols_line);
-
-    println!("└─────────────────────────────────────────┘\n");
-
-    copy_to_clipboard(&password)?;
-    Ok(password)
+    show_generated(
+        &options.apply(base),
+        policy_name.as_deref(),
+        clipboard_timeout,
+    )
 }
 
 pub fn handle_command_change_master(
     option_session: &mut Option<Session>,
+    key_file: KeyFileArgs,
+    remove_key_file: bool,
 ) -> Result<(), SessionError> {
     if !active_session(option_session) {
         return Err(SessionError::SessionInactive);
@@ -794,9 +1838,29 @@ pub fn handle_command_change_master(
         session_vault_name
     ))?
     .into();
-    session.verify_master_pw(old_password)?;
+    session.verify_master_pw(old_password.clone())?;
+
+    let changes_key_file = remove_key_file || !key_file.is_empty();
+    if remove_key_file && !session.uses_key_file() {
+        return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+            "Vault '{}' does not use a key file!",
+            session_vault_name
+        ))));
+    }
+
+    let keep_password = changes_key_file && {
+        print!("Do you also want to change the master password? (y/n): ");
+        stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        !input.trim().eq_ignore_ascii_case("y")
+    };
 
     let new_password: SecretString = 'input_new_master: loop {
+        if keep_password {
+            break 'input_new_master old_password;
+        }
+
         io::stdout().flush().unwrap();
         let input: SecretString = rpassword::prompt_password(format!(
             "Enter the new master password for '{}': ",
@@ -824,8 +1888,20 @@ pub fn handle_command_change_master(
         }
     };
 
+    if changes_key_file {
+        let new_key_file = key_file.resolve().map_err(SessionError::VaultError)?;
+        if new_key_file.is_some() {
+            println!("From now on the vault requires the key file.");
+        } else {
+            println!("The vault no longer requires a key file.");
+        }
+        session.change_key_file(new_key_file);
+    }
+
     session.change_master_pw(new_password)?;
-    println!("Master password successfully updated!");
+    if !keep_password {
+        println!("Master password successfully updated!");
+    }
 
     let spinner = spinner();
     spinner.set_message("Automatically encrypting vault with new password ...");
@@ -873,6 +1949,11 @@ pub fn handle_command_edit(
     let current_notes = current_entry.get_notes().clone();
     let current_password = current_entry.get_password().clone();
     let has_password = current_password.is_some();
+    let has_totp = current_entry.get_totp().is_some();
+    let current_fields = current_entry.get_fields().clone();
+    let current_expiry = current_entry.expires();
+    let current_folder = current_entry.get_folder().map(|f| f.to_string());
+    let current_tags = current_entry.get_tags().clone();
 
     // Collect all existing entrynames except the own one
     let existing_names: Vec<String> = vault
@@ -982,30 +2063,165 @@ pub fn handle_command_edit(
             }
         }
     } else {
-        add_password_to_entry()?
+        let site_policy = new_url
+            .as_deref()
+            .or(current_url.as_deref())
+            .and_then(|url| policy_for_url(&vault.settings.policies, url))
+            .map(|(name, policy)| (name.clone(), policy.clone()));
+        add_password_to_entry(site_policy, vault.settings.effective_clipboard_timeout())?
     };
 
-    let entry = vault
-        .get_entry_by_name(&entry_name)
-        .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+    // TOTP
+    let new_totp = read_totp(
+        &format!(
+            "New TOTP secret or otpauth:// URI (press Enter to keep current, type 'clear' to remove) [current: {}]: ",
+            if has_totp { "set" } else { "--EMPTY--" }
+        ),
+        true,
+    )?;
+
+    // Expiry date, None keeps the current one, Some(None) removes it
+    let new_expiry = loop {
+        print!(
+            "New expiry date YYYY-MM-DD (press Enter to keep current, type 'clear' to remove) [current: {}]: ",
+            current_expiry
+                .map(|day| day.to_string())
+                .unwrap_or("--EMPTY--".to_string())
+        );
+        stdout().flush().unwrap();
+        let mut input_expiry = String::new();
+        io::stdin().read_line(&mut input_expiry)?;
+        let trimmed = input_expiry.trim();
 
-    if let Some(new_name) = new_entryname {
-        entry.entryname = new_name;
-    }
-    if let Some(username) = new_username {
-        entry.set_username(username);
-    }
-    if let Some(url) = new_url {
-        entry.set_url(url);
-    }
-    if let Some(notes) = new_notes {
-        entry.set_notes(notes);
-    }
-    if let Some(password) = new_password {
-        entry.set_password(password);
-    }
+        if trimmed.is_empty() {
+            break None;
+        } else if trimmed == "clear" {
+            break Some(None);
+        }
+        match parse_date_arg(trimmed) {
+            Ok(day) => break Some(Some(day)),
+            Err(e) => println!("Error: {}", e),
+        }
+    };
 
-    let final_entry_name = entry.get_entry_name().clone();
+    // Folder, '/' moves the entry to the top level
+    print!(
+        "New folder, '/' for the top level [current: {}]: ",
+        current_folder.as_deref().unwrap_or("--EMPTY--")
+    );
+    stdout().flush().unwrap();
+    let mut input_folder = String::new();
+    io::stdin().read_line(&mut input_folder)?;
+    let new_folder = if input_folder.trim().is_empty() {
+        None
+    } else {
+        Some(input_folder.trim().to_string())
+    };
+
+    // Tags, replaced as a whole
+    print!(
+        "New tags, comma separated (type 'clear' to remove all) [current: {}]: ",
+        if current_tags.is_empty() {
+            "--EMPTY--".to_string()
+        } else {
+            current_tags.join(", ")
+        }
+    );
+    stdout().flush().unwrap();
+    let mut input_tags = String::new();
+    io::stdin().read_line(&mut input_tags)?;
+    let new_tags: Option<Vec<String>> = match input_tags.trim() {
+        "" => None,
+        "clear" => Some(Vec::new()),
+        tags => Some(tags.split(',').map(|t| t.trim().to_string()).collect()),
+    };
+
+    // Custom fields: pick one by name to change or remove it, an unknown name adds a new field
+    let mut field_changes: Vec<(String, Option<CustomField>)> = Vec::new();
+    loop {
+        let names: Vec<&str> = current_fields.iter().map(|f| f.name()).collect();
+        print!(
+            "Custom field to add, change or remove (press Enter to finish) [current: {}]: ",
+            if names.is_empty() {
+                "--EMPTY--".to_string()
+            } else {
+                names.join(", ")
+            }
+        );
+        stdout().flush().unwrap();
+        let mut input_field = String::new();
+        io::stdin().read_line(&mut input_field)?;
+        let field_name = input_field.trim().to_string();
+        if field_name.is_empty() {
+            break;
+        } else if field_name == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+
+        let current = current_fields.iter().find(|f| f.name() == field_name);
+        let field_type = match current {
+            Some(field) => {
+                println!("(type 'clear' to remove the field)");
+                field.field_type()
+            }
+            None => read_field_type()?,
+        };
+        if let Some(change) = read_field_value(&field_name, field_type, current)? {
+            field_changes.push((field_name, change));
+        }
+    }
+
+    let history_limit = vault.settings.history_count;
+    let stored_entry = vault
+        .get_entry_by_name(&entry_name)
+        .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+    // changes go to a copy, so a failing change leaves the entry as it was
+    let mut entry = stored_entry.clone();
+    let previous = entry.snapshot();
+
+    if let Some(new_name) = new_entryname {
+        entry.rename(new_name);
+    }
+    if let Some(username) = new_username {
+        entry.set_username(username);
+    }
+    if let Some(url) = new_url {
+        entry.set_url(url);
+    }
+    if let Some(notes) = new_notes {
+        entry.set_notes(notes);
+    }
+    if let Some(password) = new_password {
+        entry.set_password(password);
+    }
+    match new_totp {
+        Some(Some(totp)) => entry.set_totp(totp),
+        Some(None) => entry.remove_totp(),
+        None => { /* keep current */ }
+    }
+    if let Some(folder) = new_folder {
+        entry.set_folder(&folder);
+    }
+    if let Some(tags) = new_tags {
+        entry.set_tags(&tags);
+    }
+    match new_expiry {
+        Some(Some(day)) => entry.set_expiry(day),
+        Some(None) => entry.remove_expiry(),
+        None => { /* keep current */ }
+    }
+    for (field_name, change) in field_changes {
+        match change {
+            Some(field) => entry.set_field(field),
+            None => entry
+                .remove_field(&field_name)
+                .map_err(SessionError::VaultError)?,
+        }
+    }
+    entry.keep_version(previous, history_limit);
+
+    let final_entry_name = entry.get_entry_name().clone();
+    *stored_entry = entry;
 
     let spinner = spinner();
     spinner.enable_steady_tick(Duration::from_millis(80));
@@ -1018,56 +2234,160 @@ pub fn handle_command_edit(
     Ok(())
 }
 
-pub fn handle_command_open(
-    vault_to_open: String,
-    current_session: &mut Option<Session>,
-    timeout: &Option<u64>,
-) -> Result<Session, SessionError> {
-    match vault_exists(&vault_to_open) {
-        Ok(true) => { /* Do nothing */ }
-        Ok(false) => return Err(SessionError::VaultError(VaultError::VaultDoesNotExist)),
-        Err(e) => return Err(SessionError::VaultError(e)),
-    }
+pub fn handle_command_history(
+    option_session: &mut Option<Session>,
+    entry_name: String,
+    show: bool,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
 
-    if let Some(session) = current_session.as_ref()
-        && vault_to_open == session.vault_name
-        && active_session(current_session)
-    {
-        println!();
-        return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
-            "Vault '{}' already opened!",
-            vault_to_open
-        ))));
+    if show {
+        let name_of_vault: &String = match &session.opened_vault {
+            Some(vault) => vault.get_name(),
+            None => {
+                return Err(SessionError::VaultError(VaultError::NoVaultOpen));
+            }
+        };
+        let master_input: SecretString =
+            rpassword::prompt_password(format!("Enter master password for '{}': ", name_of_vault))?
+                .into();
+        session.verify_master_pw(master_input)?;
     }
 
-    if let Some(session) = current_session.as_mut() {
-        let old_name = session.vault_name.clone();
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
 
-        println!();
+    display_history(vault, entry_name, show)
+}
 
-        let spinner = spinner();
-        spinner.set_message(format!(
-            "Closing currently opened vault '{}' first",
-            old_name
-        ));
-        spinner.enable_steady_tick(Duration::from_millis(80));
+/// Prints the earlier versions of an entry, callers have to re-authenticate for `show` themselves.
+pub fn display_history(
+    vault: &mut Vault,
+    entry_name: String,
+    show: bool,
+) -> Result<(), SessionError> {
+    let entry = vault
+        .get_entry_by_name(&entry_name)
+        .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
 
-        match session.end_session() {
-            Ok(()) => {
-                spinner.finish_and_clear();
-                println!("Vault '{}' closed successfully.", old_name);
-            }
-            Err(SessionError::SessionInactive) => {
-                spinner.finish_and_clear();
-                // Session was already inactive, just verify it's cleared
+    print_history(entry, show);
+    Ok(())
+}
+
+pub fn handle_command_restore(
+    option_session: &mut Option<Session>,
+    entry_name: String,
+    version: Option<usize>,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+    let history_limit = vault.settings.history_count;
+    let entry = vault
+        .get_entry_by_name(&entry_name)
+        .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+
+    let count = entry.get_history().len();
+    if count == 0 {
+        println!("There are no earlier versions of '{}'.", entry_name);
+        return Err(SessionError::VaultError(VaultError::VersionNotFound));
+    }
+
+    let number = match version {
+        Some(n) if (1..=count).contains(&n) => n,
+        Some(_) => return Err(SessionError::VaultError(VaultError::VersionNotFound)),
+        None => {
+            print_history(entry, false);
+            'input: loop {
+                print!(
+                    "Which version do you want to restore? (1-{}, or '{}'): ",
+                    count, CANCEL_ARG
+                );
+                stdout().flush().unwrap();
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                let trimmed = input.trim();
+
+                if trimmed == CANCEL_ARG {
+                    return Err(SessionError::VaultError(VaultError::ActionCancelled));
+                }
+                match trimmed.parse::<usize>() {
+                    Ok(n) if (1..=count).contains(&n) => break 'input n,
+                    _ => {
+                        println!("Invalid choice! Try again or type '{}'.", CANCEL_ARG);
+                        continue 'input;
+                    }
+                }
             }
-            Err(_) => {
-                spinner.finish_and_clear();
-                return Err(SessionError::VaultError(VaultError::CouldNotClose));
+        }
+    };
+
+    let replaced = entry.get_history()[number - 1].replaced;
+    println!(
+        "'{}' will be restored to the version replaced on {}.",
+        entry_name,
+        replaced.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("The current values are kept as the newest version.");
+    print!("Do you wish to continue? (y/n): ");
+    stdout().flush().unwrap();
+
+    let mut confirm = String::new();
+    io::stdin().read_line(&mut confirm)?;
+    if !confirm.trim().eq_ignore_ascii_case("y") {
+        return Err(SessionError::VaultError(VaultError::ActionCancelled));
+    }
+
+    entry
+        .restore_version(number - 1, history_limit)
+        .map_err(SessionError::VaultError)?;
+
+    println!("\nEntry '{}' restored successfully!", entry_name);
+    Ok(())
+}
+
+/// The name of the vault to open, a vault file given with `--file` is registered under its file name.
+pub fn vault_name_or_file(
+    name: Option<String>,
+    file: Option<PathBuf>,
+) -> Result<String, VaultError> {
+    match (name, file) {
+        (_, Some(file)) => {
+            if !file.is_file() {
+                return Err(VaultError::VaultDoesNotExist);
             }
+            register_vault_file(&file)
         }
-        *current_session = None;
+        (Some(name), None) => Ok(name),
+        (None, None) => Err(VaultError::VaultDoesNotExist),
     }
+}
+
+// Other unlocked vaults stay open, the caller makes the new session the current one.
+pub fn handle_command_open(
+    vault_to_open: String,
+    timeout: &Option<u64>,
+    key_file: Option<PathBuf>,
+) -> Result<Session, SessionError> {
+    match vault_exists(&vault_to_open) {
+        Ok(true) => { /* Do nothing */ }
+        Ok(false) => return Err(SessionError::VaultError(VaultError::VaultDoesNotExist)),
+        Err(e) => return Err(SessionError::VaultError(e)),
+    }
+
+    let key_file = key_file
+        .map(|path| KeyFile::load(&path))
+        .transpose()
+        .map_err(SessionError::VaultError)?;
 
     println!();
     println!("Selected vault: {}", vault_to_open);
@@ -1083,7 +2403,7 @@ pub fn handle_command_open(
     if let Some(minutes) = timeout {
         new_session.wished_timeout = minutes * 60;
     }
-    match new_session.start_session(master) {
+    match new_session.start_session_with_key_file(master, key_file) {
         Ok(()) => {
             spinner.finish_and_clear();
 
@@ -1104,7 +2424,7 @@ pub fn handle_command_open(
             println!("║{: <43}║", entries_line);
             println!("║                                           ║");
 
-            let timeout_minutes = timeout.unwrap_or(5);
+            let timeout_minutes = new_session.wished_timeout / 60;
             let timeout_line = format!("  Auto-close after {} min inactivity", timeout_minutes);
             println!("║{: <43}║", timeout_line);
             println!("╚═══════════════════════════════════════════╝");
@@ -1182,33 +2502,60 @@ pub fn handle_command_close(
     Ok(LoopCommand::Continue)
 }
 
-pub fn handle_command_vaults(current_session: &Option<Session>) {
+pub fn handle_command_use(sessions: &mut Sessions, name: String) -> Result<(), SessionError> {
+    sessions.switch_to(&name)?;
+    println!("Switched to vault '{}'.", name);
+    Ok(())
+}
+
+pub fn handle_command_copy_entry(
+    sessions: &mut Sessions,
+    names: Vec<String>,
+    to: String,
+    on_conflict: ConflictPolicy,
+) -> Result<(), SessionError> {
+    transfer_entries(sessions, names, to, on_conflict, false)
+}
+
+pub fn handle_command_move_entry(
+    sessions: &mut Sessions,
+    names: Vec<String>,
+    to: String,
+    on_conflict: ConflictPolicy,
+) -> Result<(), SessionError> {
+    transfer_entries(sessions, names, to, on_conflict, true)
+}
+
+// `unlocked` are the names of the vaults unlocked besides the current one.
+pub fn handle_command_vaults(current: Option<&str>, unlocked: &[String]) {
     println!("\n=== Available Vaults ===");
 
-    match list_vaults() {
-        Ok(mut vault_files) => {
-            if vault_files.is_empty() {
+    match list_vaults_by_dir() {
+        Ok(dirs) => {
+            if dirs.iter().all(|(_, vault_files)| vault_files.is_empty()) {
                 println!("  (no vaults found)");
                 println!("\nCreate a new vault with: init <vault_name>");
             } else {
-                vault_files.sort();
+                // only the first vault of a name opens, the others are hidden by it
+                let mut seen: Vec<String> = Vec::new();
 
-                if current_session.is_none() || !active_session(current_session) {
-                    for vault_name in vault_files {
-                        println!("    {}", vault_name);
+                for (dir, mut vault_files) in dirs {
+                    if vault_files.is_empty() {
+                        continue;
                     }
-                } else {
-                    let current_vault_name = current_session
-                        .as_ref()
-                        .and_then(|s| s.opened_vault.as_ref())
-                        .map(|v| v.get_name());
-
+                    println!("  {}", dir.display());
+                    vault_files.sort();
                     for vault_name in vault_files {
-                        if Some(&vault_name) == current_vault_name {
-                            println!("  → {} (currently open)", vault_name);
+                        if seen.contains(&vault_name) {
+                            println!("      {} (hidden by the one above)", vault_name);
+                        } else if current == Some(vault_name.as_str()) {
+                            println!("  →   {} (currently open)", vault_name);
+                        } else if unlocked.contains(&vault_name) {
+                            println!("      {} (unlocked)", vault_name);
                         } else {
-                            println!("    {}", vault_name);
+                            println!("      {}", vault_name);
                         }
+                        seen.push(vault_name);
                     }
                 }
             }
@@ -1220,50 +2567,1176 @@ pub fn handle_command_vaults(current_session: &Option<Session>) {
     }
 }
 
-pub fn handle_command_clear() {
-    clear_terminal();
-    intro_animation();
+pub fn handle_command_restore_backup(
+    option_session: &mut Option<Session>,
+    name: Option<String>,
+) -> Result<(), SessionError> {
+    let open_vault_name = option_session.as_ref().map(|s| s.vault_name.clone());
+    let vault_name = name
+        .or_else(|| open_vault_name.clone())
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    if !vault_exists(&vault_name).map_err(SessionError::VaultError)? {
+        return Err(SessionError::VaultError(VaultError::VaultDoesNotExist));
+    }
+
+    let backups = list_backups(&vault_name).map_err(SessionError::VaultError)?;
+    if backups.is_empty() {
+        println!("There are no backups of '{}' yet.", vault_name);
+        return Ok(());
+    }
+
+    println!("\n=== Backups of '{}' ===", vault_name);
+    for (i, backup) in backups.iter().enumerate() {
+        println!(
+            "  [{}] {}",
+            i + 1,
+            backup.created.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    println!();
+
+    let backup = 'input: loop {
+        print!(
+            "Which backup do you want to restore? (1-{}, or '{}'): ",
+            backups.len(),
+            CANCEL_ARG
+        );
+        stdout().flush().unwrap();
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let trimmed = input.trim();
+
+        if trimmed == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+        match trimmed.parse::<usize>() {
+            Ok(n) if (1..=backups.len()).contains(&n) => break 'input &backups[n - 1],
+            _ => {
+                println!("Invalid choice! Try again or type '{}'.", CANCEL_ARG);
+                continue 'input;
+            }
+        }
+    };
+
+    println!(
+        "WARNING: '{}' will be replaced by the backup from {}.",
+        vault_name,
+        backup.created.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("The current file is backed up first.");
+    print!("Do you wish to continue? (y/n): ");
+    stdout().flush().unwrap();
+
+    let mut confirm = String::new();
+    io::stdin().read_line(&mut confirm)?;
+    if !confirm.trim().eq_ignore_ascii_case("y") {
+        return Err(SessionError::VaultError(VaultError::ActionCancelled));
+    }
+
+    let spinner = spinner();
+    spinner.set_message("Restoring backup ...");
+    spinner.enable_steady_tick(Duration::from_millis(80));
+    restore_backup(&vault_name, backup).map_err(SessionError::VaultError)?;
+    spinner.finish_and_clear();
+
+    println!("Backup restored!");
+    // The vault in memory is older than the file now and must not be saved over it.
+    if open_vault_name.as_deref() == Some(vault_name.as_str()) {
+        *option_session = None;
+        println!(
+            "'{}' was closed, open it again with the master password of the backup.",
+            vault_name
+        );
+    }
+    Ok(())
+}
+
+pub fn handle_command_rekdf(
+    option_session: &mut Option<Session>,
+    kdf: KdfArgs,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault_name = session.vault_name.clone();
+    let current = session
+        .opened_vault
+        .as_ref()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?
+        .settings
+        .kdf;
+
+    println!("\nCurrent key derivation of '{}': {}", vault_name, current);
+    if kdf.is_empty() {
+        println!(
+            "Hint: Use --kdf-memory, --kdf-iterations, --kdf-parallelism or --benchmark to change it."
+        );
+        return Err(SessionError::VaultError(VaultError::ActionCancelled));
+    }
+
+    let new_settings = resolve_kdf_settings(current, &kdf).map_err(SessionError::VaultError)?;
+    println!("New key derivation: {}", new_settings);
+
+    print!("Enter master password for {}: ", vault_name);
+    stdout().flush().unwrap();
+    let master_input: SecretString = rpassword::read_password()?.into();
+    session.verify_master_pw(master_input)?;
+
+    if let Some(vault) = session.opened_vault.as_mut() {
+        vault.settings.kdf = new_settings;
+    }
+    println!("Password verified, the vault is re-encrypted when it is saved.");
+    Ok(())
+}
+
+pub fn handle_command_settings(
+    option_session: &mut Option<Session>,
+    backups: Option<usize>,
+    history: Option<usize>,
+    clipboard_timeout: Option<u64>,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    if let Some(count) = backups {
+        vault.settings.backup_count = count;
+    }
+    if let Some(count) = history {
+        vault.settings.history_count = count;
+        // shorten existing histories right away, not only when an entry changes the next time
+        for entry in vault.entries.iter_mut() {
+            entry.truncate_history(count);
+        }
+    }
+    if let Some(seconds) = clipboard_timeout {
+        vault.settings.clipboard_timeout = Some(seconds);
+    }
+
+    println!("\n=== Settings of '{}' ===", vault.get_name());
+    println!(
+        "  Backups kept: {}",
+        match vault.settings.backup_count {
+            0 => "disabled".to_string(),
+            n => n.to_string(),
+        }
+    );
+    println!(
+        "  Versions kept per entry: {}",
+        match vault.settings.history_count {
+            0 => "disabled".to_string(),
+            n => n.to_string(),
+        }
+    );
+    println!(
+        "  Clipboard cleared after: {} seconds{}",
+        vault.settings.effective_clipboard_timeout(),
+        match vault.settings.clipboard_timeout {
+            Some(_) => "",
+            None => " (from the config)",
+        }
+    );
+    println!("  Key derivation: {}", vault.settings.kdf);
+    println!();
+    Ok(())
+}
+
+pub fn handle_command_policy(
+    option_session: &mut Option<Session>,
+    action: PolicyCommand,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    match action {
+        PolicyCommand::List {} => {
+            if vault.settings.policies.is_empty() {
+                println!("Vault '{}' has no password policies.", vault.get_name());
+                println!("Hint: Use 'policy set <name> [options]' to create one.");
+                return Ok(());
+            }
+            println!("\n=== Password policies of '{}' ===", vault.get_name());
+            for (name, policy) in &vault.settings.policies {
+                println!("  {}: {}", name, policy);
+            }
+            println!();
+        }
+        PolicyCommand::Set { name, options } => {
+            let name = name.trim().to_string();
+            if name.is_empty() {
+                return Err(SessionError::VaultError(VaultError::InvalidPolicy(
+                    "the name cannot be empty".to_string(),
+                )));
+            }
+            let policy = options.apply(config::current().generator);
+            // a policy that cannot generate anything is rejected right away
+            generate(&policy).map_err(SessionError::VaultError)?;
+            println!("Policy '{}' saved: {}", name, policy);
+            vault.settings.policies.insert(name, policy);
+        }
+        PolicyCommand::Remove { name } => {
+            if vault.settings.policies.remove(&name).is_none() {
+                return Err(SessionError::VaultError(VaultError::InvalidPolicy(
+                    format!("there is no policy '{}'", name),
+                )));
+            }
+            println!("Policy '{}' removed.", name);
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_command_config(action: ConfigCommand) -> Result<(), VaultError> {
+    let overrides = config::overrides();
+
+    match action {
+        ConfigCommand::List {} => {
+            println!(
+                "\n=== Config ({}) ===",
+                config::config_path(&overrides)?.display()
+            );
+            if let Some(dir) = config::portable_dir() {
+                println!(
+                    "  Portable mode: vaults and config are kept in {}",
+                    dir.display()
+                );
+            }
+            for value in config::list(&overrides)? {
+                println!(
+                    "  {} = {}  [{}]",
+                    value.key,
+                    value.value.as_deref().unwrap_or("(not set)"),
+                    value.source
+                );
+            }
+            println!();
+        }
+        ConfigCommand::Get { key } => match config::get(&overrides, &key)? {
+            Some(value) => println!("{}", value),
+            None => println!("{} is not set", key),
+        },
+        ConfigCommand::Set { key, value } => {
+            config::set(&overrides, &key, &value)?;
+            println!(
+                "Set {} = {} in {}",
+                key,
+                value,
+                config::config_path(&overrides)?.display()
+            );
+            if let Some(source) = config::overridden_by(&overrides, &key) {
+                println!("Hint: {} is overridden by {} right now.", key, source);
+            }
+            // the session and the extension server have read their values already
+            if matches!(key.as_str(), "session_timeout" | "extension_address") {
+                println!("Hint: {} takes effect the next time pw is started.", key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_command_import(
+    option_session: &mut Option<Session>,
+    file: PathBuf,
+    format: ImportFormat,
+    key_file: Option<PathBuf>,
+    on_conflict: ConflictPolicy,
+    dry_run: bool,
+    yes: bool,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    let format = match format {
+        ImportFormat::Auto if is_kdbx_file(&file) => ImportFormat::Kdbx,
+        ImportFormat::Auto if is_encrypted_export(&file) => ImportFormat::Encrypted,
+        ImportFormat::Auto if is_json_file(&file) => ImportFormat::BitwardenJson,
+        format => format,
+    };
+    if key_file.is_some() && format != ImportFormat::Kdbx {
+        return Err(SessionError::VaultError(VaultError::InvalidImport(
+            "--key-file only applies to KeePass databases".to_string(),
+        )));
+    }
+
+    let imported = match format {
+        ImportFormat::Kdbx | ImportFormat::Encrypted => {
+            let password: SecretString = rpassword::prompt_password(format!(
+                "Enter the password of '{}': ",
+                file.display()
+            ))?
+            .into();
+            let (entries, warnings) = if format == ImportFormat::Kdbx {
+                let database = read_kdbx_file(&file, &password, key_file.as_deref())
+                    .map_err(SessionError::VaultError)?;
+                (database.vault.entries, database.warnings)
+            } else {
+                let exported =
+                    read_encrypted_export(&file, &password).map_err(SessionError::VaultError)?;
+                (exported.entries, Vec::new())
+            };
+            Imported {
+                format,
+                entries,
+                warnings,
+            }
+        }
+        ImportFormat::BitwardenJson => {
+            read_bitwarden_json_file(&file).map_err(SessionError::VaultError)?
+        }
+        _ => read_csv_file(&file, format).map_err(SessionError::VaultError)?,
+    };
+    let plan = plan_import(vault, imported.entries, on_conflict);
+
+    println!(
+        "\n=== Import from '{}' ({}) ===",
+        file.display(),
+        imported.format
+    );
+    print_import_plan(&plan);
+    if !imported.warnings.is_empty() {
+        println!("\nWarnings:");
+        for warning in &imported.warnings {
+            println!("  {}", warning);
+        }
+    }
+    println!();
+
+    let to_import = plan
+        .iter()
+        .filter(|planned| planned.action != ImportAction::Skip)
+        .count();
+    if dry_run {
+        println!("Dry run, nothing was imported.");
+        return Ok(());
+    }
+    if to_import == 0 {
+        println!("Nothing to import.");
+        return Ok(());
+    }
+
+    if !yes {
+        print!(
+            "Import {} entries into '{}'? (y/n): ",
+            to_import,
+            vault.get_name()
+        );
+        stdout().flush().unwrap();
+
+        let mut confirm = String::new();
+        io::stdin().read_line(&mut confirm)?;
+        if !confirm.trim().eq_ignore_ascii_case("y") {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+    }
+
+    let count = apply_import(vault, plan).map_err(SessionError::VaultError)?;
+    println!("\n{} entries imported into '{}'!", count, vault.get_name());
+    Ok(())
+}
+
+pub fn handle_command_export(
+    option_session: &mut Option<Session>,
+    file: PathBuf,
+    format: ExportFormat,
+    key_file: Option<PathBuf>,
+    selection: ExportSelection,
+) -> Result<(), SessionError> {
+    if key_file.is_some() && format != ExportFormat::Kdbx {
+        return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+            "--key-file only applies to --format kdbx"
+        ))));
+    }
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let selected = match &session.opened_vault {
+        Some(vault) => exported_vault(vault, &selection),
+        None => return Err(SessionError::VaultError(VaultError::NoVaultOpen)),
+    };
+    if selected.entries.is_empty() {
+        println!("No entries match, nothing was exported.");
+        return Ok(());
+    }
+
+    // every export contains the passwords in a file that no longer needs the master password
+    let master_input: SecretString =
+        rpassword::prompt_password(format!("Enter master password for '{}': ", selected.name))?
+            .into();
+    session.verify_master_pw(master_input)?;
+
+    if file.exists() {
+        print!("'{}' already exists, overwrite it? (y/n): ", file.display());
+        stdout().flush().unwrap();
+
+        let mut confirm = String::new();
+        io::stdin().read_line(&mut confirm)?;
+        if !confirm.trim().eq_ignore_ascii_case("y") {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+    }
+
+    if format.is_plaintext() {
+        let (contents, warnings) = match format {
+            ExportFormat::Json => (
+                to_json(&selected).map_err(SessionError::VaultError)?,
+                Vec::new(),
+            ),
+            ExportFormat::Bitwarden => {
+                bitwarden::write(&selected).map_err(SessionError::VaultError)?
+            }
+            _ => {
+                let mut contents = Vec::new();
+                write_csv(&mut contents, &selected).map_err(SessionError::VaultError)?;
+                (String::from_utf8_lossy(&contents).into_owned(), Vec::new())
+            }
+        };
+        if !warnings.is_empty() {
+            println!("\nNot exported:");
+            for warning in &warnings {
+                println!("  {}", warning);
+            }
+        }
+
+        println!("\n!!! WARNING: UNENCRYPTED EXPORT !!!");
+        println!(
+            "'{}' will contain {} entries with their passwords in PLAIN TEXT.",
+            file.display(),
+            selected.entries.len()
+        );
+        println!("Anyone who can read the file can read them. Delete it as soon as possible.");
+        print!("Type 'plaintext' to continue: ");
+        stdout().flush().unwrap();
+
+        let mut confirm = String::new();
+        io::stdin().read_line(&mut confirm)?;
+        if confirm.trim() != "plaintext" {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+
+        write_export_file(&file, contents.as_bytes()).map_err(SessionError::VaultError)?;
+    } else {
+        let passphrase = read_export_passphrase()?;
+        match format {
+            ExportFormat::Kdbx => {
+                write_kdbx_file(&selected, &file, &passphrase, key_file.as_deref())
+            }
+            _ => write_encrypted_export(&selected, &file, &passphrase),
+        }
+        .map_err(SessionError::VaultError)?;
+    }
+
+    println!(
+        "\n{} entries exported to '{}' ({})!",
+        selected.entries.len(),
+        file.display(),
+        format
+    );
+    Ok(())
+}
+
+/// Returns whether the vault was changed and has to be saved.
+pub fn handle_command_merge(
+    option_session: &mut Option<Session>,
+    file: PathBuf,
+    key_file: Option<PathBuf>,
+    show: bool,
+    dry_run: bool,
+) -> Result<bool, SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let key_file = key_file
+        .map(|path| KeyFile::load(&path))
+        .transpose()
+        .map_err(SessionError::VaultError)?;
+
+    if show {
+        let master_input: SecretString = rpassword::prompt_password(format!(
+            "Enter master password for '{}': ",
+            session.vault_name
+        ))?
+        .into();
+        session.verify_master_pw(master_input)?;
+    }
+
+    // copies of a vault usually still share the master password
+    let progress = spinner();
+    progress.set_message(format!("Decrypting '{}' ...", file.display()));
+    progress.enable_steady_tick(Duration::from_millis(80));
+    let copy = session.read_copy(&file, key_file.as_ref());
+    progress.finish_and_clear();
+    let other = match copy {
+        Ok(vault) => vault,
+        Err(SessionError::VaultError(VaultError::InvalidKey)) => {
+            let password: SecretString = rpassword::prompt_password(format!(
+                "Enter the master password of '{}': ",
+                file.display()
+            ))?
+            .into();
+            let progress = spinner();
+            progress.set_message(format!("Decrypting '{}' ...", file.display()));
+            progress.enable_steady_tick(Duration::from_millis(80));
+            let copy = read_vault_file(&file, password, key_file.as_ref());
+            progress.finish_and_clear();
+            copy.map_err(SessionError::VaultError)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    let vault = session
+        .opened_vault
+        .as_mut()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+    let plan = plan_merge(vault, &other);
+
+    println!(
+        "\n=== Merge of '{}' into '{}' ===",
+        file.display(),
+        vault.get_name()
+    );
+    print_merge_plan(&plan);
+    println!();
+
+    if !plan.changes_vault(vault) {
+        println!("Nothing to merge, the other copy has no changes.");
+        return Ok(false);
+    }
+    if dry_run {
+        println!("Dry run, nothing was merged.");
+        return Ok(false);
+    }
+
+    let conflicts: Vec<&MergeConflict> = plan.conflicts().collect();
+    let mut choices = Vec::new();
+    for (i, conflict) in conflicts.iter().enumerate() {
+        print_merge_conflict(conflict, i + 1, conflicts.len(), show);
+        choices.push(ask_merge_side(conflict.suggested)?);
+    }
+
+    for (from, to) in apply_merge(vault, plan, &choices) {
+        println!("  {} -> {} (name exists)", from, to);
+    }
+    println!(
+        "\n'{}' was merged into '{}'!",
+        file.display(),
+        vault.get_name()
+    );
+    Ok(true)
+}
+
+pub fn handle_command_audit(
+    option_session: &mut Option<Session>,
+    options: AuditOptions,
+    json: bool,
+) -> Result<(), SessionError> {
+    let session = option_session
+        .as_mut()
+        .ok_or(SessionError::SessionInactive)?;
+    let vault = session
+        .opened_vault
+        .as_ref()
+        .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+
+    print_audit(vault, options, json)
+}
+
+/// Prints the audit of the vault as tables or as JSON. No password is ever printed.
+pub fn print_audit(vault: &Vault, options: AuditOptions, json: bool) -> Result<(), SessionError> {
+    let report = audit(vault, &options, Utc::now()).map_err(SessionError::VaultError)?;
+    if json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| SessionError::VaultError(e.into()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!(
+        "\n=== Audit of '{}' ({} entries) ===",
+        report.vault, report.checked
+    );
+    print_audit_report(&report, &options);
+
+    match report.issue_count() {
+        0 => println!("\nNo issues found."),
+        count => println!("\n{} issues found.", count),
+    }
+    Ok(())
+}
+
+pub fn handle_command_agent(
+    action: AgentCommand,
+    password_fd: Option<i32>,
+) -> Result<(), VaultError> {
+    match action {
+        AgentCommand::Start { extension } => {
+            let token = extension.then(crate::extension_server::generate_token);
+            crate::agent::spawn_background(token.clone())?;
+            println!("Agent started.");
+            if let Some(token) = token {
+                println!(
+                    "🔒 Extension Token (store in extension settings): {}",
+                    token
+                );
+            }
+            println!("Hint: Use 'agent unlock <vault-name>' to unlock a vault.");
+        }
+        AgentCommand::Serve {} => {
+            let token = std::env::var(crate::agent::EXTENSION_TOKEN_ENV).ok();
+            crate::agent::serve(&crate::agent::socket_path()?, token)?;
+        }
+        AgentCommand::Stop {} => {
+            crate::agent::request(AgentAction::Shutdown)?;
+            println!("Agent stopped.");
+        }
+        AgentCommand::Status {} => match crate::agent::request(AgentAction::Status) {
+            Ok(response) => match response.vault {
+                Some(vault) => println!("Agent is running, vault '{}' is unlocked.", vault),
+                None => println!("Agent is running, no vault is unlocked."),
+            },
+            Err(VaultError::AgentNotRunning) => println!("Agent is not running."),
+            Err(e) => return Err(e),
+        },
+        AgentCommand::Unlock {
+            name,
+            timeout,
+            key_file,
+        } => {
+            if !vault_exists(&name)? {
+                return Err(VaultError::VaultDoesNotExist);
+            }
+            // the agent may run in another directory, so it gets an absolute path
+            let key_file = key_file
+                .or_else(crate::oneshot::key_file_path)
+                .map(std::fs::canonicalize)
+                .transpose()?;
+            let master = crate::oneshot::read_master_password(&name, password_fd)?;
+
+            let spinner = spinner();
+            spinner.set_message("Unlocking vault in agent ...");
+            spinner.enable_steady_tick(Duration::from_millis(80));
+            let result = crate::agent::unlock(&name, &master, key_file.as_deref(), timeout);
+            spinner.finish_and_clear();
+            result?;
+
+            println!("Vault '{}' is unlocked in the agent.", name);
+        }
+        AgentCommand::Lock {} => {
+            crate::agent::request(AgentAction::Lock)?;
+            println!("Agent vault locked.");
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_command_clear() {
+    clear_terminal();
+    intro_animation();
+}
+
+pub fn handle_command_quit(force: bool) -> Result<LoopCommand, VaultError> {
+    if force {
+        println!("Quitting RustPass...");
+        return Ok(LoopCommand::Continue);
+    }
+
+    print!("Are you sure you want to quit? (y/n): ");
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    if input.trim().eq_ignore_ascii_case("y") {
+        println!("Quitting RustPass...");
+        // Closing the vault is happening in main.rs to avoid cloning.
+        Ok(LoopCommand::Continue)
+    } else {
+        println!("Cancelled. \n");
+        io::stdout().flush().unwrap();
+        Ok(LoopCommand::Cancel)
+    }
+}
+
+fn copy_to_clipboard(content: &str, duration: u64) -> Result<(), SessionError> {
+    copy_secret(content, duration)
+        .map_err(|_| SessionError::VaultError(VaultError::ClipboardError))?;
+    println!("Password copied to clipboard!");
+    println!("  (Clipboard will be cleared in {} seconds)", &duration);
+    Ok(())
+}
+
+// Copies a secret and clears it again after `duration` seconds. Clipboard managers are asked
+// not to keep it in their history.
+fn copy_secret(secret: &str, duration: u64) -> Result<(), arboard::Error> {
+    let mut clipboard = Clipboard::new()?;
+    set_secret_text(&mut clipboard, secret)?;
+    clear_clipboard_after(secret, duration);
+    Ok(())
+}
+
+// Linux: sets the x-kde-passwordManagerHint type for X11 and Wayland (data control) clipboard managers
+#[cfg(target_os = "linux")]
+fn set_secret_text(clipboard: &mut Clipboard, secret: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtLinux;
+    clipboard.set().exclude_from_history().text(secret)
+}
+
+#[cfg(target_os = "windows")]
+fn set_secret_text(clipboard: &mut Clipboard, secret: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtWindows;
+    clipboard.set().exclude_from_history().text(secret)
+}
+
+#[cfg(target_os = "macos")]
+fn set_secret_text(clipboard: &mut Clipboard, secret: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtApple;
+    clipboard.set().exclude_from_history().text(secret)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+fn set_secret_text(clipboard: &mut Clipboard, secret: &str) -> Result<(), arboard::Error> {
+    clipboard.set_text(secret)
+}
+
+// None = input skipped, Some(None) = 'clear' (only if allowed), Some(Some(_)) = new secret.
+// The secret is read without echo, like a password.
+fn read_totp(prompt: &str, allow_clear: bool) -> Result<Option<Option<Totp>>, SessionError> {
+    loop {
+        print!("{}", prompt);
+        stdout().flush().unwrap();
+        let input = rpassword::read_password()?;
+        let trimmed = input.trim();
+
+        if trimmed.is_empty() {
+            return Ok(None);
+        } else if allow_clear && trimmed == "clear" {
+            return Ok(Some(None));
+        } else if trimmed == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+
+        match Totp::parse(trimmed) {
+            Ok(totp) => return Ok(Some(Some(totp))),
+            Err(e) => println!("Error: {} Try again or press Enter to skip.", e),
+        }
+    }
+}
+
+// value parser for dates given as YYYY-MM-DD
+fn parse_date_arg(arg: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(arg.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("'{}' is not a date in the format YYYY-MM-DD", arg))
+}
+
+// value parser for `add --field [TYPE:]NAME=VALUE`, the type defaults to text
+fn parse_field_arg(arg: &str) -> Result<CustomField, String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected [TYPE:]NAME=VALUE".to_string())?;
+    let (field_type, name) = match key.split_once(':') {
+        Some((prefix, name)) => match <FieldType as clap::ValueEnum>::from_str(prefix, true) {
+            Ok(field_type) => (field_type, name),
+            // the colon is part of the name, e.g. "Question: first pet"
+            Err(_) => (FieldType::Text, key),
+        },
+        None => (FieldType::Text, key),
+    };
+    CustomField::new(name.to_string(), field_type, value.to_string()).map_err(|e| e.to_string())
+}
+
+// Asks for one more custom field while adding an entry, None once the user presses Enter.
+fn read_new_field(entry: &Entry) -> Result<Option<CustomField>, SessionError> {
+    loop {
+        print!("Custom field name, e.g. PIN (enter skips it): ");
+        stdout().flush().unwrap();
+        let mut input_name = String::new();
+        io::stdin().read_line(&mut input_name)?;
+        let name = input_name.trim().to_string();
+
+        if name.is_empty() {
+            return Ok(None);
+        } else if name == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        } else if entry.get_field(&name).is_some() {
+            println!("Error: the field '{}' already exists!", name);
+            continue;
+        }
+
+        let field_type = read_field_type()?;
+        let field = read_field_value(&name, field_type, None)?;
+        return Ok(field.flatten());
+    }
+}
+
+fn read_field_type() -> Result<FieldType, SessionError> {
+    loop {
+        print!("Type [text, hidden, url, email, date, multiline] (enter for text): ");
+        stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let trimmed = input.trim();
+
+        if trimmed.is_empty() {
+            return Ok(FieldType::Text);
+        } else if trimmed == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+        match <FieldType as clap::ValueEnum>::from_str(trimmed, true) {
+            Ok(field_type) => return Ok(field_type),
+            Err(_) => println!("Error: unknown type '{}'.", trimmed),
+        }
+    }
+}
+
+// Reads the value of a field. Hidden values are read without echo, multiline values until an empty line.
+// Returns None if the user pressed Enter to keep the current value, Some(None) on 'clear'
+// (only offered for existing fields).
+fn read_field_value(
+    name: &str,
+    field_type: FieldType,
+    current: Option<&CustomField>,
+) -> Result<Option<Option<CustomField>>, SessionError> {
+    loop {
+        let value = match field_type {
+            FieldType::Hidden => {
+                print!("Value of '{}' (hidden): ", name);
+                stdout().flush().unwrap();
+                rpassword::read_password()?.trim().to_string()
+            }
+            FieldType::Multiline => {
+                println!("Value of '{}' (an empty line finishes it): ", name);
+                let mut lines = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    if io::stdin().read_line(&mut line)? == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                    lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+                }
+                lines.join("\n")
+            }
+            _ => {
+                print!("Value of '{}' ({}): ", name, field_type);
+                stdout().flush().unwrap();
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                input.trim().to_string()
+            }
+        };
+
+        if value.is_empty() {
+            return Ok(None);
+        } else if value == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        } else if current.is_some() && value == "clear" {
+            return Ok(Some(None));
+        }
+
+        match CustomField::new(name.to_string(), field_type, value) {
+            Ok(field) => return Ok(Some(Some(field))),
+            Err(e) => println!("Error: {} Try again or press Enter to skip.", e),
+        }
+    }
+}
+
+// Copy or move, both vaults are saved by Sessions::transfer_entries
+fn transfer_entries(
+    sessions: &mut Sessions,
+    names: Vec<String>,
+    to: String,
+    on_conflict: ConflictPolicy,
+    remove: bool,
+) -> Result<(), SessionError> {
+    let spinner = spinner();
+    spinner.set_message("Saving vaults ...");
+    spinner.enable_steady_tick(Duration::from_millis(80));
+    let result = sessions.transfer_entries(&names, &to, on_conflict, remove);
+    spinner.finish_and_clear();
+    let plan = result?;
+
+    let transferred = plan
+        .iter()
+        .filter(|planned| planned.action != ImportAction::Skip)
+        .count();
+    println!();
+    print_import_plan(&plan);
+    println!(
+        "\n{} of {} entries {} to '{}'.",
+        transferred,
+        plan.len(),
+        if remove { "moved" } else { "copied" },
+        to
+    );
+    Ok(())
+}
+
+fn print_merge_plan(plan: &MergePlan) {
+    let count = |wanted: fn(&MergeItem) -> bool| plan.items.iter().filter(|i| wanted(i)).count();
+    println!(
+        "  Unchanged:   {}",
+        count(|i| matches!(i, MergeItem::Keep(_)))
+    );
+    println!(
+        "  Updated:     {}",
+        count(|i| matches!(i, MergeItem::Update { .. }))
+    );
+    println!(
+        "  New:         {}",
+        count(|i| matches!(i, MergeItem::Add(_)))
+    );
+    println!(
+        "  Deleted:     {}",
+        count(|i| matches!(i, MergeItem::Delete(_)))
+    );
+    println!(
+        "  Conflicts:   {}",
+        count(|i| matches!(i, MergeItem::Conflict(_)))
+    );
+
+    for item in &plan.items {
+        match item {
+            MergeItem::Keep(_) | MergeItem::Conflict(_) => {}
+            MergeItem::Update { entry, changed } => println!(
+                "  {} ({} taken from the other copy)",
+                entry.get_entry_name(),
+                changed.join(", ")
+            ),
+            MergeItem::Add(entry) => {
+                println!("  {} (new in the other copy)", entry.get_entry_name())
+            }
+            MergeItem::Delete(entry) => {
+                println!("  {} (deleted in the other copy)", entry.get_entry_name())
+            }
+        }
+    }
+}
+
+fn print_merge_conflict(conflict: &MergeConflict, number: usize, total: usize, show: bool) {
+    println!("\nConflict {}/{}: '{}'", number, total, conflict.name);
+    let modified = |entry: &Entry| match entry.modified() {
+        Some(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "unknown".to_string(),
+    };
+    match (&conflict.keep_local, &conflict.keep_other) {
+        (Some(local), Some(other)) => {
+            for value in &conflict.values {
+                println!("  {}:", value);
+                println!("    [h]ere:  {}", merge_value(local, value, show));
+                println!("    [o]ther: {}", merge_value(other, value, show));
+            }
+            println!(
+                "  Modified here {}, in the other copy {}",
+                modified(local),
+                modified(other)
+            );
+        }
+        (Some(local), None) => println!(
+            "  Deleted in the other copy, but changed [h]ere afterwards ({}).",
+            modified(local)
+        ),
+        (None, Some(other)) => println!(
+            "  Deleted [h]ere, but changed in the [o]ther copy afterwards ({}).",
+            modified(other)
+        ),
+        (None, None) => {}
+    }
+}
+
+// One of the values listed by EntryVersion::changed_values or Entry::changed_metadata,
+// secrets only with --show
+fn merge_value(entry: &Entry, value: &str, show: bool) -> String {
+    let shown = |text: Option<&str>| text.unwrap_or("--EMPTY--").to_string();
+    match value {
+        "username" => shown(entry.username()),
+        "url" => shown(entry.url()),
+        "notes" => shown(entry.get_notes().as_deref()),
+        "password" if show => shown(entry.password()),
+        "password" => "*****".to_string(),
+        "fields" => entry
+            .get_fields()
+            .iter()
+            .map(|field| {
+                let value = if field.is_hidden() && !show {
+                    "*****"
+                } else {
+                    field.value()
+                };
+                format!("{}={}", field.name(), value)
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        "name" => entry.get_entry_name().clone(),
+        "folder" => shown(entry.get_folder()),
+        "tags" if entry.get_tags().is_empty() => "--EMPTY--".to_string(),
+        "tags" => entry.get_tags().join(", "),
+        "totp" => match entry.get_totp() {
+            Some(totp) if show => totp.to_uri(entry.get_entry_name()),
+            Some(_) => "*****".to_string(),
+            None => "--EMPTY--".to_string(),
+        },
+        "expires" => entry
+            .expires()
+            .map(|day| day.to_string())
+            .unwrap_or_else(|| "--EMPTY--".to_string()),
+        _ => String::new(),
+    }
 }
 
-pub fn handle_command_quit(force: bool) -> Result<LoopCommand, VaultError> {
-    if force {
-        println!("Quitting RustPass...");
-        return Ok(LoopCommand::Continue);
-    }
+fn ask_merge_side(suggested: Side) -> Result<Side, SessionError> {
+    let default = match suggested {
+        Side::Local => "h",
+        Side::Other => "o",
+    };
+    loop {
+        print!(
+            "Keep which version? (h/o, Enter for '{}', or '{}'): ",
+            default, CANCEL_ARG
+        );
+        stdout().flush().unwrap();
 
-    print!("Are you sure you want to quit? (y/n): ");
-    io::stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        match input.trim() {
+            "" => return Ok(suggested),
+            "h" | "H" => return Ok(Side::Local),
+            "o" | "O" => return Ok(Side::Other),
+            trimmed if trimmed == CANCEL_ARG => {
+                return Err(SessionError::VaultError(VaultError::ActionCancelled));
+            }
+            _ => println!("Invalid choice! Type 'h', 'o' or '{}'.", CANCEL_ARG),
+        }
+    }
+}
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+// Counts per action, then every entry that is not simply added. Passwords are never printed.
+fn print_import_plan(plan: &[PlannedEntry]) {
+    let count = |wanted: fn(&ImportAction) -> bool| {
+        plan.iter()
+            .filter(|planned| wanted(&planned.action))
+            .count()
+    };
+    println!(
+        "  New:         {}",
+        count(|a| matches!(a, ImportAction::Add))
+    );
+    println!(
+        "  Renamed:     {}",
+        count(|a| matches!(a, ImportAction::Rename { .. }))
+    );
+    println!(
+        "  Overwritten: {}",
+        count(|a| matches!(a, ImportAction::Overwrite))
+    );
+    println!(
+        "  Skipped:     {}",
+        count(|a| matches!(a, ImportAction::Skip))
+    );
 
-    if input.trim().eq_ignore_ascii_case("y") {
-        println!("Quitting RustPass...");
-        // Closing the vault is happening in main.rs to avoid cloning.
-        Ok(LoopCommand::Continue)
-    } else {
-        println!("Cancelled. \n");
-        io::stdout().flush().unwrap();
-        Ok(LoopCommand::Cancel)
+    for planned in plan {
+        let name = planned.entry.get_entry_name();
+        match &planned.action {
+            ImportAction::Add => {}
+            ImportAction::Rename { from } => println!("  {} -> {} (name exists)", from, name),
+            ImportAction::Overwrite => println!("  {} (overwrites the existing entry)", name),
+            ImportAction::Skip => println!("  {} (skipped, name exists)", name),
+        }
     }
 }
 
-fn copy_to_clipboard(content: &str) -> Result<(), SessionError> {
-    let mut clipboard =
-        Clipboard::new().map_err(|_| SessionError::VaultError(VaultError::ClipboardError))?;
-    clipboard
-        .set_text(content.to_string())
-        .map_err(|_| SessionError::VaultError(VaultError::ClipboardError))?;
-    println!("Password copied to clipboard!");
+// Versions are numbered from 1 (the most recent one), as expected by 'restore'.
+fn print_history(entry: &Entry, show: bool) {
+    let history = entry.get_history();
+    if history.is_empty() {
+        println!(
+            "There are no earlier versions of '{}'.",
+            entry.get_entry_name()
+        );
+        return;
+    }
 
-    let duration = 30;
-    println!("  (Clipboard will be cleared in {} seconds)", &duration);
+    println!(
+        "\n==== History of: {} ({} versions) ====",
+        entry.get_entry_name(),
+        history.len()
+    );
+    let mut newer = entry.snapshot();
+    for (i, version) in history.iter().enumerate() {
+        println!(
+            "\n[{}] replaced on {} (changed afterwards: {})",
+            i + 1,
+            version
+                .replaced
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            version.changed_values(&newer).join(", ")
+        );
+        println!(
+            "Username: {}",
+            version.username.as_deref().unwrap_or("--EMPTY--")
+        );
+        println!(
+            "URL:      {}",
+            version.url.as_deref().unwrap_or("--EMPTY--")
+        );
+        println!(
+            "Notes:    {}",
+            version.notes.as_deref().unwrap_or("--EMPTY--")
+        );
+        if show {
+            println!(
+                "Password: {}",
+                version.password.as_deref().unwrap_or("--EMPTY--")
+            );
+        } else {
+            println!("Password: *****");
+        }
+        print_fields(&version.fields, show);
+        newer = version.clone();
+    }
+    println!();
+}
 
-    clear_clipboard_after(duration);
-    Ok(())
+fn print_fields(fields: &[CustomField], show: bool) {
+    for field in fields {
+        let label = format!("{} ({})", field.name(), field.field_type());
+        if field.is_hidden() && !show {
+            println!("{}: *****", label);
+        } else if field.field_type() == FieldType::Multiline {
+            println!("{}:", label);
+            for line in field.value().lines() {
+                println!("    {}", line);
+            }
+        } else {
+            println!("{}: {}", label, field.value());
+        }
+    }
 }
 
-fn add_password_to_entry() -> Result<Option<String>, SessionError> {
+// The site policy is the stored policy matching the URL of the entry, it replaces the questions
+// for length and symbols.
+fn add_password_to_entry(
+    site_policy: Option<(String, PasswordPolicy)>,
+    clipboard_timeout: u64,
+) -> Result<Option<String>, SessionError> {
     let mut loop_pw = String::new();
     'input_pw: loop {
         println!("Generate password for entry (y/n): ");
@@ -1272,8 +3745,15 @@ fn add_password_to_entry() -> Result<Option<String>, SessionError> {
         io::stdout().flush().unwrap();
         io::stdin().read_line(&mut input_choice_gen)?;
 
+        if input_choice_gen.trim().eq_ignore_ascii_case("y")
+            && let Some((name, policy)) = &site_policy
+        {
+            loop_pw = show_generated(policy, Some(name), clipboard_timeout)?;
+            break 'input_pw;
+        }
+
         if input_choice_gen.trim().eq_ignore_ascii_case("y") {
-            let length: u32;
+            let length: usize;
 
             'input_length: loop {
                 print!("Enter desired password-length: ");
@@ -1286,7 +3766,7 @@ fn add_password_to_entry() -> Result<Option<String>, SessionError> {
                     return Ok(None);
                 }
 
-                if let Ok(len) = trimmed_input.parse::<u32>() {
+                if let Ok(len) = trimmed_input.parse::<usize>() {
                     length = len;
                     break 'input_length;
                 }
@@ -1296,8 +3776,12 @@ fn add_password_to_entry() -> Result<Option<String>, SessionError> {
             io::stdout().flush().unwrap();
             let mut no_symbols_input = String::new();
             io::stdin().read_line(&mut no_symbols_input)?;
-            let no_symbols: bool = !no_symbols_input.trim().eq_ignore_ascii_case("y");
-            loop_pw = handle_command_generate(length, no_symbols)?;
+            let policy = PasswordPolicy {
+                length,
+                symbols: no_symbols_input.trim().eq_ignore_ascii_case("y"),
+                ..Default::default()
+            };
+            loop_pw = show_generated(&policy, None, clipboard_timeout)?;
             break 'input_pw;
         }
 
@@ -1330,6 +3814,194 @@ fn add_password_to_entry() -> Result<Option<String>, SessionError> {
     }
 }
 
+// Generates a password following the policy, prints it together with its entropy and copies it.
+fn show_generated(
+    policy: &PasswordPolicy,
+    policy_name: Option<&str>,
+    clipboard_timeout: u64,
+) -> Result<String, SessionError> {
+    let generated = generate(policy).map_err(SessionError::VaultError)?;
+
+    println!("\n┌─────────────────────────────────────────┐");
+    println!("│ Generated Password                      │");
+    println!("├─────────────────────────────────────────┤");
+    println!("│ {: <40}│", generated.password);
+    println!("├─────────────────────────────────────────┤");
+
+    let mut lines = vec![format!("Mode: {}", policy.mode)];
+    if policy.mode == GeneratorMode::Passphrase {
+        lines.push(format!("Words: {}", policy.words));
+    } else {
+        lines.push(format!("Length: {} characters", policy.length));
+        lines.push(format!(
+            "Symbols: {}",
+            if policy.symbols { "Yes" } else { "No" }
+        ));
+    }
+    lines.push(format!("Entropy: ~{:.0} bits", generated.entropy_bits));
+    if let Some(name) = policy_name {
+        lines.push(format!("Policy: {}", name));
+    }
+    for line in lines {
+        println!("│ {: <40}│", line);
+    }
+
+    println!("└─────────────────────────────────────────┘\n");
+
+    copy_to_clipboard(&generated.password, clipboard_timeout)?;
+    Ok(generated.password)
+}
+
+fn print_audit_report(report: &AuditReport, options: &AuditOptions) {
+    println!(
+        "\nWeak passwords (zxcvbn score below {}): {}",
+        options.min_score,
+        report.weak.len()
+    );
+    print_table(
+        &["Entry", "Score", "Warning"],
+        report
+            .weak
+            .iter()
+            .map(|weak| {
+                vec![
+                    weak.entry.clone(),
+                    weak.score.to_string(),
+                    weak.warning.clone().unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    );
+
+    println!("\nReused passwords: {}", report.reused.len());
+    print_table(
+        &["Group", "Entries"],
+        report
+            .reused
+            .iter()
+            .enumerate()
+            .map(|(i, group)| vec![(i + 1).to_string(), group.join(", ")])
+            .collect(),
+    );
+
+    println!(
+        "\nPasswords older than {} days: {}",
+        options.max_age_days,
+        report.old.len()
+    );
+    print_table(
+        &["Entry", "Changed", "Days"],
+        report
+            .old
+            .iter()
+            .map(|old| {
+                vec![
+                    old.entry.clone(),
+                    old.changed
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                    old.days.to_string(),
+                ]
+            })
+            .collect(),
+    );
+
+    println!("\nInsecure URLs (http://): {}", report.insecure_urls.len());
+    print_table(
+        &["Entry", "URL"],
+        report
+            .insecure_urls
+            .iter()
+            .map(|insecure| vec![insecure.entry.clone(), insecure.url.clone()])
+            .collect(),
+    );
+
+    if let Some(breaches) = &report.breaches {
+        println!(
+            "\nBreached passwords (Pwned Passwords): {}",
+            breaches.breached.len()
+        );
+        print_table(
+            &["Entry", "Seen"],
+            breaches
+                .breached
+                .iter()
+                .map(|breached| vec![breached.entry.clone(), breached.count.to_string()])
+                .collect(),
+        );
+        if !breaches.unchecked.is_empty() {
+            println!(
+                "  Not checked, range file missing: {}",
+                breaches.unchecked.join(", ")
+            );
+        }
+    }
+
+    println!("\nEntries without password: {}", report.no_password.len());
+    if !report.no_password.is_empty() {
+        println!("  {}", report.no_password.join(", "));
+    }
+    println!("\nEntries without URL: {}", report.no_url.len());
+    if !report.no_url.is_empty() {
+        println!("  {}", report.no_url.join(", "));
+    }
+}
+
+// Prints the rows below the headers with every column as wide as its longest value.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    if rows.is_empty() {
+        return;
+    }
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(header.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let print_row = |values: Vec<&str>| {
+        let cells: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect();
+        println!("  {}", cells.join("  ").trim_end());
+    };
+
+    let separators: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    print_row(headers.to_vec());
+    print_row(separators.iter().map(String::as_str).collect());
+    for row in &rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+// Asks twice for the passphrase of an encrypted export, it has to be as strong as a master password
+fn read_export_passphrase() -> Result<SecretString, SessionError> {
+    loop {
+        let input: SecretString =
+            rpassword::prompt_password("Enter a passphrase for the export: ")?.into();
+        if input.expose_secret() == CANCEL_ARG {
+            return Err(SessionError::VaultError(VaultError::ActionCancelled));
+        }
+        if check_password_strength(&input).is_err() {
+            continue;
+        }
+
+        let confirm: SecretString = rpassword::prompt_password("Confirm the passphrase: ")?.into();
+        if input.expose_secret() != confirm.expose_secret() {
+            println!("Passphrases do not match! Try again.");
+            continue;
+        }
+        return Ok(input);
+    }
+}
+
 fn check_password_strength(password: &SecretString) -> Result<(), VaultError> {
     if password.expose_secret().is_empty() {
         println!("The Master-Password may not be empty! Try again.");
@@ -1355,21 +4027,33 @@ fn check_password_strength(password: &SecretString) -> Result<(), VaultError> {
     Ok(())
 }
 
-fn check_vault_name(vault_name: &str) -> Result<(), VaultError> {
-    if vault_name.len() > 64 {
-        return Err(VaultError::InvalidVaultName);
+// Applies the given arguments on top of `base` and runs the benchmark, if requested.
+fn resolve_kdf_settings(base: KdfSettings, args: &KdfArgs) -> Result<KdfSettings, VaultError> {
+    let mut settings = base;
+    if let Some(memory_mib) = args.memory_mib {
+        settings.memory_kib = memory_mib.saturating_mul(1024);
     }
-    if vault_name.is_empty() {
-        return Err(VaultError::InvalidVaultName);
+    if let Some(iterations) = args.iterations {
+        settings.iterations = iterations;
     }
-    if !vault_name
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
-    {
-        return Err(VaultError::InvalidVaultName);
+    if let Some(parallelism) = args.parallelism {
+        settings.parallelism = parallelism;
     }
+    settings.validate()?;
 
-    Ok(())
+    if let Some(target_ms) = args.benchmark {
+        let spinner = spinner();
+        spinner.set_message(format!(
+            "Calibrating key derivation to {} ms ...",
+            target_ms
+        ));
+        spinner.enable_steady_tick(Duration::from_millis(80));
+        let calibrated = settings.calibrate(Duration::from_millis(target_ms));
+        spinner.finish_and_clear();
+        settings = calibrated?;
+        println!("Benchmark picked {} iterations.", settings.iterations);
+    }
+    Ok(settings)
 }
 
 pub fn url_matches(entry_url: &str, target_url: &str) -> bool {
@@ -1402,14 +4086,37 @@ pub fn extract_domain(url: &str) -> String {
     url.to_string()
 }
 
-pub fn clear_clipboard_after(duration: u64) {
-    use arboard::Clipboard;
-    std::thread::spawn(move || {
+/// Clears the clipboard after `duration` seconds, but only if it still holds `secret`.
+// Anything the user copied in the meantime is left alone. Only a hash of the secret is kept until then.
+pub fn clear_clipboard_after(secret: &str, duration: u64) {
+    let hash = Sha256::digest(secret.as_bytes());
+    let handle = std::thread::spawn(move || {
         std::thread::sleep(std::time::Duration::from_secs(duration));
-        if let Ok(mut clip) = Clipboard::new() {
-            let _ = clip.set_text("".to_string());
+        if let Ok(mut clip) = Clipboard::new()
+            && clip
+                .get_text()
+                .is_ok_and(|text| Sha256::digest(text.as_bytes()) == hash)
+        {
+            let _ = clip.clear();
         }
     });
+
+    if let Ok(mut pending) = PENDING_CLIPBOARD_CLEARS.lock() {
+        pending.retain(|h| !h.is_finished());
+        pending.push(handle);
+    }
+}
+
+/// Blocks until every scheduled clipboard clear has run.
+// The REPL never needs this, but a one-shot process would otherwise exit with the secret still copied.
+pub fn wait_for_clipboard_clear() {
+    let handles = match PENDING_CLIPBOARD_CLEARS.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    for handle in handles {
+        let _ = handle.join();
+    }
 }
 
 #[cfg(test)]
@@ -1544,7 +4251,7 @@ mod tests {
     fn test_handle_command_getall_no_vault() {
         let vault_name = "test_vault";
         let session = create_test_session(vault_name);
-        let res = handle_command_getall(&mut Some(session), false);
+        let res = handle_command_getall(&mut Some(session), false, &ListArgs::default());
         assert!(res.is_err());
         match res {
             Err(SessionError::VaultError(VaultError::CouldNotGetEntry)) => {}
@@ -1566,7 +4273,7 @@ mod tests {
         );
         let vault = session.opened_vault.as_mut().unwrap();
         vault.add_entry(entry).unwrap();
-        let res = handle_command_getall(&mut Some(session), false);
+        let res = handle_command_getall(&mut Some(session), false, &ListArgs::default());
         assert!(res.is_ok());
         cleanup_test_vault(vault_name);
     }
@@ -1574,7 +4281,12 @@ mod tests {
     // ================== GENERATE TESTS ==================
     #[test]
     fn test_generate_invalid_length() {
-        let res = handle_command_generate(0, true);
+        let options = GeneratorArgs {
+            length: Some(0),
+            no_symbols: true,
+            ..Default::default()
+        };
+        let res = handle_command_generate(&None, options, None, None);
         assert!(res.is_err());
         match res {
             Err(SessionError::VaultError(VaultError::InvalidLength)) => {}
@@ -1585,19 +4297,28 @@ mod tests {
     #[test]
     fn test_generate_success() {
         let length = 12;
-        let res = handle_command_generate(length, false);
+        let options = GeneratorArgs {
+            length: Some(length),
+            ..Default::default()
+        };
+        let res = handle_command_generate(&None, options, None, None);
         assert!(res.is_ok());
         let password = res.unwrap();
-        assert_eq!(password.len(), length as usize);
+        assert_eq!(password.len(), length);
     }
 
     #[test]
     fn test_generate_correct_characters() {
         let length = 20;
-        let res = handle_command_generate(length, true);
+        let options = GeneratorArgs {
+            length: Some(length),
+            no_symbols: true,
+            ..Default::default()
+        };
+        let res = handle_command_generate(&None, options, None, None);
         assert!(res.is_ok());
         let password = res.unwrap();
-        assert_eq!(password.len(), length as usize);
+        assert_eq!(password.len(), length);
         for c in password.chars() {
             assert!(
                 c.is_ascii_alphanumeric(),
@@ -1612,7 +4333,7 @@ mod tests {
     fn test_delete_entry_not_found() {
         let vault_name = "test_vault_delete_not_found";
         let session = create_test_session(vault_name);
-        let res = handle_command_delete(&mut Some(session), "does_not_exist".to_string());
+        let res = handle_command_delete(&mut Some(session), "does_not_exist".to_string(), None);
         assert!(res.is_err());
         match res {
             Err(SessionError::VaultError(VaultError::EntryNotFound)) => {}
@@ -1643,6 +4364,85 @@ mod tests {
         }
     */
 
+    //Test: move single entries and whole folders, scoped lookups only see their folder
+    #[test]
+    fn test_move_and_folder_scope() {
+        let vault_name = "test_vault_move";
+        let mut session = create_test_session(vault_name);
+        let vault = session.opened_vault.as_mut().unwrap();
+        for (name, folder) in [("db", "work/old/prod"), ("wiki", "work/old"), ("mail", "")] {
+            let mut entry = Entry::new(name.to_string(), None, None, None, None);
+            entry.set_folder(folder);
+            vault.add_entry(entry).unwrap();
+        }
+        let mut opt_session = Some(session);
+
+        let result = handle_command_move(
+            &mut opt_session,
+            "work/old".to_string(),
+            "archive".to_string(),
+            true,
+        );
+        assert!(result.is_ok());
+        let result = handle_command_move(
+            &mut opt_session,
+            "mail".to_string(),
+            "/private/".to_string(),
+            false,
+        );
+        assert!(result.is_ok());
+        let result = handle_command_move(
+            &mut opt_session,
+            "archive".to_string(),
+            "archive/sub".to_string(),
+            true,
+        );
+        assert!(result.is_err());
+
+        let vault = opt_session.as_mut().unwrap().opened_vault.as_mut().unwrap();
+        let folder_of = |vault: &mut Vault, name: &str| {
+            vault
+                .get_entry_by_name(&name.to_string())
+                .unwrap()
+                .get_folder()
+                .map(|f| f.to_string())
+        };
+        assert_eq!(folder_of(vault, "db").as_deref(), Some("archive/old/prod"));
+        assert_eq!(folder_of(vault, "wiki").as_deref(), Some("archive/old"));
+        assert_eq!(folder_of(vault, "mail").as_deref(), Some("private"));
+
+        let result = handle_command_get(
+            &mut opt_session,
+            "db".to_string(),
+            false,
+            ClipboardArgs::default(),
+            Some("private".to_string()),
+        );
+        assert!(matches!(
+            result,
+            Err(SessionError::VaultError(VaultError::EntryNotFound))
+        ));
+        let result = handle_command_get(
+            &mut opt_session,
+            "db".to_string(),
+            false,
+            ClipboardArgs::default(),
+            Some("archive".to_string()),
+        );
+        assert!(result.is_ok());
+        let result = handle_command_delete(
+            &mut opt_session,
+            "db".to_string(),
+            Some("private".to_string()),
+        );
+        assert!(matches!(
+            result,
+            Err(SessionError::VaultError(VaultError::EntryNotFound))
+        ));
+
+        cleanup_test_vault(vault_name);
+    }
+
     // ================== FORMAT OF CUSTOM VAULTERRORS TESTS ==================
     // Testing format of custom VaultErrors
     #[test]
@@ -1667,8 +4467,15 @@ mod tests {
         let notes = Some("original notes".to_string());
         let password = Some("original_password".to_string());
 
-        let result =
-            handle_command_add(&mut opt_session, entry_name, username, url, notes, password);
+        let result = handle_command_add(
+            &mut opt_session,
+            entry_name,
+            username,
+            url,
+            notes,
+            password,
+            EntryExtraArgs::default(),
+        );
 
         assert!(result.is_ok());
 
@@ -1678,6 +4485,47 @@ mod tests {
         cleanup_test_vault(vault_name);
     }
 
+    #[test]
+    fn test_add_entry_with_fields() {
+        let vault_name = "test_vault_add_fields";
+
+        let session = create_test_session(vault_name);
+        let mut opt_session = Some(session);
+
+        let extra = EntryExtraArgs {
+            totp: None,
+            fields: vec![
+                parse_field_arg("hidden:PIN=1234").unwrap(),
+                parse_field_arg("Question: first pet=Rex").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let result = handle_command_add(
+            &mut opt_session,
+            Some("test_entry".to_string()),
+            None,
+            None,
+            None,
+            Some("password".to_string()),
+            extra,
+        );
+        assert!(result.is_ok());
+
+        let vault_ref = opt_session.as_mut().unwrap().opened_vault.as_mut().unwrap();
+        let entry = vault_ref
+            .get_entry_by_name(&"test_entry".to_string())
+            .unwrap();
+        assert!(entry.get_field("PIN").unwrap().is_hidden());
+        assert_eq!(
+            entry.get_field("Question: first pet").unwrap().field_type(),
+            FieldType::Text
+        );
+        assert!(parse_field_arg("date:Expires=tomorrow").is_err());
+        assert!(parse_field_arg("no value").is_err());
+
+        cleanup_test_vault(vault_name);
+    }
+
     #[test]
     fn test_add_entry_with_existing_entry() {
         let vault_name = "test_vault_add";
@@ -1700,14 +4548,22 @@ mod tests {
             url.clone(),
             notes.clone(),
             password.clone(),
+            EntryExtraArgs::default(),
         );
         assert!(first_add.is_ok());
 
         let vault_ref = opt_session.as_ref().unwrap().opened_vault.as_ref().unwrap();
         assert!(vault_ref.entryname_exists("test_entry"));
 
-        let second_add =
-            handle_command_add(&mut opt_session, entry_name, username, url, notes, password);
+        let second_add = handle_command_add(
+            &mut opt_session,
+            entry_name,
+            username,
+            url,
+            notes,
+            password,
+            EntryExtraArgs::default(),
+        );
         assert!(matches!(
             second_add,
             Err(SessionError::VaultError(VaultError::NameExists))
@@ -1716,13 +4572,84 @@ mod tests {
         cleanup_test_vault(vault_name);
     }
 
+    //Test: getall filters and sorts on the entry dates
+    #[test]
+    fn test_select_entries_by_dates() {
+        let mut fresh = Entry::new(
+            "b-fresh".to_string(),
+            None,
+            Some("pw".to_string()),
+            None,
+            None,
+        );
+        fresh.mark_used();
+        let mut expired = Entry::new("a-expired".to_string(), None, None, None, None);
+        expired.set_expiry(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap());
+        let mut future = Entry::new("c-future".to_string(), None, None, None, None);
+        future.set_expiry(NaiveDate::from_ymd_opt(2999, 1, 1).unwrap());
+        let entries = vec![fresh, expired, future];
+        let names = |selected: Vec<&Entry>| -> Vec<String> {
+            selected
+                .iter()
+                .map(|e| e.get_entry_name().clone())
+                .collect()
+        };
+
+        let sorted = select_entries(&entries, &ListArgs::default(), Utc::now());
+        assert_eq!(names(sorted), vec!["a-expired", "b-fresh", "c-future"]);
+
+        let list = ListArgs {
+            sort: EntrySort::Expires,
+            reverse: true,
+            ..Default::default()
+        };
+        let sorted = select_entries(&entries, &list, Utc::now());
+        assert_eq!(names(sorted), vec!["b-fresh", "c-future", "a-expired"]);
+
+        let list = ListArgs {
+            expired: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            names(select_entries(&entries, &list, Utc::now())),
+            vec!["a-expired"]
+        );
+
+        // never used entries count as unused, the password of b-fresh is only old a year from now
+        let list = ListArgs {
+            unused_for: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(select_entries(&entries, &list, Utc::now())),
+            vec!["a-expired", "c-future"]
+        );
+        let list = ListArgs {
+            password_older_than: Some(365),
+            ..Default::default()
+        };
+        let in_a_year = Utc::now() + TimeDelta::days(366);
+        assert_eq!(select_entries(&entries, &list, in_a_year).len(), 3);
+        let list = ListArgs {
+            modified_within: Some(1),
+            ..Default::default()
+        };
+        assert!(select_entries(&entries, &list, in_a_year).is_empty());
+    }
+
     // ================== GET TESTS ==================
 
     //Test: no session active -> error
     #[test]
     fn test_get_entry_no_session() {
         let mut opt_session = None;
-        let result = handle_command_get(&mut opt_session, "unimportant".to_string(), false, false);
+        let result = handle_command_get(
+            &mut opt_session,
+            "unimportant".to_string(),
+            false,
+            ClipboardArgs::default(),
+            None,
+        );
         assert!(matches!(result, Err(SessionError::SessionInactive)));
     }
 
@@ -1741,14 +4668,27 @@ mod tests {
         let notes = Some("original notes".to_string());
         let password = Some("original_password".to_string());
 
-        let add_entry =
-            handle_command_add(&mut opt_session, entry_name, username, url, notes, password);
+        let add_entry = handle_command_add(
+            &mut opt_session,
+            entry_name,
+            username,
+            url,
+            notes,
+            password,
+            EntryExtraArgs::default(),
+        );
         assert!(add_entry.is_ok());
 
         let vault_ref = opt_session.as_ref().unwrap().opened_vault.as_ref().unwrap();
         assert!(vault_ref.entryname_exists("test_entry"));
 
-        let result = handle_command_get(&mut opt_session, "test_entry".to_string(), false, false);
+        let result = handle_command_get(
+            &mut opt_session,
+            "test_entry".to_string(),
+            false,
+            ClipboardArgs::default(),
+            None,
+        );
         assert!(result.is_ok());
 
         cleanup_test_vault(vault_name);
@@ -1762,7 +4702,13 @@ mod tests {
         let session = create_test_session(vault_name);
         let mut opt_session = Some(session);
 
-        let result = handle_command_get(&mut opt_session, "nonexistent".to_string(), false, false);
+        let result = handle_command_get(
+            &mut opt_session,
+            "nonexistent".to_string(),
+            false,
+            ClipboardArgs::default(),
+            None,
+        );
         assert!(matches!(
             result,
             Err(SessionError::VaultError(VaultError::EntryNotFound))
@@ -0,0 +1,546 @@
+/*what belongs here:
+- The user config file (`config.toml`) with the defaults that are not stored in a vault:
+  session timeout, clipboard timeout, address of the extension server, vault directory and
+  the password policy `generate` starts from
+- Layering and validation of these values, `config get/set/list`
+
+Values are layered, later ones win:
+  built-in defaults < config file < environment variables < command line options
+The file is `$PW_CONFIG` or `config.toml` in the config dir of the app, e.g.
+~/.config/password_manager/config.toml on Linux. Settings of a vault (e.g. its clipboard
+timeout) still take precedence over the config.
+
+Portable mode (a file named `portable` next to the binary, or `$PW_PORTABLE`) keeps the
+config file and the vaults next to the binary instead, e.g. on a USB stick.
+
+  session_timeout = 600
+  vault_dir = "/mnt/usb/vaults"
+
+  [generator]
+  mode = "passphrase"
+  words = 7
+*/
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use toml::{Table, Value};
+
+use crate::errors::VaultError;
+use crate::generator::{PasswordPolicy, generate};
+use crate::vault_file_manager::write_atomically;
+
+pub const CONFIG_ENV: &str = "PW_CONFIG";
+pub const VAULT_DIR_ENV: &str = "PW_VAULT_DIR";
+pub const PORTABLE_ENV: &str = "PW_PORTABLE";
+
+// marker file next to the binary that turns on portable mode
+const PORTABLE_MARKER: &str = "portable";
+
+pub const DEFAULT_SESSION_TIMEOUT: u64 = 300;
+pub const DEFAULT_CLIPBOARD_TIMEOUT: u64 = 30;
+pub const DEFAULT_EXTENSION_ADDRESS: &str = "127.0.0.1:9123";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Number,
+    Switch,
+    Text,
+    // several paths, separated like in $PATH
+    Paths,
+}
+
+// every key of the file, `generator.*` are the fields of PasswordPolicy
+const KEYS: [(&str, Kind); 17] = [
+    ("session_timeout", Kind::Number),
+    ("clipboard_timeout", Kind::Number),
+    ("extension_address", Kind::Text),
+    ("vault_dir", Kind::Text),
+    ("vault_dirs", Kind::Paths),
+    ("generator.mode", Kind::Text),
+    ("generator.length", Kind::Number),
+    ("generator.symbols", Kind::Switch),
+    ("generator.min_lowercase", Kind::Number),
+    ("generator.min_uppercase", Kind::Number),
+    ("generator.min_digits", Kind::Number),
+    ("generator.min_symbols", Kind::Number),
+    ("generator.exclude_ambiguous", Kind::Switch),
+    ("generator.charset", Kind::Text),
+    ("generator.words", Kind::Number),
+    ("generator.separator", Kind::Text),
+    ("generator.capitalization", Kind::Text),
+];
+
+// environment variables overriding a single key
+const ENV_OVERRIDES: [(&str, &str); 4] = [
+    ("session_timeout", "PW_SESSION_TIMEOUT"),
+    ("clipboard_timeout", "PW_CLIPBOARD_TIMEOUT"),
+    ("extension_address", "PW_EXTENSION_ADDRESS"),
+    ("vault_dir", VAULT_DIR_ENV),
+];
+
+// the config of this process and the command line options it was loaded with, see init
+static LOADED: Mutex<Option<(ConfigOverrides, Config)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Seconds of inactivity until an open vault is locked
+    pub session_timeout: u64,
+    // Seconds until a copied secret is cleared, for vaults without a clipboard timeout of their own
+    pub clipboard_timeout: u64,
+    // Where the extension server listens, only loopback addresses are allowed
+    pub extension_address: String,
+    // Directory of the vaults, the data dir of the app (or next to the binary) if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault_dir: Option<PathBuf>,
+    // More directories with vaults, searched after vault_dir
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vault_dirs: Vec<PathBuf>,
+    // Policy `generate` starts from when no named policy is given
+    pub generator: PasswordPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
+            clipboard_timeout: DEFAULT_CLIPBOARD_TIMEOUT,
+            extension_address: DEFAULT_EXTENSION_ADDRESS.to_string(),
+            vault_dir: None,
+            vault_dirs: Vec::new(),
+            generator: PasswordPolicy::default(),
+        }
+    }
+}
+
+/// Global options of the command line, they take precedence over the file and the environment.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub config_file: Option<PathBuf>,
+    pub vault_dir: Option<PathBuf>,
+}
+
+/// One line of `config list`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigValue {
+    pub key: String,
+    pub value: Option<String>,
+    // "default", "config file", "$PW_..." or "command line"
+    pub source: String,
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Loads the config for this process, invalid values are an error instead of being ignored.
+pub fn init(overrides: ConfigOverrides) -> Result<Config, VaultError> {
+    let config = load(&overrides)?;
+    *LOADED.lock().unwrap_or_else(|e| e.into_inner()) = Some((overrides, config.clone()));
+    Ok(config)
+}
+
+/// The config loaded by `init`, the built-in defaults if it was never called (e.g. in tests).
+pub fn current() -> Config {
+    LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|(_, config)| config.clone())
+        .unwrap_or_default()
+}
+
+/// The command line options `init` was called with.
+pub fn overrides() -> ConfigOverrides {
+    LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|(overrides, _)| overrides.clone())
+        .unwrap_or_default()
+}
+
+/// Reads the file and applies the environment and `overrides` on top of it.
+pub fn load(overrides: &ConfigOverrides) -> Result<Config, VaultError> {
+    let (table, _) = layered(overrides)?;
+    to_config(table)
+}
+
+/// `--config`, `$PW_CONFIG` or `config.toml` in the config dir of the app (next to the binary
+/// in portable mode).
+pub fn config_path(overrides: &ConfigOverrides) -> Result<PathBuf, VaultError> {
+    if let Some(path) = overrides
+        .config_file
+        .clone()
+        .or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from))
+    {
+        return Ok(path);
+    }
+    if let Some(dir) = portable_dir() {
+        return Ok(dir.join("config.toml"));
+    }
+    let proj_dirs = ProjectDirs::from("", "", "password_manager").ok_or_else(|| {
+        VaultError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Home directory not found",
+        ))
+    })?;
+    Ok(proj_dirs.config_dir().join("config.toml"))
+}
+
+/// The directory of the binary if `pw` runs in portable mode.
+pub fn portable_dir() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    let enabled = match std::env::var(PORTABLE_ENV) {
+        Ok(value) => !matches!(value.trim(), "" | "0" | "false"),
+        Err(_) => exe_dir.join(PORTABLE_MARKER).is_file(),
+    };
+    enabled.then_some(exe_dir)
+}
+
+/// Every key with its effective value and the layer it comes from.
+pub fn list(overrides: &ConfigOverrides) -> Result<Vec<ConfigValue>, VaultError> {
+    let (table, sources) = layered(overrides)?;
+    let values = flatten(&Table::try_from(to_config(table)?).map_err(invalid)?);
+    Ok(KEYS
+        .iter()
+        .map(|(key, _)| ConfigValue {
+            key: key.to_string(),
+            value: values.get(*key).map(display_value),
+            source: sources
+                .get(*key)
+                .cloned()
+                .unwrap_or_else(|| "default".to_string()),
+        })
+        .collect())
+}
+
+/// The effective value of `key`, None if it is not set (e.g. `vault_dir`).
+pub fn get(overrides: &ConfigOverrides, key: &str) -> Result<Option<String>, VaultError> {
+    kind_of(key)?;
+    Ok(list(overrides)?
+        .into_iter()
+        .find(|value| value.key == key)
+        .and_then(|value| value.value))
+}
+
+/// Writes `key = value` to the config file, after checking that the result is valid.
+/// Reloads the config of this process if `init` was called.
+pub fn set(overrides: &ConfigOverrides, key: &str, value: &str) -> Result<(), VaultError> {
+    let path = config_path(overrides)?;
+    let mut table = read_file(&path)?;
+    let value = parse_value(key, kind_of(key)?, value).map_err(VaultError::InvalidConfig)?;
+    set_key(&mut table, key, value);
+
+    // an invalid file would make every later command fail, so it is checked before writing
+    to_config(table.clone())?;
+    let contents = toml::to_string_pretty(&table).map_err(invalid)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomically(&path, contents.as_bytes())?;
+
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((overrides, config)) = loaded.as_mut() {
+        *config = load(overrides)?;
+    }
+    Ok(())
+}
+
+/// The environment variable or command line option that hides the value of `key` in the file.
+pub fn overridden_by(overrides: &ConfigOverrides, key: &str) -> Option<String> {
+    if key == "vault_dir" && overrides.vault_dir.is_some() {
+        return Some("--vault-dir".to_string());
+    }
+    ENV_OVERRIDES
+        .iter()
+        .find(|(name, env)| *name == key && std::env::var_os(env).is_some())
+        .map(|(_, env)| format!("${}", env))
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+// The file with the environment and the command line applied, and where each key was set
+fn layered(overrides: &ConfigOverrides) -> Result<(Table, BTreeMap<String, String>), VaultError> {
+    let path = config_path(overrides)?;
+    let mut table = read_file(&path)?;
+    let mut sources: BTreeMap<String, String> = flatten(&table)
+        .into_keys()
+        .map(|key| (key, "config file".to_string()))
+        .collect();
+
+    for (key, env) in ENV_OVERRIDES {
+        if let Ok(raw) = std::env::var(env) {
+            let value = parse_value(key, kind_of(key)?, &raw)
+                .map_err(|e| VaultError::InvalidConfig(format!("${}: {}", env, e)))?;
+            set_key(&mut table, key, value);
+            sources.insert(key.to_string(), format!("${}", env));
+        }
+    }
+    if let Some(dir) = &overrides.vault_dir {
+        set_key(
+            &mut table,
+            "vault_dir",
+            Value::String(dir.to_string_lossy().into_owned()),
+        );
+        sources.insert("vault_dir".to_string(), "command line".to_string());
+    }
+    Ok((table, sources))
+}
+
+// A missing file is an empty one, unknown keys are rejected so that typos do not go unnoticed
+fn read_file(path: &Path) -> Result<Table, VaultError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Table::new()),
+        Err(e) => return Err(VaultError::IoError(e)),
+    };
+    let table: Table = toml::from_str(&contents)
+        .map_err(|e| invalid(format!("{}: {}", path.display(), e.message())))?;
+    if let Some(key) = flatten(&table)
+        .into_keys()
+        .find(|key| kind_of(key).is_err())
+    {
+        return Err(VaultError::InvalidConfig(format!(
+            "{}: unknown key '{}'",
+            path.display(),
+            key
+        )));
+    }
+    Ok(table)
+}
+
+fn to_config(table: Table) -> Result<Config, VaultError> {
+    let config: Config = table.try_into().map_err(invalid)?;
+    validate(&config)?;
+    Ok(config)
+}
+
+fn validate(config: &Config) -> Result<(), VaultError> {
+    let invalid = |e: String| Err(VaultError::InvalidConfig(e));
+
+    if config.session_timeout == 0 {
+        return invalid("session_timeout must be at least 1 second".to_string());
+    }
+    if config.clipboard_timeout == 0 {
+        return invalid("clipboard_timeout must be at least 1 second".to_string());
+    }
+    match config.extension_address.parse::<SocketAddr>() {
+        Ok(address) if address.ip().is_loopback() => {}
+        Ok(_) => {
+            return invalid(format!(
+                "extension_address {} is not a loopback address, the extension server would be reachable from the network",
+                config.extension_address
+            ));
+        }
+        Err(_) => {
+            return invalid(format!(
+                "extension_address '{}' is not an address like {}",
+                config.extension_address, DEFAULT_EXTENSION_ADDRESS
+            ));
+        }
+    }
+    let dirs = config.vault_dir.iter().map(|dir| ("vault_dir", dir));
+    for (key, dir) in dirs.chain(config.vault_dirs.iter().map(|dir| ("vault_dirs", dir))) {
+        if !dir.is_absolute() {
+            return invalid(format!("{} {:?} is not an absolute path", key, dir));
+        }
+        if dir.exists() && !dir.is_dir() {
+            return invalid(format!("{} {:?} is not a directory", key, dir));
+        }
+    }
+    // a policy that cannot generate anything is rejected right away
+    if let Err(e) = generate(&config.generator) {
+        return invalid(format!("generator: {}", e));
+    }
+    Ok(())
+}
+
+fn kind_of(key: &str) -> Result<Kind, VaultError> {
+    KEYS.iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| {
+            VaultError::InvalidConfig(format!(
+                "unknown key '{}', see 'config list' for all keys",
+                key
+            ))
+        })
+}
+
+// Values on the command line and in the environment are plain text, the key decides the type
+fn parse_value(key: &str, kind: Kind, raw: &str) -> Result<Value, String> {
+    let raw = raw.trim();
+    match kind {
+        Kind::Number => raw
+            .parse::<u32>()
+            .map(|n| Value::Integer(n.into()))
+            .map_err(|_| format!("{} must be a number, not '{}'", key, raw)),
+        Kind::Switch => raw
+            .parse::<bool>()
+            .map(Value::Boolean)
+            .map_err(|_| format!("{} must be true or false, not '{}'", key, raw)),
+        Kind::Text => Ok(Value::String(raw.to_string())),
+        Kind::Paths => Ok(Value::Array(
+            std::env::split_paths(raw)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| Value::String(path.to_string_lossy().into_owned()))
+                .collect(),
+        )),
+    }
+}
+
+// `generator.length` is the key `length` of the table `generator`
+fn set_key(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, rest)) => {
+            let entry = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(inner) = entry {
+                set_key(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn flatten(table: &Table) -> BTreeMap<String, Value> {
+    let mut values = BTreeMap::new();
+    for (key, value) in table {
+        match value {
+            Value::Table(inner) => {
+                for (inner_key, inner_value) in flatten(inner) {
+                    values.insert(format!("{}.{}", key, inner_key), inner_value);
+                }
+            }
+            _ => {
+                values.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    values
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        // the way it is written on the command line, e.g. /a:/b
+        Value::Array(values) => {
+            let paths = values.iter().map(display_value).collect::<Vec<_>>();
+            std::env::join_paths(paths)
+                .map(|joined| joined.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }
+        other => other.to_string(),
+    }
+}
+
+// toml puts the key on a line of its own, the error is shown on one line
+fn invalid<E: std::fmt::Display>(e: E) -> VaultError {
+    VaultError::InvalidConfig(e.to_string().trim().replace('\n', " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GeneratorMode;
+
+    fn overrides_for(path: &Path) -> ConfigOverrides {
+        ConfigOverrides {
+            config_file: Some(path.to_path_buf()),
+            vault_dir: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_file_gives_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = load(&overrides_for(&dir.path().join("config.toml"))).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_set_writes_typed_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let overrides = overrides_for(&dir.path().join("config.toml"));
+
+        set(&overrides, "clipboard_timeout", "12").unwrap();
+        set(&overrides, "generator.mode", "passphrase").unwrap();
+        set(&overrides, "generator.words", "8").unwrap();
+        let dirs = std::env::join_paths([dir.path().join("a"), dir.path().join("b")]).unwrap();
+        set(&overrides, "vault_dirs", dirs.to_str().unwrap()).unwrap();
+
+        let config = load(&overrides).unwrap();
+        assert_eq!(config.clipboard_timeout, 12);
+        assert_eq!(config.generator.mode, GeneratorMode::Passphrase);
+        assert_eq!(config.generator.words, 8);
+        assert_eq!(
+            config.vault_dirs,
+            vec![dir.path().join("a"), dir.path().join("b")]
+        );
+        assert_eq!(
+            get(&overrides, "generator.mode").unwrap().as_deref(),
+            Some("passphrase")
+        );
+        let sources = list(&overrides).unwrap();
+        let timeout = sources
+            .iter()
+            .find(|value| value.key == "clipboard_timeout")
+            .unwrap();
+        assert_eq!(timeout.source, "config file");
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let overrides = overrides_for(&path);
+
+        assert!(set(&overrides, "session_timeout", "soon").is_err());
+        assert!(set(&overrides, "session_timeout", "0").is_err());
+        assert!(set(&overrides, "extension_address", "0.0.0.0:9123").is_err());
+        assert!(set(&overrides, "vault_dir", "relative/dir").is_err());
+        assert!(set(&overrides, "generator.length", "1").is_err());
+        assert!(set(&overrides, "no_such_key", "1").is_err());
+        // nothing invalid was written
+        assert!(!path.exists());
+
+        fs::write(&path, "session_timeout = 60\nsesion_timeout = 60\n").unwrap();
+        assert!(matches!(
+            load(&overrides),
+            Err(VaultError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_command_line_overrides_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let other = tempfile::tempdir().unwrap();
+        fs::write(&path, format!("vault_dir = {:?}\n", other.path())).unwrap();
+        let overrides = ConfigOverrides {
+            config_file: Some(path),
+            vault_dir: Some(dir.path().to_path_buf()),
+        };
+
+        let config = load(&overrides).unwrap();
+        assert_eq!(config.vault_dir.as_deref(), Some(dir.path()));
+        assert_eq!(
+            overridden_by(&overrides, "vault_dir").as_deref(),
+            Some("--vault-dir")
+        );
+    }
+}
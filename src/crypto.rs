@@ -8,8 +8,9 @@ Password exists only during encrypt/decrypt calls
 File tampering is detected by authentication failure
 */
 
-use enc_file::{AeadAlg, EncryptOptions, decrypt_bytes, encrypt_bytes};
+use enc_file::{AeadAlg, EncFileError, EncryptOptions, KdfParams, decrypt_bytes, encrypt_bytes};
 use secrecy::SecretString;
+use std::time::{Duration, Instant};
 
 use crate::errors::CryptoError;
 
@@ -17,25 +18,46 @@ use crate::errors::CryptoError;
 // Public functions
 //----------------------------------------------------------------------------
 
-pub fn encrypt_vault(password: &SecretString, vault_json: String) -> Result<Vec<u8>, CryptoError> {
-    let encrypted_vault = encrypt_string(password, vault_json.as_bytes())
+pub fn encrypt_vault(
+    password: &SecretString,
+    vault_json: String,
+    kdf_params: KdfParams,
+) -> Result<Vec<u8>, CryptoError> {
+    let encrypted_vault = encrypt_string(password, vault_json.as_bytes(), kdf_params)
         .map_err(|_| CryptoError::CouldNotEncrypt)?;
     Ok(encrypted_vault)
 }
 
 pub fn decrypt_vault(pw: SecretString, msg: &[u8]) -> Result<String, CryptoError> {
-    let pt = decrypt_bytes(msg, pw).map_err(|_| CryptoError::CouldNotDecrypt)?;
+    // A failed authentication means wrong password (or tampering), anything else a malformed file
+    let pt = decrypt_bytes(msg, pw).map_err(|e| match e {
+        EncFileError::Crypto => CryptoError::CouldNotDecrypt,
+        _ => CryptoError::InvalidFormat,
+    })?;
     let result_string = str::from_utf8(&pt).map_err(|_| CryptoError::CouldNotDecrypt)?;
     Ok(result_string.into())
 }
 
+/// Measures how long one key derivation with `kdf_params` takes on this machine.
+pub fn measure_kdf(kdf_params: KdfParams) -> Result<Duration, CryptoError> {
+    let start = Instant::now();
+    encrypt_string(&"benchmark".into(), b"", kdf_params)
+        .map_err(|_| CryptoError::CouldNotEncrypt)?;
+    Ok(start.elapsed())
+}
+
 //----------------------------------------------------------------------------
 // Internal helper functions (private)
 //----------------------------------------------------------------------------
 
-fn encrypt_string(pw: &SecretString, msg: &[u8]) -> Result<Vec<u8>, enc_file::EncFileError> {
+fn encrypt_string(
+    pw: &SecretString,
+    msg: &[u8],
+    kdf_params: KdfParams,
+) -> Result<Vec<u8>, EncFileError> {
     let opts = EncryptOptions {
         alg: AeadAlg::XChaCha20Poly1305,
+        kdf_params,
         ..Default::default()
     };
 
@@ -1,11 +1,13 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
 use zxcvbn::ZxcvbnError;
 
 #[derive(Debug)]
 pub enum CryptoError {
     CouldNotEncrypt,
     CouldNotDecrypt,
+    InvalidFormat,
 }
 
 impl fmt::Display for CryptoError {
@@ -13,6 +15,7 @@ impl fmt::Display for CryptoError {
         match self {
             CryptoError::CouldNotEncrypt => write!(f, "COULD NOT ENCRYPT"),
             CryptoError::CouldNotDecrypt => write!(f, "COULD NOT DECRYPT"),
+            CryptoError::InvalidFormat => write!(f, "INVALID CIPHERTEXT FORMAT"),
         }
     }
 }
@@ -50,6 +53,18 @@ pub enum VaultError {
     NoVaultOpen,
     CouldNotOpen,
     VaultDoesNotExist,
+    CorruptVault,
+    UnsupportedFormat(u16),
+    InvalidKdfParameters(String),
+    KeyFileRequired,
+    InvalidKeyFile,
+    KeyFileNotFound(PathBuf),
+    InvalidTotp(String),
+    InvalidField(String),
+    VersionNotFound,
+    InvalidImport(String),
+    InvalidPolicy(String),
+    InvalidConfig(String),
     WeakPassword,
     InvalidVaultName,
     ActionCancelled,
@@ -61,6 +76,8 @@ pub enum VaultError {
     CryptoError(CryptoError),
     ZxcvbnError(zxcvbn::ZxcvbnError),
     ClipboardError,
+    AgentNotRunning,
+    AgentError(String),
 }
 
 impl fmt::Display for VaultError {
@@ -80,6 +97,26 @@ impl fmt::Display for VaultError {
             VaultError::NoVaultOpen => write!(f, "NO VAULT IS OPEN"),
             VaultError::CouldNotOpen => write!(f, "COULD NOT OPEN VAULT"),
             VaultError::VaultDoesNotExist => write!(f, "VAULT DOES NOT EXIST"),
+            VaultError::CorruptVault => write!(f, "VAULT FILE IS CORRUPT"),
+            VaultError::UnsupportedFormat(v) => write!(
+                f,
+                "VAULT FORMAT VERSION {} IS NOT SUPPORTED, PLEASE UPDATE RUSTPASS",
+                v
+            ),
+            VaultError::InvalidKdfParameters(e) => write!(f, "INVALID KDF PARAMETERS: {}", e),
+            VaultError::KeyFileRequired => write!(f, "VAULT REQUIRES A KEY FILE"),
+            VaultError::InvalidKeyFile => {
+                write!(f, "KEY FILE IS INVALID OR DOES NOT BELONG TO THIS VAULT")
+            }
+            VaultError::KeyFileNotFound(path) => {
+                write!(f, "KEY FILE {} DOES NOT EXIST", path.display())
+            }
+            VaultError::InvalidTotp(e) => write!(f, "INVALID TOTP: {}", e),
+            VaultError::InvalidField(e) => write!(f, "INVALID FIELD: {}", e),
+            VaultError::VersionNotFound => write!(f, "VERSION NOT FOUND"),
+            VaultError::InvalidImport(e) => write!(f, "INVALID IMPORT: {}", e),
+            VaultError::InvalidPolicy(e) => write!(f, "INVALID PASSWORD POLICY: {}", e),
+            VaultError::InvalidConfig(e) => write!(f, "INVALID CONFIGURATION: {}", e),
             VaultError::WeakPassword => write!(f, "PASSWORD IS TOO WEAK"),
             VaultError::InvalidVaultName => write!(f, "VAULT NAME IS INVALID"),
             VaultError::ActionCancelled => write!(f, "ACTION CANCELLED"),
@@ -91,6 +128,8 @@ impl fmt::Display for VaultError {
             VaultError::CryptoError(e) => write!(f, "CRYPTO ERROR: {}", e),
             VaultError::ZxcvbnError(e) => write!(f, "ZXCVBN ERROR: {}", e),
             VaultError::ClipboardError => write!(f, "COULD NOT COPY TO CLIPBOARD"),
+            VaultError::AgentNotRunning => write!(f, "AGENT IS NOT RUNNING"),
+            VaultError::AgentError(e) => write!(f, "AGENT ERROR: {}", e),
         }
     }
 }
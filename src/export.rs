@@ -0,0 +1,286 @@
+/*what belongs here:
+- Choosing the entries of an export (by name, tag or URL domain)
+- Writing entries as plaintext JSON or CSV (Bitwarden JSON: bitwarden.rs)
+
+Encrypted exports and KeePass databases are written by vault_file_manager.rs, this module only
+produces the plaintext formats. Those contain every password unencrypted, the caller has to make
+sure the user really wants that.
+*/
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+
+use crate::errors::VaultError;
+use crate::import::in_domain;
+use crate::vault_entry_manager::{Entry, Vault};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    // a vault file protected by a separate export passphrase
+    #[default]
+    Encrypted,
+    // a KeePass database (KDBX 4)
+    Kdbx,
+    Json,
+    // same columns as a Bitwarden CSV export
+    Csv,
+    // the unencrypted JSON export of Bitwarden (see bitwarden.rs)
+    Bitwarden,
+}
+
+/// Entries to export, an entry is exported if it matches any of the given names, tags or domains.
+/// Without any of them the whole vault is exported.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ExportSelection {
+    // Entry to export, can be given more than once
+    #[arg(long = "name")]
+    pub names: Vec<String>,
+
+    // Export the entries with this tag, can be given more than once
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+
+    // Export the entries whose URL belongs to this domain (subdomains included)
+    #[arg(long = "domain")]
+    pub domains: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    vault: &'a str,
+    exported: DateTime<Utc>,
+    entries: &'a [Entry],
+}
+
+const CSV_HEADER: [&str; 11] = [
+    "folder",
+    "favorite",
+    "type",
+    "name",
+    "notes",
+    "fields",
+    "reprompt",
+    "login_uri",
+    "login_username",
+    "login_password",
+    "login_totp",
+];
+
+impl ExportFormat {
+    pub fn is_plaintext(&self) -> bool {
+        matches!(
+            self,
+            ExportFormat::Json | ExportFormat::Csv | ExportFormat::Bitwarden
+        )
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Encrypted => "encrypted",
+            ExportFormat::Kdbx => "KeePass database",
+            ExportFormat::Json => "plaintext JSON",
+            ExportFormat::Csv => "plaintext CSV",
+            ExportFormat::Bitwarden => "plaintext Bitwarden JSON",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ExportSelection {
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.tags.is_empty() && self.domains.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        self.names
+            .iter()
+            .any(|name| entry.get_entry_name().eq_ignore_ascii_case(name))
+            || self.tags.iter().any(|tag| entry.has_tag(tag))
+            || entry
+                .url()
+                .is_some_and(|url| self.domains.iter().any(|domain| in_domain(url, domain)))
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Copy of the vault (name and settings included) with only the selected entries.
+pub fn exported_vault(vault: &Vault, selection: &ExportSelection) -> Vault {
+    let mut selected = vault.clone();
+    selected.entries.retain(|entry| selection.matches(entry));
+    selected
+}
+
+/// All values of the entries, including their history, as pretty printed JSON.
+pub fn to_json(vault: &Vault) -> Result<String, VaultError> {
+    let export = JsonExport {
+        vault: vault.get_name(),
+        exported: Utc::now(),
+        entries: vault.get_entries(),
+    };
+    Ok(serde_json::to_string_pretty(&export)?)
+}
+
+/// Writes the entries in the CSV layout of Bitwarden, which `import` reads back as well.
+/// Tags and the history have no column and are left out.
+pub fn write_csv<W: Write>(writer: W, vault: &Vault) -> Result<(), VaultError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(CSV_HEADER).map_err(csv_error)?;
+
+    for entry in vault.get_entries() {
+        let fields: Vec<String> = entry
+            .get_fields()
+            .iter()
+            .map(|field| format!("{}: {}", field.name(), field.value()))
+            .collect();
+        let totp = entry
+            .get_totp()
+            .as_ref()
+            .map(|totp| totp.to_uri(entry.get_entry_name()));
+
+        writer
+            .write_record([
+                entry.get_folder().unwrap_or_default(),
+                "",
+                "login",
+                entry.get_entry_name(),
+                entry.get_notes().as_deref().unwrap_or_default(),
+                &fields.join("\n"),
+                "0",
+                entry.url().unwrap_or_default(),
+                entry.username().unwrap_or_default(),
+                entry.password().unwrap_or_default(),
+                totp.as_deref().unwrap_or_default(),
+            ])
+            .map_err(csv_error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn csv_error(e: csv::Error) -> VaultError {
+    VaultError::AnyhowError(anyhow::anyhow!("Could not write the CSV export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{ImportFormat, read_csv};
+    use crate::totp::Totp;
+    use crate::vault_entry_manager::{CustomField, FieldType};
+
+    fn vault() -> Vault {
+        let mut vault = Vault::new("export".to_string());
+        let mut github = Entry::new(
+            "GitHub".to_string(),
+            Some("alice".to_string()),
+            Some("secret, \"quoted\"".to_string()),
+            Some("https://login.github.com/session".to_string()),
+            Some("first line\nsecond line".to_string()),
+        );
+        github.set_folder("dev/work");
+        github.add_tag("dev");
+        github.set_totp(Totp::parse("JBSWY3DPEHPK3PXP").unwrap());
+        github.set_field(
+            CustomField::new("PIN".to_string(), FieldType::Hidden, "1234".to_string()).unwrap(),
+        );
+        vault.add_entry(github).unwrap();
+        vault
+            .add_entry(Entry::new(
+                "Not GitHub".to_string(),
+                None,
+                Some("secret2".to_string()),
+                Some("notgithub.com".to_string()),
+                None,
+            ))
+            .unwrap();
+        vault
+            .add_entry(Entry::new(
+                "Mail".to_string(),
+                None,
+                Some("secret3".to_string()),
+                None,
+                None,
+            ))
+            .unwrap();
+        vault
+    }
+
+    fn names(vault: &Vault) -> Vec<&str> {
+        vault
+            .get_entries()
+            .iter()
+            .map(|e| e.get_entry_name().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_selection() {
+        let vault = vault();
+        let all = exported_vault(&vault, &ExportSelection::default());
+        assert_eq!(all.get_entries().len(), 3);
+
+        let by_domain = ExportSelection {
+            domains: vec!["www.github.com".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(names(&exported_vault(&vault, &by_domain)), vec!["GitHub"]);
+
+        let by_name_or_tag = ExportSelection {
+            names: vec!["mail".to_string()],
+            tags: vec!["dev".to_string()],
+            domains: vec![],
+        };
+        assert_eq!(
+            names(&exported_vault(&vault, &by_name_or_tag)),
+            vec!["GitHub", "Mail"]
+        );
+    }
+
+    #[test]
+    fn test_csv_export_can_be_imported() {
+        let vault = vault();
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &vault).unwrap();
+
+        let imported = read_csv(csv.as_slice(), ImportFormat::Auto).unwrap();
+        assert_eq!(imported.format, ImportFormat::Bitwarden);
+        assert!(imported.warnings.is_empty());
+        assert_eq!(imported.entries.len(), 3);
+
+        let github = &imported.entries[0];
+        assert_eq!(github.password(), Some("secret, \"quoted\""));
+        assert_eq!(
+            github.get_notes().as_deref(),
+            Some("first line\nsecond line")
+        );
+        assert_eq!(github.get_folder(), Some("dev/work"));
+        assert_eq!(github.get_field("PIN").unwrap().value(), "1234");
+        assert_eq!(
+            github.get_totp(),
+            &Some(Totp::parse("JBSWY3DPEHPK3PXP").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_json_export_contains_the_entries() {
+        let vault = vault();
+        let json: serde_json::Value = serde_json::from_str(&to_json(&vault).unwrap()).unwrap();
+        assert_eq!(json["vault"], "export");
+        assert_eq!(json["entries"].as_array().unwrap().len(), 3);
+        assert_eq!(json["entries"][2]["password"], "secret3");
+    }
+}
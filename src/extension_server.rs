@@ -1,13 +1,27 @@
-use crate::session::Session;
+use crate::config;
+use crate::session::{Session, SessionHolder};
+use rand::Rng;
 use serde_json::{Value, json};
 use std::sync::{Arc, Mutex};
 use tiny_http::{Request, Response, Server};
 
-// Extension server to handle requests from the web extension
-pub fn run(session: Arc<Mutex<Option<Session>>>, token: String) {
-    let listener = match Server::http("127.0.0.1:9123") {
+// Random 32 hex character token the extension has to send with every request
+pub fn generate_token() -> String {
+    let mut rng = rand::rng();
+    (0..32)
+        .map(|_| {
+            let idx = rng.random_range(0..16);
+            format!("{:x}", idx)
+        })
+        .collect()
+}
+
+// Extension server to handle requests from the web extension, it searches every unlocked vault
+pub fn run<H: SessionHolder>(session: Arc<Mutex<H>>, token: String) {
+    let address = config::current().extension_address;
+    let listener = match Server::http(&address) {
         Ok(server) => {
-            eprintln!("Extension server listening on http://127.0.0.1:9123");
+            eprintln!("Extension server listening on http://{}", address);
             server
         }
         Err(e) => {
@@ -29,9 +43,9 @@ pub fn run(session: Arc<Mutex<Option<Session>>>, token: String) {
     }
 }
 
-fn handle_request(
+fn handle_request<H: SessionHolder>(
     request: Request,
-    session: Arc<Mutex<Option<Session>>>,
+    session: Arc<Mutex<H>>,
     token: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if request.method() != &tiny_http::Method::Post {
@@ -63,10 +77,27 @@ fn handle_request(
 
     let response = match (action, url) {
         (Some("fill"), Some(url)) => match session.lock() {
-            Ok(session_guard) => match session_guard.as_ref() {
-                Some(sess) => match_entries_by_url(sess, url),
-                None => json!({"status": "error", "message": "No session open"}),
-            },
+            Ok(mut session_guard) => {
+                let mut sessions = session_guard.sessions_mut();
+                sessions.retain(|sess| sess.opened_vault.is_some());
+                if sessions.is_empty() {
+                    json!({"status": "error", "message": "No session open"})
+                } else {
+                    match_entries_by_url(&mut sessions, url)
+                }
+            }
+            Err(_) => json!({"status": "error", "message": "Session state unavailable"}),
+        },
+        (Some("totp"), Some(url)) => match session.lock() {
+            Ok(session_guard) => {
+                let mut sessions = session_guard.sessions();
+                sessions.retain(|sess| sess.opened_vault.is_some());
+                if sessions.is_empty() {
+                    json!({"status": "error", "message": "No session open"})
+                } else {
+                    match_totp_by_url(&sessions, url)
+                }
+            }
             Err(_) => json!({"status": "error", "message": "Session state unavailable"}),
         },
         _ => json!({"error": "Invalid request"}),
@@ -82,30 +113,42 @@ fn handle_request(
     Ok(())
 }
 
-fn match_entries_by_url(session: &Session, url: &str) -> Value {
+// Entries matching the URL across all unlocked vaults
+fn match_entries_by_url(sessions: &mut [&mut Session], url: &str) -> Value {
     use crate::cli::url_matches;
 
-    let vault = match session.opened_vault.as_ref() {
-        Some(vault) => vault,
-        None => return json!({"status": "error", "message": "No vault open"}),
-    };
     let mut matches = Vec::new();
 
-    for entry in &vault.entries {
-        if let Some(entry_url) = entry.url()
-            && url_matches(entry_url, url)
-        {
-            matches.push(json!({
-                "username": entry.username(),
-                "password": entry.password(),
-                "url": entry.url(),
-            }));
+    // (session, entry) index of every match
+    let mut positions = Vec::new();
+
+    for (s, session) in sessions.iter().enumerate() {
+        let Some(vault) = session.opened_vault.as_ref() else {
+            continue;
+        };
+        for (i, entry) in vault.entries.iter().enumerate() {
+            if let Some(entry_url) = entry.url()
+                && url_matches(entry_url, url)
+            {
+                positions.push((s, i));
+                matches.push(json!({
+                    "vault": session.vault_name,
+                    "username": entry.username(),
+                    "password": entry.password(),
+                    "url": entry.url(),
+                }));
+            }
         }
     }
 
     match matches.len() {
         0 => json!({"status": "not_found"}),
         1 => {
+            // the popup picks one of several matches itself, so only a single match counts as used
+            let (s, i) = positions[0];
+            if let Some(vault) = sessions[s].opened_vault.as_mut() {
+                vault.entries[i].mark_used();
+            }
             let entry = &matches[0];
             json!({
                 "status": "ok",
@@ -118,20 +161,66 @@ fn match_entries_by_url(session: &Session, url: &str) -> Value {
     }
 }
 
+// Current one-time codes of the entries matching the URL in all unlocked vaults,
+// the TOTP secret itself never leaves the vault
+fn match_totp_by_url(sessions: &[&Session], url: &str) -> Value {
+    use crate::cli::url_matches;
+
+    let mut matches = Vec::new();
+
+    for session in sessions {
+        let Some(vault) = session.opened_vault.as_ref() else {
+            continue;
+        };
+        for entry in &vault.entries {
+            if let Some(entry_url) = entry.url()
+                && url_matches(entry_url, url)
+                && let Some(totp) = entry.get_totp()
+                && let Ok((code, remaining)) = totp.current_code()
+            {
+                matches.push(json!({
+                    "vault": session.vault_name,
+                    "username": entry.username(),
+                    "url": entry.url(),
+                    "code": code,
+                    "remaining": remaining,
+                }));
+            }
+        }
+    }
+
+    match matches.len() {
+        0 => json!({"status": "not_found"}),
+        1 => {
+            let entry = &matches[0];
+            json!({
+                "status": "ok",
+                "mode": "single",
+                "code": entry.get("code"),
+                "remaining": entry.get("remaining"),
+            })
+        }
+        _ => json!({"status": "ok", "mode": "multiple", "entries": matches}),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::session::Sessions;
     use crate::vault_entry_manager::{Entry, Vault};
     use serde_json::Value as JsonValue;
     use std::io::{Read, Write};
     use std::net::TcpStream;
 
     fn make_session_with_entries(entries: Vec<Entry>) -> Session {
-        let mut session = Session::new("test_vault".to_string());
-        let vault = Vault {
-            name: "test_vault".to_string(),
-            entries,
-        };
+        make_named_session("test_vault", entries)
+    }
+
+    fn make_named_session(name: &str, entries: Vec<Entry>) -> Session {
+        let mut session = Session::new(name.to_string());
+        let mut vault = Vault::new(name.to_string());
+        vault.entries = entries;
         session.opened_vault = Some(vault);
         session
     }
@@ -161,12 +250,16 @@ mod tests {
     }
 
     fn parse_body_json(response: &str) -> JsonValue {
-        let body = response.splitn(2, "\r\n\r\n").nth(1).unwrap_or("").trim();
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, body)| body)
+            .unwrap_or("")
+            .trim();
         serde_json::from_str(body).unwrap_or_else(|_| json!({}))
     }
 
-    fn with_server(
-        session: Arc<Mutex<Option<Session>>>,
+    fn with_server<H: SessionHolder>(
+        session: Arc<Mutex<H>>,
         token: String,
         method: &str,
         body: Option<&str>,
@@ -230,12 +323,16 @@ mod tests {
         let session = make_session_with_entries(entries);
         let session = Arc::new(Mutex::new(Some(session)));
         let body = r#"{"action":"fill","url":"https://example.com/login","token":"token"}"#;
-        let response = with_server(session, "token".to_string(), "POST", Some(body));
+        let response = with_server(session.clone(), "token".to_string(), "POST", Some(body));
         let json = parse_body_json(&response);
         assert_eq!(json.get("status").and_then(|v| v.as_str()), Some("ok"));
         assert_eq!(json.get("mode").and_then(|v| v.as_str()), Some("single"));
         assert_eq!(json.get("username").and_then(|v| v.as_str()), Some("user1"));
         assert_eq!(json.get("password").and_then(|v| v.as_str()), Some("pass1"));
+
+        let guard = session.lock().unwrap();
+        let vault = guard.as_ref().unwrap().opened_vault.as_ref().unwrap();
+        assert!(vault.entries[0].last_used().is_some());
     }
 
     #[test]
@@ -267,4 +364,77 @@ mod tests {
         assert!(entries.is_some(), "Expected entries array in response");
         assert_eq!(entries.map(|values| values.len()), Some(2));
     }
+
+    #[test]
+    fn test_totp_code() {
+        let mut entry = Entry::new(
+            "entry1".to_string(),
+            Some("user1".to_string()),
+            Some("pass1".to_string()),
+            Some("https://example.com".to_string()),
+            None,
+        );
+        entry.set_totp(crate::totp::Totp::parse("JBSWY3DPEHPK3PXP").unwrap());
+        let without_totp = Entry::new(
+            "entry2".to_string(),
+            None,
+            None,
+            Some("https://example.com".to_string()),
+            None,
+        );
+        let session = make_session_with_entries(vec![entry, without_totp]);
+        let session = Arc::new(Mutex::new(Some(session)));
+        let body = r#"{"action":"totp","url":"https://example.com/2fa","token":"token"}"#;
+        let response = with_server(session, "token".to_string(), "POST", Some(body));
+        let json = parse_body_json(&response);
+        assert_eq!(json.get("mode").and_then(|v| v.as_str()), Some("single"));
+        let code = json.get("code").and_then(|v| v.as_str()).unwrap_or("");
+        assert_eq!(code.len(), 6);
+        assert!(json.get("secret").is_none());
+    }
+
+    #[test]
+    fn test_fill_searches_every_unlocked_vault() {
+        let entry = |name: &str, user: &str| {
+            Entry::new(
+                name.to_string(),
+                Some(user.to_string()),
+                Some("pass".to_string()),
+                Some("https://example.com".to_string()),
+                None,
+            )
+        };
+        let sessions = Sessions {
+            active: Some(make_named_session("personal", vec![entry("mail", "me")])),
+            background: vec![make_named_session("team", vec![entry("mail", "team")])],
+        };
+        let body = r#"{"action":"fill","url":"https://example.com","token":"token"}"#;
+        let response = with_server(
+            Arc::new(Mutex::new(sessions)),
+            "token".to_string(),
+            "POST",
+            Some(body),
+        );
+        let json = parse_body_json(&response);
+        assert_eq!(json.get("mode").and_then(|v| v.as_str()), Some("multiple"));
+        let vaults: Vec<_> = json
+            .get("entries")
+            .and_then(|v| v.as_array())
+            .map(|entries| entries.iter().filter_map(|e| e.get("vault")).collect())
+            .unwrap_or_default();
+        assert_eq!(vaults, vec!["personal", "team"]);
+
+        // a match only in a background vault is still found
+        let sessions = Sessions {
+            active: Some(make_named_session("personal", Vec::new())),
+            background: vec![make_named_session("team", vec![entry("mail", "team")])],
+        };
+        let sessions = Arc::new(Mutex::new(sessions));
+        let response = with_server(sessions.clone(), "token".to_string(), "POST", Some(body));
+        let json = parse_body_json(&response);
+        assert_eq!(json.get("username").and_then(|v| v.as_str()), Some("team"));
+        let guard = sessions.lock().unwrap();
+        let vault = guard.background[0].opened_vault.as_ref().unwrap();
+        assert!(vault.entries[0].last_used().is_some());
+    }
 }
@@ -0,0 +1,579 @@
+/*what belongs here:
+- Generating random passwords, diceware-style passphrases and pronounceable passwords
+- Password policies (mode, length, character classes, ...) and the entropy of what they generate
+
+Named policies are stored in the vault settings. A policy named after a domain (e.g.
+`mybank.com`) is used whenever a password is generated for an entry with a URL in that domain.
+The words of passphrases come from the wordlist bundled with passgenr (7,000+ words).
+*/
+
+use passgenr::charsets;
+use rand::Rng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::errors::VaultError;
+use crate::import::in_domain;
+
+pub const DEFAULT_LENGTH: usize = 20;
+pub const DEFAULT_WORDS: usize = 6;
+pub const MAX_WORDS: usize = 20;
+
+// characters easily mistaken for each other, e.g. when typed from a printout
+pub const AMBIGUOUS: &str = "0O1Il|`'\"";
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+// pronounceable passwords alternate between these, q, x and y are left out as hard to read aloud
+const CONSONANTS: &str = "bcdfghjklmnprstvwz";
+const VOWELS: &str = "aeiou";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorMode {
+    // characters drawn uniformly from the allowed classes
+    #[default]
+    Random,
+    // words of the bundled wordlist
+    Passphrase,
+    // alternating consonants and vowels, digits and symbols at the end
+    Pronounceable,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Capitalization {
+    #[default]
+    Lower,
+    // first letter of every word
+    Title,
+    Upper,
+    // every word either lower or title case, adds one bit per word
+    Random,
+}
+
+/// Everything `generate` needs to produce a password. Character classes only apply to the random
+/// and pronounceable modes, words, separator and capitalization only to passphrases.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub mode: GeneratorMode,
+    pub length: usize,
+    pub symbols: bool,
+    pub min_lowercase: usize,
+    pub min_uppercase: usize,
+    pub min_digits: usize,
+    pub min_symbols: usize,
+    pub exclude_ambiguous: bool,
+    // replaces the character classes of random passwords
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+}
+
+/// Options of `generate` and `policy set`, anything not given keeps the value of the policy it
+/// is applied to. The switches can only restrict a policy, not lift its restrictions.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct GeneratorArgs {
+    // Number of characters (default: generator.length of the config, 20)
+    pub length: Option<usize>,
+
+    #[arg(short = 'm', long = "mode", value_enum)]
+    pub mode: Option<GeneratorMode>,
+
+    #[arg(short = 'f', long = "no_symbols")]
+    pub no_symbols: bool,
+
+    // Leave out characters that are easily mistaken for each other (0O1Il|`'")
+    #[arg(short = 'a', long = "exclude-ambiguous")]
+    pub exclude_ambiguous: bool,
+
+    #[arg(long = "min-lower", value_name = "N")]
+    pub min_lowercase: Option<usize>,
+
+    #[arg(long = "min-upper", value_name = "N")]
+    pub min_uppercase: Option<usize>,
+
+    #[arg(long = "min-digits", value_name = "N")]
+    pub min_digits: Option<usize>,
+
+    #[arg(long = "min-symbols", value_name = "N")]
+    pub min_symbols: Option<usize>,
+
+    // Only use these characters (random mode)
+    #[arg(long = "charset", value_name = "CHARS")]
+    pub charset: Option<String>,
+
+    // Number of words of a passphrase, implies --mode passphrase
+    #[arg(short = 'w', long = "words")]
+    pub words: Option<usize>,
+
+    // Put between the words of a passphrase (default '-')
+    #[arg(long = "separator")]
+    pub separator: Option<String>,
+
+    #[arg(long = "capitalize", value_enum)]
+    pub capitalization: Option<Capitalization>,
+}
+
+#[derive(Debug)]
+pub struct Generated {
+    pub password: String,
+    // lower bound, assumes the attacker knows the policy
+    pub entropy_bits: f64,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            mode: GeneratorMode::Random,
+            length: DEFAULT_LENGTH,
+            symbols: true,
+            min_lowercase: 0,
+            min_uppercase: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            exclude_ambiguous: false,
+            charset: None,
+            words: DEFAULT_WORDS,
+            separator: "-".to_string(),
+            capitalization: Capitalization::Lower,
+        }
+    }
+}
+
+impl fmt::Display for GeneratorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            GeneratorMode::Random => "random",
+            GeneratorMode::Passphrase => "passphrase",
+            GeneratorMode::Pronounceable => "pronounceable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for PasswordPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mode == GeneratorMode::Passphrase {
+            let capitalization = match self.capitalization {
+                Capitalization::Lower => "lower case",
+                Capitalization::Title => "title case",
+                Capitalization::Upper => "upper case",
+                Capitalization::Random => "random case",
+            };
+            return write!(
+                f,
+                "passphrase, {} words separated by '{}', {}",
+                self.words, self.separator, capitalization
+            );
+        }
+
+        let mut parts = vec![format!("{}, {} characters", self.mode, self.length)];
+        if let Some(charset) = &self.charset {
+            parts.push(format!("only '{}'", charset));
+        } else if !self.symbols {
+            parts.push("no symbols".to_string());
+        }
+        for (min, class) in [
+            (self.min_lowercase, "lower case letters"),
+            (self.min_uppercase, "upper case letters"),
+            (self.min_digits, "digits"),
+            (self.min_symbols, "symbols"),
+        ] {
+            if min > 0 {
+                parts.push(format!("at least {} {}", min, class));
+            }
+        }
+        if self.exclude_ambiguous {
+            parts.push("no ambiguous characters".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl GeneratorArgs {
+    pub fn apply(&self, policy: PasswordPolicy) -> PasswordPolicy {
+        let mode = match (self.mode, self.words) {
+            (Some(mode), _) => mode,
+            (None, Some(_)) => GeneratorMode::Passphrase,
+            (None, None) => policy.mode,
+        };
+        PasswordPolicy {
+            mode,
+            length: self.length.unwrap_or(policy.length),
+            symbols: policy.symbols && !self.no_symbols,
+            min_lowercase: self.min_lowercase.unwrap_or(policy.min_lowercase),
+            min_uppercase: self.min_uppercase.unwrap_or(policy.min_uppercase),
+            min_digits: self.min_digits.unwrap_or(policy.min_digits),
+            min_symbols: self.min_symbols.unwrap_or(policy.min_symbols),
+            exclude_ambiguous: policy.exclude_ambiguous || self.exclude_ambiguous,
+            charset: self.charset.clone().or(policy.charset),
+            words: self.words.unwrap_or(policy.words),
+            separator: self.separator.clone().unwrap_or(policy.separator),
+            capitalization: self.capitalization.unwrap_or(policy.capitalization),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Checks the policy first, a policy that cannot be fulfilled is an error instead of a weaker password.
+pub fn generate(policy: &PasswordPolicy) -> Result<Generated, VaultError> {
+    validate(policy)?;
+    match policy.mode {
+        GeneratorMode::Random => generate_random(policy),
+        GeneratorMode::Passphrase => Ok(generate_passphrase(policy)),
+        GeneratorMode::Pronounceable => Ok(generate_pronounceable(policy)),
+    }
+}
+
+/// The stored policy named after the domain of the URL, the most specific one if several match.
+pub fn policy_for_url<'a>(
+    policies: &'a BTreeMap<String, PasswordPolicy>,
+    url: &str,
+) -> Option<(&'a String, &'a PasswordPolicy)> {
+    policies
+        .iter()
+        .filter(|(name, _)| in_domain(url, name))
+        .max_by_key(|(name, _)| name.len())
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn validate(policy: &PasswordPolicy) -> Result<(), VaultError> {
+    let invalid = |e: &str| Err(VaultError::InvalidPolicy(e.to_string()));
+
+    if policy.mode == GeneratorMode::Passphrase {
+        if policy.words < 3 || policy.words > MAX_WORDS {
+            return invalid(&format!("a passphrase needs 3 to {} words", MAX_WORDS));
+        }
+        return Ok(());
+    }
+
+    if policy.length <= 1 || policy.length > 200 {
+        return Err(VaultError::InvalidLength);
+    }
+    if policy.charset.is_some() && policy.mode != GeneratorMode::Random {
+        return invalid("a custom charset only applies to random passwords");
+    }
+    if !policy.symbols && policy.charset.is_none() && policy.min_symbols > 0 {
+        return invalid("symbols are required but not allowed");
+    }
+    let required =
+        policy.min_lowercase + policy.min_uppercase + policy.min_digits + policy.min_symbols;
+    if required > policy.length {
+        return invalid(&format!(
+            "{} characters are required, but the length is only {}",
+            required, policy.length
+        ));
+    }
+    if policy.mode == GeneratorMode::Pronounceable {
+        let letters = policy.length - policy.min_digits - policy.min_symbols;
+        if letters < 2 {
+            return invalid("a pronounceable password needs at least 2 letters");
+        }
+        if policy.min_uppercase > letters.div_ceil(2) {
+            return invalid("only the consonants of a pronounceable password are upper case");
+        }
+    }
+    Ok(())
+}
+
+// The characters of `chars` that the policy allows
+fn allowed(chars: &str, policy: &PasswordPolicy) -> Vec<char> {
+    let mut allowed: Vec<char> = chars
+        .chars()
+        .filter(|c| !policy.exclude_ambiguous || !AMBIGUOUS.contains(*c))
+        .collect();
+    allowed.sort_unstable();
+    allowed.dedup();
+    allowed
+}
+
+// Picks the required characters of every class first, fills up from all allowed characters and
+// shuffles. The entropy only counts the choices, not the positions of the required characters.
+fn generate_random(policy: &PasswordPolicy) -> Result<Generated, VaultError> {
+    let alphabet = match &policy.charset {
+        Some(charset) => allowed(charset, policy),
+        None if policy.symbols => {
+            allowed(&[LOWERCASE, UPPERCASE, DIGITS, SYMBOLS].concat(), policy)
+        }
+        None => allowed(&[LOWERCASE, UPPERCASE, DIGITS].concat(), policy),
+    };
+    if alphabet.len() < 2 {
+        return Err(VaultError::InvalidPolicy(
+            "at least 2 different characters are needed".to_string(),
+        ));
+    }
+
+    let class = |is_member: fn(&char) -> bool| -> Vec<char> {
+        alphabet.iter().copied().filter(is_member).collect()
+    };
+    let classes = [
+        (
+            policy.min_lowercase,
+            class(char::is_ascii_lowercase),
+            "lower case letters",
+        ),
+        (
+            policy.min_uppercase,
+            class(char::is_ascii_uppercase),
+            "upper case letters",
+        ),
+        (policy.min_digits, class(char::is_ascii_digit), "digits"),
+        (
+            policy.min_symbols,
+            class(|c| !c.is_ascii_alphanumeric()),
+            "symbols",
+        ),
+    ];
+
+    let mut rng = rand::rng();
+    let mut password: Vec<char> = Vec::with_capacity(policy.length);
+    let mut entropy_bits = 0.0;
+    for (min, members, name) in &classes {
+        if *min > 0 && members.is_empty() {
+            return Err(VaultError::InvalidPolicy(format!(
+                "{} are required, but the charset contains none",
+                name
+            )));
+        }
+        for _ in 0..*min {
+            password.extend(members.choose(&mut rng));
+            entropy_bits += (members.len() as f64).log2();
+        }
+    }
+    while password.len() < policy.length {
+        password.extend(alphabet.choose(&mut rng));
+        entropy_bits += (alphabet.len() as f64).log2();
+    }
+    password.shuffle(&mut rng);
+
+    Ok(Generated {
+        password: password.into_iter().collect(),
+        entropy_bits,
+    })
+}
+
+fn generate_passphrase(policy: &PasswordPolicy) -> Generated {
+    let mut rng = rand::rng();
+    // no word is picked twice
+    let picked: Vec<&str> = charsets::WORDS
+        .choose_multiple(&mut rng, policy.words)
+        .copied()
+        .collect();
+    let mut entropy_bits: f64 = (0..policy.words)
+        .map(|i| ((charsets::WORDS.len() - i) as f64).log2())
+        .sum();
+
+    let title_case = |word: &str| -> String {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    };
+    let words: Vec<String> = picked
+        .into_iter()
+        .map(|word| match policy.capitalization {
+            Capitalization::Lower => word.to_string(),
+            Capitalization::Title => title_case(word),
+            Capitalization::Upper => word.to_uppercase(),
+            Capitalization::Random if rng.random_bool(0.5) => title_case(word),
+            Capitalization::Random => word.to_string(),
+        })
+        .collect();
+    if policy.capitalization == Capitalization::Random {
+        entropy_bits += policy.words as f64;
+    }
+
+    Generated {
+        password: words.join(&policy.separator),
+        entropy_bits,
+    }
+}
+
+// e.g. "bafuvorike" + "Z" in an upper case position + "47" + "!"
+fn generate_pronounceable(policy: &PasswordPolicy) -> Generated {
+    let mut rng = rand::rng();
+    let consonants = allowed(CONSONANTS, policy);
+    let vowels = allowed(VOWELS, policy);
+    let digits = allowed(DIGITS, policy);
+    let symbols = allowed(SYMBOLS, policy);
+
+    let letters = policy.length - policy.min_digits - policy.min_symbols;
+    let mut password: Vec<char> = Vec::with_capacity(policy.length);
+    let mut entropy_bits = 0.0;
+    for i in 0..letters {
+        let set = if i % 2 == 0 { &consonants } else { &vowels };
+        password.extend(set.choose(&mut rng));
+        entropy_bits += (set.len() as f64).log2();
+    }
+
+    // upper case letters are placed on random consonants, an upper case I or O would be ambiguous
+    let mut positions: Vec<usize> = (0..letters).step_by(2).collect();
+    positions.shuffle(&mut rng);
+    for i in positions.into_iter().take(policy.min_uppercase) {
+        password[i] = password[i].to_ascii_uppercase();
+    }
+
+    for (count, set) in [(policy.min_digits, &digits), (policy.min_symbols, &symbols)] {
+        for _ in 0..count {
+            password.extend(set.choose(&mut rng));
+            entropy_bits += (set.len() as f64).log2();
+        }
+    }
+
+    Generated {
+        password: password.into_iter().collect(),
+        entropy_bits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(password: &str, is_member: fn(&char) -> bool) -> usize {
+        password.chars().filter(is_member).count()
+    }
+
+    #[test]
+    fn test_random_with_minimums() {
+        let policy = PasswordPolicy {
+            length: 12,
+            min_uppercase: 3,
+            min_digits: 4,
+            min_symbols: 2,
+            exclude_ambiguous: true,
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            let generated = generate(&policy).unwrap();
+            let password = &generated.password;
+            assert_eq!(password.chars().count(), 12);
+            assert!(count(password, char::is_ascii_uppercase) >= 3);
+            assert!(count(password, char::is_ascii_digit) >= 4);
+            assert!(count(password, |c| !c.is_ascii_alphanumeric()) >= 2);
+            assert!(!password.chars().any(|c| AMBIGUOUS.contains(c)));
+        }
+
+        let too_many = PasswordPolicy {
+            length: 4,
+            min_digits: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generate(&too_many),
+            Err(VaultError::InvalidPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn test_custom_charset_and_entropy() {
+        let policy = PasswordPolicy {
+            length: 16,
+            charset: Some("abcdef0123456789".to_string()),
+            ..Default::default()
+        };
+        let generated = generate(&policy).unwrap();
+        assert!(generated.password.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(generated.entropy_bits, 64.0);
+
+        let no_digits = PasswordPolicy {
+            charset: Some("abc".to_string()),
+            min_digits: 1,
+            ..policy
+        };
+        assert!(generate(&no_digits).is_err());
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let policy = PasswordPolicy {
+            mode: GeneratorMode::Passphrase,
+            words: 5,
+            separator: ".".to_string(),
+            capitalization: Capitalization::Title,
+            ..Default::default()
+        };
+        let generated = generate(&policy).unwrap();
+        let words: Vec<&str> = generated.password.split('.').collect();
+        assert_eq!(words.len(), 5);
+        for word in words {
+            assert!(word.starts_with(|c: char| c.is_ascii_uppercase()));
+            assert!(charsets::WORDS.contains(&word.to_lowercase().as_str()));
+        }
+        // about 12.8 bits per word
+        assert!(generated.entropy_bits > 63.0 && generated.entropy_bits < 65.0);
+    }
+
+    #[test]
+    fn test_pronounceable() {
+        let policy = PasswordPolicy {
+            mode: GeneratorMode::Pronounceable,
+            length: 10,
+            min_uppercase: 1,
+            min_digits: 2,
+            min_symbols: 1,
+            ..Default::default()
+        };
+        let password = generate(&policy).unwrap().password;
+        let (letters, rest) = password.split_at(7);
+        assert_eq!(count(letters, char::is_ascii_uppercase), 1);
+        for (i, c) in letters.to_ascii_lowercase().chars().enumerate() {
+            let set = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+            assert!(set.contains(c), "{} in {}", c, password);
+        }
+        assert!(rest[..2].chars().all(|c| c.is_ascii_digit()));
+        assert!(SYMBOLS.contains(&rest[2..]));
+    }
+
+    #[test]
+    fn test_args_and_site_policies() {
+        let stored = PasswordPolicy {
+            length: 16,
+            symbols: false,
+            ..Default::default()
+        };
+        let args = GeneratorArgs {
+            length: Some(24),
+            min_digits: Some(2),
+            ..Default::default()
+        };
+        let applied = args.apply(stored.clone());
+        assert_eq!(applied.length, 24);
+        assert_eq!(applied.min_digits, 2);
+        assert!(!applied.symbols);
+
+        let passphrase = GeneratorArgs {
+            words: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            passphrase.apply(stored.clone()).mode,
+            GeneratorMode::Passphrase
+        );
+
+        let mut policies = BTreeMap::new();
+        policies.insert("bank.com".to_string(), stored.clone());
+        policies.insert("login.bank.com".to_string(), PasswordPolicy::default());
+        let (name, _) = policy_for_url(&policies, "https://login.bank.com/x").unwrap();
+        assert_eq!(name, "login.bank.com");
+        let (name, _) = policy_for_url(&policies, "https://www.bank.com").unwrap();
+        assert_eq!(name, "bank.com");
+        assert!(policy_for_url(&policies, "https://notbank.com").is_none());
+    }
+}
@@ -0,0 +1,593 @@
+/*what belongs here:
+- Reading the CSV exports of other password managers into entries (KeePass databases: kdbx.rs,
+  Bitwarden JSON: bitwarden.rs)
+- Planning how imported entries are added to a vault (name collisions) and applying that plan
+*/
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::bitwarden;
+use crate::errors::VaultError;
+use crate::totp::Totp;
+use crate::vault_entry_manager::{CustomField, Entry, FieldType, Vault, normalize_folder};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ImportFormat {
+    // detected from the header row
+    #[default]
+    Auto,
+    Bitwarden,
+    // the unencrypted JSON export of Bitwarden
+    BitwardenJson,
+    #[value(name = "1password", alias = "onepassword")]
+    OnePassword,
+    // KeePassXC and KeePass 2 CSV exports
+    Keepass,
+    Lastpass,
+    Chrome,
+    Firefox,
+    // a KeePass database file (.kdbx), not a CSV export
+    Kdbx,
+    // a file written by `export --format encrypted`
+    Encrypted,
+}
+
+// What happens to an imported entry whose name already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    // e.g. 'GitHub (2)'
+    #[default]
+    Rename,
+    Skip,
+    Overwrite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportAction {
+    Add,
+    Rename { from: String },
+    Skip,
+    Overwrite,
+}
+
+#[derive(Debug)]
+pub struct Imported {
+    // the detected format if Auto was requested
+    pub format: ImportFormat,
+    pub entries: Vec<Entry>,
+    // rows or values that could not be imported
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedEntry {
+    pub entry: Entry,
+    pub action: ImportAction,
+}
+
+// Header names (lower case) per entry value, the first one present in the file is used
+struct Columns {
+    name: &'static [&'static str],
+    username: &'static [&'static str],
+    password: &'static [&'static str],
+    url: &'static [&'static str],
+    notes: &'static [&'static str],
+    totp: &'static [&'static str],
+    folder: &'static [&'static str],
+    tags: &'static [&'static str],
+    // Bitwarden keeps custom fields as 'name: value' lines
+    fields: &'static [&'static str],
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImportFormat::Auto => "auto",
+            ImportFormat::Bitwarden => "Bitwarden",
+            ImportFormat::BitwardenJson => "Bitwarden JSON",
+            ImportFormat::OnePassword => "1Password",
+            ImportFormat::Keepass => "KeePass",
+            ImportFormat::Lastpass => "LastPass",
+            ImportFormat::Chrome => "Chrome",
+            ImportFormat::Firefox => "Firefox",
+            ImportFormat::Kdbx => "KeePass database",
+            ImportFormat::Encrypted => "encrypted export",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ImportFormat {
+    fn columns(&self) -> Columns {
+        match self {
+            ImportFormat::Bitwarden => Columns {
+                name: &["name"],
+                username: &["login_username"],
+                password: &["login_password"],
+                url: &["login_uri"],
+                notes: &["notes"],
+                totp: &["login_totp"],
+                folder: &["folder"],
+                tags: &[],
+                fields: &["fields"],
+            },
+            ImportFormat::OnePassword => Columns {
+                name: &["title"],
+                username: &["username"],
+                password: &["password"],
+                url: &["url", "website"],
+                notes: &["notes", "notesplain"],
+                totp: &["otpauth", "one-time password"],
+                folder: &[],
+                tags: &["tags"],
+                fields: &[],
+            },
+            ImportFormat::Keepass => Columns {
+                name: &["title", "account"],
+                username: &["username", "login name"],
+                password: &["password"],
+                url: &["url", "web site"],
+                notes: &["notes", "comments"],
+                totp: &["totp"],
+                folder: &["group"],
+                tags: &[],
+                fields: &[],
+            },
+            ImportFormat::Lastpass => Columns {
+                name: &["name"],
+                username: &["username"],
+                password: &["password"],
+                url: &["url"],
+                notes: &["extra"],
+                totp: &["totp"],
+                folder: &["grouping"],
+                tags: &[],
+                fields: &[],
+            },
+            ImportFormat::Chrome => Columns {
+                name: &["name"],
+                username: &["username"],
+                password: &["password"],
+                url: &["url"],
+                notes: &["note"],
+                totp: &[],
+                folder: &[],
+                tags: &[],
+                fields: &[],
+            },
+            // has no name column, the name is taken from the URL
+            ImportFormat::Firefox => Columns {
+                name: &[],
+                username: &["username"],
+                password: &["password"],
+                url: &["url"],
+                notes: &[],
+                totp: &[],
+                folder: &[],
+                tags: &[],
+                fields: &[],
+            },
+            ImportFormat::Auto
+            | ImportFormat::BitwardenJson
+            | ImportFormat::Kdbx
+            | ImportFormat::Encrypted => {
+                unreachable!("the format is detected before, databases are not CSV")
+            }
+        }
+    }
+
+    fn detect(headers: &[String]) -> Option<ImportFormat> {
+        let has = |name: &str| headers.iter().any(|h| h == name);
+
+        if has("login_password") {
+            Some(ImportFormat::Bitwarden)
+        } else if has("grouping") && has("extra") {
+            Some(ImportFormat::Lastpass)
+        } else if (has("group") && has("title")) || has("login name") {
+            Some(ImportFormat::Keepass)
+        } else if has("title") && has("password") {
+            Some(ImportFormat::OnePassword)
+        } else if has("httprealm") || has("formactionorigin") {
+            Some(ImportFormat::Firefox)
+        } else if has("name") && has("url") && has("password") {
+            Some(ImportFormat::Chrome)
+        } else {
+            None
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+pub fn read_csv_file(path: &Path, format: ImportFormat) -> Result<Imported, VaultError> {
+    let file = File::open(path)?;
+    read_csv(file, format)
+}
+
+/// Reads an unencrypted Bitwarden JSON export, the warnings list what could not be imported.
+pub fn read_bitwarden_json_file(path: &Path) -> Result<Imported, VaultError> {
+    let imported = bitwarden::read(&fs::read_to_string(path)?)?;
+    Ok(Imported {
+        format: ImportFormat::BitwardenJson,
+        entries: imported.entries,
+        warnings: imported.report,
+    })
+}
+
+/// JSON exports start with an object, CSV exports with their header row.
+pub fn is_json_file(path: &Path) -> bool {
+    let mut start = [0u8; 64];
+    let Ok(read) = File::open(path).and_then(|mut file| file.read(&mut start)) else {
+        return false;
+    };
+    String::from_utf8_lossy(&start[..read])
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('{')
+}
+
+/// Reads a CSV export, `Auto` detects the password manager from the header row.
+pub fn read_csv<R: Read>(reader: R, format: ImportFormat) -> Result<Imported, VaultError> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| VaultError::InvalidImport(e.to_string()))?
+        .iter()
+        .map(|h| h.trim().trim_start_matches('\u{feff}').to_lowercase())
+        .collect();
+
+    let format = match format {
+        ImportFormat::Auto => ImportFormat::detect(&headers).ok_or_else(|| {
+            VaultError::InvalidImport(
+                "unknown CSV layout, choose the password manager with --format".to_string(),
+            )
+        })?,
+        ImportFormat::BitwardenJson | ImportFormat::Kdbx | ImportFormat::Encrypted => {
+            return Err(VaultError::InvalidImport(format!(
+                "a {} is not a CSV export",
+                format
+            )));
+        }
+        format => format,
+    };
+
+    let columns = format.columns();
+    let index = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|h| h == name))
+    };
+    let password_column = index(columns.password).ok_or_else(|| {
+        VaultError::InvalidImport(format!("not a {} export, no password column", format))
+    })?;
+    let name_column = index(columns.name);
+    if name_column.is_none() && !columns.name.is_empty() {
+        return Err(VaultError::InvalidImport(format!(
+            "not a {} export, no name column",
+            format
+        )));
+    }
+    let username_column = index(columns.username);
+    let url_column = index(columns.url);
+    let notes_column = index(columns.notes);
+    let totp_column = index(columns.totp);
+    let folder_column = index(columns.folder);
+    let tags_column = index(columns.tags);
+    let fields_column = index(columns.fields);
+
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (row, record) in reader.records().enumerate() {
+        // the header is line 1
+        let line = row + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warnings.push(format!("line {}: {}", line, e));
+                continue;
+            }
+        };
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|i| record.get(i))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+
+        let username = value(username_column);
+        let password = value(Some(password_column));
+        let url = value(url_column);
+        let notes = value(notes_column);
+
+        let Some(name) = value(name_column)
+            .or_else(|| url.as_deref().and_then(name_from_url))
+            .or_else(|| username.clone())
+        else {
+            if password.is_some() || notes.is_some() {
+                warnings.push(format!("line {}: skipped, the entry has no name", line));
+            }
+            continue;
+        };
+
+        let mut entry = Entry::new(name, username, password, url, notes);
+
+        if let Some(secret) = value(totp_column) {
+            match Totp::parse(&secret) {
+                Ok(totp) => entry.set_totp(totp),
+                Err(e) => warnings.push(format!(
+                    "line {}: TOTP of '{}' not imported: {}",
+                    line,
+                    entry.get_entry_name(),
+                    e
+                )),
+            }
+        }
+        if let Some(folder) = value(folder_column) {
+            let folder = match format {
+                // KeePass puts every group below 'Root'
+                ImportFormat::Keepass => folder
+                    .strip_prefix("Root")
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .unwrap_or(&folder)
+                    .to_string(),
+                _ => folder,
+            };
+            if normalize_folder(&folder).is_some() {
+                entry.set_folder(&folder);
+            }
+        }
+        if let Some(tags) = value(tags_column) {
+            for tag in tags.split([',', ';']).filter(|t| !t.trim().is_empty()) {
+                entry.add_tag(tag);
+            }
+        }
+        if let Some(fields) = value(fields_column) {
+            for line_of_field in fields.lines() {
+                if let Some((name, field_value)) = line_of_field.split_once(": ")
+                    && let Ok(field) = CustomField::new(
+                        name.trim().to_string(),
+                        FieldType::Text,
+                        field_value.to_string(),
+                    )
+                {
+                    entry.set_field(field);
+                }
+            }
+        }
+
+        entries.push(entry);
+    }
+
+    Ok(Imported {
+        format,
+        entries,
+        warnings,
+    })
+}
+
+/// Decides for every imported entry whether it is added, renamed, skipped or overwrites an entry.
+/// Names that appear twice within the import are never overwritten, only renamed or skipped.
+pub fn plan_import(
+    vault: &Vault,
+    entries: Vec<Entry>,
+    policy: ConflictPolicy,
+) -> Vec<PlannedEntry> {
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut plan = Vec::new();
+
+    for mut entry in entries {
+        let name = entry.get_entry_name().clone();
+        let in_vault = vault.entryname_exists(&name);
+        let in_import = taken.contains(&name);
+
+        let action = if !in_vault && !in_import {
+            ImportAction::Add
+        } else if policy == ConflictPolicy::Skip {
+            ImportAction::Skip
+        } else if policy == ConflictPolicy::Overwrite && !in_import {
+            ImportAction::Overwrite
+        } else {
+            let new_name = (2..)
+                .map(|n| format!("{} ({})", name, n))
+                .find(|candidate| !vault.entryname_exists(candidate) && !taken.contains(candidate))
+                .expect("some number is free");
+            entry.entryname = new_name;
+            ImportAction::Rename { from: name }
+        };
+
+        if action != ImportAction::Skip {
+            taken.insert(entry.get_entry_name().clone());
+        }
+        plan.push(PlannedEntry { entry, action });
+    }
+    plan
+}
+
+/// Writes the plan into the vault, returns the number of entries that were added or overwritten.
+/// Nothing is changed if one of the entries cannot be written.
+pub fn apply_import(vault: &mut Vault, plan: Vec<PlannedEntry>) -> Result<usize, VaultError> {
+    let history_limit = vault.settings.history_count;
+    let mut imported = 0;
+    let mut updated = vault.clone();
+
+    for planned in plan {
+        match planned.action {
+            ImportAction::Skip => continue,
+            ImportAction::Overwrite => {
+                let name = planned.entry.get_entry_name().clone();
+                updated
+                    .get_entry_by_name(&name)
+                    .ok_or(VaultError::EntryNotFound)?
+                    .overwrite_with(planned.entry, history_limit);
+            }
+            ImportAction::Add | ImportAction::Rename { .. } => updated.add_entry(planned.entry)?,
+        }
+        imported += 1;
+    }
+    *vault = updated;
+    Ok(imported)
+}
+
+/// Name for an entry without one, e.g. https://www.github.com/login -> github.com
+pub fn name_from_url(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    Some(host.strip_prefix("www.").unwrap_or(host).to_string())
+}
+
+/// Whether the host of the URL is the domain or one of its subdomains, e.g.
+/// https://login.github.com/x is in the domain github.com, https://notgithub.com is not.
+pub fn in_domain(url: &str, domain: &str) -> bool {
+    let with_scheme = |s: &str| {
+        if s.contains("://") {
+            s.to_string()
+        } else {
+            format!("https://{}", s)
+        }
+    };
+    let (Some(host), Some(domain)) = (
+        name_from_url(&with_scheme(url)),
+        name_from_url(&with_scheme(domain.trim())),
+    ) else {
+        return false;
+    };
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BITWARDEN: &str = "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp
+Work,,login,GitHub,,\"PIN: 1234\",0,https://github.com,alice,secret1,JBSWY3DPEHPK3PXP
+,,login,Mail,some notes,,0,,alice@example.com,secret2,
+";
+
+    const FIREFOX: &str = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"
+\"https://www.github.com\",\"alice\",\"secret1\",,\"https://github.com\",\"{1}\",\"1\",\"1\",\"1\"
+";
+
+    const KEEPASS: &str = "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\",\"TOTP\"
+\"Root/Work\",\"GitHub\",\"alice\",\"secret1\",\"https://github.com\",\"\",\"not a secret!\"
+";
+
+    #[test]
+    fn test_formats_are_detected_and_mapped() {
+        let imported = read_csv(BITWARDEN.as_bytes(), ImportFormat::Auto).unwrap();
+        assert_eq!(imported.format, ImportFormat::Bitwarden);
+        assert_eq!(imported.entries.len(), 2);
+        let github = &imported.entries[0];
+        assert_eq!(github.get_entry_name(), "GitHub");
+        assert_eq!(github.username(), Some("alice"));
+        assert_eq!(github.password(), Some("secret1"));
+        assert_eq!(github.get_folder(), Some("Work"));
+        assert!(github.get_totp().is_some());
+        assert_eq!(github.get_field("PIN").unwrap().value(), "1234");
+        assert_eq!(
+            imported.entries[1].get_notes().as_deref(),
+            Some("some notes")
+        );
+
+        let imported = read_csv(FIREFOX.as_bytes(), ImportFormat::Auto).unwrap();
+        assert_eq!(imported.format, ImportFormat::Firefox);
+        assert_eq!(imported.entries[0].get_entry_name(), "github.com");
+
+        // an invalid TOTP secret only drops the secret
+        let imported = read_csv(KEEPASS.as_bytes(), ImportFormat::Auto).unwrap();
+        assert_eq!(imported.format, ImportFormat::Keepass);
+        assert_eq!(imported.entries[0].get_folder(), Some("Work"));
+        assert!(imported.entries[0].get_totp().is_none());
+        assert_eq!(imported.warnings.len(), 1);
+
+        assert!(read_csv(FIREFOX.as_bytes(), ImportFormat::Bitwarden).is_err());
+        assert!(read_csv("a,b\n1,2\n".as_bytes(), ImportFormat::Auto).is_err());
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let mut vault = Vault::new("import".to_string());
+        vault
+            .add_entry(Entry::new(
+                "GitHub".to_string(),
+                Some("old".to_string()),
+                Some("old-password".to_string()),
+                None,
+                None,
+            ))
+            .unwrap();
+        let entries = || {
+            read_csv(BITWARDEN.as_bytes(), ImportFormat::Auto)
+                .unwrap()
+                .entries
+        };
+
+        let plan = plan_import(&vault, entries(), ConflictPolicy::Skip);
+        assert_eq!(plan[0].action, ImportAction::Skip);
+        assert_eq!(plan[1].action, ImportAction::Add);
+
+        let plan = plan_import(&vault, entries(), ConflictPolicy::Rename);
+        assert_eq!(
+            plan[0].action,
+            ImportAction::Rename {
+                from: "GitHub".to_string()
+            }
+        );
+        assert_eq!(plan[0].entry.get_entry_name(), "GitHub (2)");
+
+        let plan = plan_import(&vault, entries(), ConflictPolicy::Overwrite);
+        assert_eq!(plan[0].action, ImportAction::Overwrite);
+        assert_eq!(apply_import(&mut vault, plan).unwrap(), 2);
+        let github = vault.get_entry_by_name(&"GitHub".to_string()).unwrap();
+        assert_eq!(github.password(), Some("secret1"));
+        // the overwritten values are kept in the history
+        assert_eq!(
+            github.get_history()[0].password.as_deref(),
+            Some("old-password")
+        );
+    }
+
+    #[test]
+    fn test_failed_import_changes_nothing() {
+        let mut vault = Vault::new("import".to_string());
+        let github = || {
+            Entry::new(
+                "GitHub".to_string(),
+                None,
+                Some("old-password".to_string()),
+                None,
+                None,
+            )
+        };
+        vault.add_entry(github()).unwrap();
+
+        let mut overwrite = github();
+        overwrite.set_password("new-password".to_string());
+        let plan = vec![
+            PlannedEntry {
+                entry: overwrite,
+                action: ImportAction::Overwrite,
+            },
+            // the name is taken, adding fails
+            PlannedEntry {
+                entry: github(),
+                action: ImportAction::Add,
+            },
+        ];
+        assert!(matches!(
+            apply_import(&mut vault, plan),
+            Err(VaultError::NameExists)
+        ));
+        let github = vault.get_entry_by_name(&"GitHub".to_string()).unwrap();
+        assert_eq!(github.password(), Some("old-password"));
+        assert!(github.get_history().is_empty());
+    }
+}
@@ -0,0 +1,1343 @@
+/*what belongs here:
+- Reading and writing KeePass KDBX 4 databases, the format of KeePass 2 and KeePassXC
+- Mapping KDBX groups, entries, custom fields and history onto `Vault` and `Entry`
+
+Layout of a KDBX 4 file (integers are little endian):
+  12 bytes  signatures and format version
+  header    fields as id (1 byte), length (4 bytes) and data, ending with field 0
+  32 bytes  SHA-256 of the header
+  32 bytes  HMAC-SHA-256 of the header
+  rest      blocks of HMAC (32 bytes), length (4 bytes) and data, ending with an empty block
+
+The blocks hold the encrypted, usually gzip compressed payload: an inner header with the key of
+the stream that protects passwords inside the XML, followed by the XML document itself.
+Groups become folders, the root group is the top level of the vault. Attachments, icons and
+auto-type settings have no counterpart in an entry and are not imported. Values the model has
+but KeePass has no place for (password change date, custom field types) are kept in the custom
+data of the KDBX entry, KeePassXC leaves them untouched.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use aes::Aes256;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{
+    BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit, StreamCipher,
+};
+use chacha20::ChaCha20;
+use chrono::{DateTime, Local, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hmac::{Hmac, Mac};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::errors::VaultError;
+use crate::import::name_from_url;
+use crate::totp::Totp;
+use crate::vault_entry_manager::{CustomField, Entry, EntryVersion, FieldType, Vault};
+use crate::vault_format::KdfSettings;
+
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+// major version in the upper 16 bits
+const VERSION_4_0: u32 = 0x0004_0000;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+const KDF_AES: [u8; 16] = [
+    0x7c, 0x02, 0xbb, 0x82, 0x79, 0xa7, 0x4a, 0xc0, 0x92, 0x7d, 0x11, 0x4a, 0x00, 0x64, 0x82, 0x38,
+];
+// AES-KDF as written by KeePass 2.x before KDBX 4, still found in upgraded databases
+const KDF_AES_LEGACY: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+
+// Fields of the outer header
+const END_OF_HEADER: u8 = 0;
+const CIPHER_ID: u8 = 2;
+const COMPRESSION_FLAGS: u8 = 3;
+const MASTER_SEED: u8 = 4;
+const ENCRYPTION_IV: u8 = 7;
+const KDF_PARAMETERS: u8 = 11;
+// Fields of the inner header
+const INNER_STREAM_ID: u8 = 1;
+const INNER_STREAM_KEY: u8 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+const VARIANT_DICTIONARY_VERSION: u16 = 0x0100;
+const HMAC_BLOCK_SIZE: usize = 1024 * 1024;
+// KDBX 4 stores times as seconds since 0001-01-01
+const SECONDS_BEFORE_UNIX_EPOCH: i64 = 62_135_596_800;
+
+// Entry strings with a fixed meaning, everything else is a custom field
+const STANDARD_KEYS: [&str; 8] = [
+    "Title",
+    "UserName",
+    "Password",
+    "URL",
+    "Notes",
+    "otp",
+    "TOTP Seed",
+    "TOTP Settings",
+];
+// Keys in the custom data of an entry
+const PASSWORD_CHANGED_KEY: &str = "rustpass:password-changed";
+const FIELD_TYPE_KEY_PREFIX: &str = "rustpass:field-type:";
+
+/// A KDBX database read into a vault, with everything that could not be taken over.
+#[derive(Debug)]
+pub struct KdbxImport {
+    pub vault: Vault,
+    pub warnings: Vec<String>,
+}
+
+// Value of a KDBX variant dictionary (used for the KDF parameters)
+#[derive(Debug, Clone, PartialEq)]
+enum Variant {
+    U32(u32),
+    U64(u64),
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+// Minimal XML tree, protected values are already decrypted
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Node>,
+}
+
+// Folders of a vault as nested KDBX groups
+#[derive(Default)]
+struct GroupTree<'a> {
+    entries: Vec<&'a Entry>,
+    groups: BTreeMap<String, GroupTree<'a>>,
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VaultError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("the file is truncated"))?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, VaultError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, VaultError> {
+        Ok(u16::from_le_bytes(
+            self.take(2)?.try_into().expect("2 bytes"),
+        ))
+    }
+
+    fn u32(&mut self) -> Result<u32, VaultError> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn text_of(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.as_str())
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// True if `bytes` start like a KeePass database (any KDBX version).
+pub fn is_kdbx(bytes: &[u8]) -> bool {
+    bytes.starts_with(&SIGNATURE)
+}
+
+/// Decrypts a KDBX 4 database, `key_file` holds the contents of its key file.
+pub fn read(
+    bytes: &[u8],
+    password: &SecretString,
+    key_file: Option<&[u8]>,
+) -> Result<KdbxImport, VaultError> {
+    let mut input = ByteReader::new(bytes);
+    if input.take(SIGNATURE.len())? != SIGNATURE {
+        return Err(invalid("not a KeePass database"));
+    }
+    let version = input.u32()?;
+    if version >> 16 != 4 {
+        return Err(invalid(&format!(
+            "KDBX {}.{} is not supported, only KDBX 4 (save it again with a current KeePass or KeePassXC)",
+            version >> 16,
+            version & 0xffff
+        )));
+    }
+
+    let mut cipher_id = None;
+    let mut compressed = false;
+    let mut master_seed = None;
+    let mut iv = None;
+    let mut kdf_parameters = None;
+    loop {
+        let id = input.u8()?;
+        let len = input.u32()? as usize;
+        let data = input.take(len)?;
+        match id {
+            END_OF_HEADER => break,
+            CIPHER_ID => cipher_id = Some(data),
+            COMPRESSION_FLAGS => compressed = data.first() == Some(&1),
+            MASTER_SEED => master_seed = Some(data),
+            ENCRYPTION_IV => iv = Some(data),
+            KDF_PARAMETERS => kdf_parameters = Some(parse_variant_dictionary(data)?),
+            _ => { /* public custom data, nothing we need */ }
+        }
+    }
+    let header = &bytes[..input.pos];
+    let missing = |field: &str| invalid(&format!("the header has no {}", field));
+    let cipher_id = cipher_id.ok_or_else(|| missing("cipher"))?;
+    let master_seed = master_seed.ok_or_else(|| missing("master seed"))?;
+    let iv = iv.ok_or_else(|| missing("encryption IV"))?;
+    let kdf_parameters = kdf_parameters.ok_or_else(|| missing("KDF parameters"))?;
+
+    if Sha256::digest(header).as_slice() != input.take(32)? {
+        return Err(invalid("the header is corrupt"));
+    }
+    let header_hmac = input.take(32)?;
+
+    let composite = composite_key(password, key_file)?;
+    let transformed = transform_key(&composite, &kdf_parameters)?;
+    let (cipher_key, hmac_key) = derive_keys(master_seed, &transformed);
+    block_mac(&hmac_key, u64::MAX, &[header])
+        .verify_slice(header_hmac)
+        .map_err(|_| VaultError::InvalidKey)?;
+
+    let mut ciphertext = Vec::new();
+    for index in 0u64.. {
+        let mac = input.take(32)?;
+        let len_bytes = input.take(4)?;
+        let len = u32::from_le_bytes(len_bytes.try_into().expect("4 bytes")) as usize;
+        let data = input.take(len)?;
+        block_mac(&hmac_key, index, &[&index.to_le_bytes(), len_bytes, data])
+            .verify_slice(mac)
+            .map_err(|_| invalid(&format!("block {} is corrupt", index)))?;
+        if len == 0 {
+            break;
+        }
+        ciphertext.extend_from_slice(data);
+    }
+
+    let mut payload = decrypt_payload(cipher_id, &cipher_key, iv, &ciphertext)?;
+    if compressed {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(payload.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|_| invalid("the payload is not valid gzip"))?;
+        payload = decompressed;
+    }
+
+    let mut inner = ByteReader::new(&payload);
+    let mut stream_id = None;
+    let mut stream_key = None;
+    loop {
+        let id = inner.u8()?;
+        let len = inner.u32()? as usize;
+        let data = inner.take(len)?;
+        match id {
+            END_OF_HEADER => break,
+            INNER_STREAM_ID if len == 4 => {
+                stream_id = Some(u32::from_le_bytes(data.try_into().expect("4 bytes")))
+            }
+            INNER_STREAM_KEY => stream_key = Some(data),
+            _ => { /* attachments */ }
+        }
+    }
+    if stream_id != Some(INNER_STREAM_CHACHA20) {
+        return Err(invalid(
+            "only the ChaCha20 inner stream of KDBX 4 is supported",
+        ));
+    }
+    let mut stream = inner_stream(stream_key.ok_or_else(|| missing("inner stream key"))?);
+
+    let document = parse_xml(inner.rest(), Some(&mut stream))?;
+    database_from_xml(&document)
+}
+
+/// Encrypts the vault as a KDBX 4 database (AES-256, Argon2id with the KDF settings of the vault).
+pub fn write(
+    vault: &Vault,
+    password: &SecretString,
+    key_file: Option<&[u8]>,
+) -> Result<Vec<u8>, VaultError> {
+    let mut rng = rand::rng();
+    let mut master_seed = [0u8; 32];
+    let mut iv = [0u8; 16];
+    let mut kdf_salt = [0u8; 32];
+    let mut stream_key = [0u8; 64];
+    rng.fill(&mut master_seed[..]);
+    rng.fill(&mut iv[..]);
+    rng.fill(&mut kdf_salt[..]);
+    rng.fill(&mut stream_key[..]);
+
+    let kdf_parameters = argon2_parameters(&vault.settings.kdf, &kdf_salt);
+
+    let mut header = SIGNATURE.to_vec();
+    header.extend_from_slice(&VERSION_4_0.to_le_bytes());
+    push_field(&mut header, CIPHER_ID, &CIPHER_AES256);
+    push_field(&mut header, COMPRESSION_FLAGS, &1u32.to_le_bytes());
+    push_field(&mut header, MASTER_SEED, &master_seed);
+    push_field(&mut header, ENCRYPTION_IV, &iv);
+    push_field(
+        &mut header,
+        KDF_PARAMETERS,
+        &write_variant_dictionary(&kdf_parameters),
+    );
+    push_field(&mut header, END_OF_HEADER, b"\r\n\r\n");
+
+    let composite = composite_key(password, key_file)?;
+    let transformed = transform_key(&composite, &kdf_parameters.into_iter().collect())?;
+    let (cipher_key, hmac_key) = derive_keys(&master_seed, &transformed);
+
+    let mut payload = Vec::new();
+    push_field(
+        &mut payload,
+        INNER_STREAM_ID,
+        &INNER_STREAM_CHACHA20.to_le_bytes(),
+    );
+    push_field(&mut payload, INNER_STREAM_KEY, &stream_key);
+    push_field(&mut payload, END_OF_HEADER, &[]);
+    payload.extend_from_slice(vault_to_xml(vault, &mut inner_stream(&stream_key)).as_bytes());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&payload)?;
+    let ciphertext = cbc::Encryptor::<Aes256>::new_from_slices(&cipher_key, &iv)
+        .expect("key and IV have fixed lengths")
+        .encrypt_padded_vec_mut::<Pkcs7>(&encoder.finish()?);
+
+    let mut out = header.clone();
+    out.extend_from_slice(&Sha256::digest(&header));
+    out.extend_from_slice(
+        &block_mac(&hmac_key, u64::MAX, &[&header])
+            .finalize()
+            .into_bytes(),
+    );
+    // the chunks, then the empty block that ends the stream
+    let chunks = ciphertext
+        .chunks(HMAC_BLOCK_SIZE)
+        .chain(std::iter::once(&[][..]));
+    for (index, chunk) in (0u64..).zip(chunks) {
+        let len = (chunk.len() as u32).to_le_bytes();
+        let mac = block_mac(&hmac_key, index, &[&index.to_le_bytes(), &len, chunk]);
+        out.extend_from_slice(&mac.finalize().into_bytes());
+        out.extend_from_slice(&len);
+        out.extend_from_slice(chunk);
+    }
+    Ok(out)
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn invalid(reason: &str) -> VaultError {
+    VaultError::InvalidImport(reason.to_string())
+}
+
+fn push_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn parse_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Variant>, VaultError> {
+    let mut input = ByteReader::new(data);
+    if input.u16()? >> 8 != VARIANT_DICTIONARY_VERSION >> 8 {
+        return Err(invalid("unknown version of the KDF parameters"));
+    }
+
+    let mut values = HashMap::new();
+    loop {
+        let kind = input.u8()?;
+        if kind == 0 {
+            break;
+        }
+        let key_len = input.u32()? as usize;
+        let key = String::from_utf8_lossy(input.take(key_len)?).into_owned();
+        let value_len = input.u32()? as usize;
+        let value = input.take(value_len)?;
+
+        let fixed = |n: usize| {
+            value
+                .get(..n)
+                .filter(|_| value.len() == n)
+                .ok_or_else(|| invalid("malformed KDF parameter"))
+        };
+        let variant = match kind {
+            0x04 => Variant::U32(u32::from_le_bytes(fixed(4)?.try_into().expect("4 bytes"))),
+            0x05 => Variant::U64(u64::from_le_bytes(fixed(8)?.try_into().expect("8 bytes"))),
+            0x08 => Variant::Bool(fixed(1)?[0] != 0),
+            0x0c => Variant::I32(i32::from_le_bytes(fixed(4)?.try_into().expect("4 bytes"))),
+            0x0d => Variant::I64(i64::from_le_bytes(fixed(8)?.try_into().expect("8 bytes"))),
+            0x18 => Variant::Str(String::from_utf8_lossy(value).into_owned()),
+            0x42 => Variant::Bytes(value.to_vec()),
+            _ => return Err(invalid("unknown type of a KDF parameter")),
+        };
+        values.insert(key, variant);
+    }
+    Ok(values)
+}
+
+fn write_variant_dictionary(values: &[(String, Variant)]) -> Vec<u8> {
+    let mut out = VARIANT_DICTIONARY_VERSION.to_le_bytes().to_vec();
+    for (key, value) in values {
+        let (kind, data) = match value {
+            Variant::U32(v) => (0x04, v.to_le_bytes().to_vec()),
+            Variant::U64(v) => (0x05, v.to_le_bytes().to_vec()),
+            Variant::Bool(v) => (0x08, vec![*v as u8]),
+            Variant::I32(v) => (0x0c, v.to_le_bytes().to_vec()),
+            Variant::I64(v) => (0x0d, v.to_le_bytes().to_vec()),
+            Variant::Str(v) => (0x18, v.as_bytes().to_vec()),
+            Variant::Bytes(v) => (0x42, v.clone()),
+        };
+        out.push(kind);
+        out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        out.extend_from_slice(key.as_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+    }
+    out.push(0);
+    out
+}
+
+fn argon2_parameters(kdf: &KdfSettings, salt: &[u8]) -> Vec<(String, Variant)> {
+    vec![
+        ("$UUID".to_string(), Variant::Bytes(KDF_ARGON2ID.to_vec())),
+        ("S".to_string(), Variant::Bytes(salt.to_vec())),
+        ("P".to_string(), Variant::U32(kdf.parallelism)),
+        ("M".to_string(), Variant::U64(kdf.memory_kib as u64 * 1024)),
+        ("I".to_string(), Variant::U64(kdf.iterations as u64)),
+        ("V".to_string(), Variant::U32(0x13)),
+    ]
+}
+
+// SHA-256 over the hashed password and the key of the key file
+fn composite_key(password: &SecretString, key_file: Option<&[u8]>) -> Result<[u8; 32], VaultError> {
+    let mut hasher = Sha256::new();
+    // like KeePass, an empty password is no part of the key at all
+    if !password.expose_secret().is_empty() {
+        hasher.update(Sha256::digest(password.expose_secret().as_bytes()));
+    }
+    if let Some(contents) = key_file {
+        hasher.update(key_file_key(contents)?);
+    }
+    Ok(hasher.finalize().into())
+}
+
+// KeePass key files: XML files carry the key, 32 raw bytes or 64 hex digits are the key,
+// any other file is hashed
+fn key_file_key(contents: &[u8]) -> Result<[u8; 32], VaultError> {
+    let invalid_key_file = |_| VaultError::InvalidKeyFile;
+
+    if let Ok(text) = std::str::from_utf8(contents)
+        && text.trim_start().starts_with('<')
+        && let Ok(document) = parse_xml(contents, None)
+        && document.name == "KeyFile"
+    {
+        let version = document
+            .child("Meta")
+            .and_then(|meta| meta.text_of("Version"))
+            .unwrap_or("1.0");
+        let data = document
+            .child("Key")
+            .and_then(|key| key.text_of("Data"))
+            .ok_or(VaultError::InvalidKeyFile)?;
+        let key = if version.starts_with('2') {
+            let digits: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            decode_hex(&digits).ok_or(VaultError::InvalidKeyFile)?
+        } else {
+            BASE64.decode(data.trim()).map_err(invalid_key_file)?
+        };
+        return key.try_into().map_err(|_| VaultError::InvalidKeyFile);
+    }
+
+    if contents.len() == 32 {
+        return Ok(contents.try_into().expect("32 bytes"));
+    }
+    if contents.len() == 64
+        && let Some(key) = std::str::from_utf8(contents).ok().and_then(decode_hex)
+    {
+        return key.try_into().map_err(|_| VaultError::InvalidKeyFile);
+    }
+    Ok(Sha256::digest(contents).into())
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn transform_key(
+    composite: &[u8; 32],
+    parameters: &HashMap<String, Variant>,
+) -> Result<[u8; 32], VaultError> {
+    let bytes = |key: &str| match parameters.get(key) {
+        Some(Variant::Bytes(value)) => Ok(value.as_slice()),
+        _ => Err(invalid(&format!("KDF parameter '{}' is missing", key))),
+    };
+    let number = |key: &str| match parameters.get(key) {
+        Some(Variant::U32(value)) => Ok(*value as u64),
+        Some(Variant::U64(value)) => Ok(*value),
+        _ => Err(invalid(&format!("KDF parameter '{}' is missing", key))),
+    };
+
+    let uuid = bytes("$UUID")?;
+    let mut key = [0u8; 32];
+    if uuid == KDF_ARGON2D || uuid == KDF_ARGON2ID {
+        let algorithm = if uuid == KDF_ARGON2D {
+            argon2::Algorithm::Argon2d
+        } else {
+            argon2::Algorithm::Argon2id
+        };
+        let version = argon2::Version::try_from(number("V").unwrap_or(0x13) as u32)
+            .map_err(|_| invalid("unknown Argon2 version"))?;
+        let to_u32 =
+            |value: u64| u32::try_from(value).map_err(|_| invalid("Argon2 parameter is too large"));
+        let params = argon2::Params::new(
+            to_u32(number("M")? / 1024)?,
+            to_u32(number("I")?)?,
+            to_u32(number("P")?)?,
+            Some(key.len()),
+        )
+        .map_err(|e| VaultError::InvalidKdfParameters(e.to_string()))?;
+        argon2::Argon2::new(algorithm, version, params)
+            .hash_password_into(composite, bytes("S")?, &mut key)
+            .map_err(|e| VaultError::InvalidKdfParameters(e.to_string()))?;
+    } else if uuid == KDF_AES || uuid == KDF_AES_LEGACY {
+        let cipher = Aes256::new_from_slice(bytes("S")?)
+            .map_err(|_| invalid("the AES-KDF seed has the wrong length"))?;
+        let mut transformed = *composite;
+        for _ in 0..number("R")? {
+            for block in transformed.chunks_exact_mut(16) {
+                cipher.encrypt_block(aes::Block::from_mut_slice(block));
+            }
+        }
+        key = Sha256::digest(transformed).into();
+    } else {
+        return Err(invalid("unknown key derivation function"));
+    }
+    Ok(key)
+}
+
+// Key of the payload cipher and the HMAC base key
+fn derive_keys(master_seed: &[u8], transformed: &[u8; 32]) -> ([u8; 32], [u8; 64]) {
+    let cipher_key = Sha256::new()
+        .chain_update(master_seed)
+        .chain_update(transformed)
+        .finalize();
+    let mut hmac_key = [0u8; 64];
+    hmac_key.copy_from_slice(
+        &Sha512::new()
+            .chain_update(master_seed)
+            .chain_update(transformed)
+            .chain_update([1u8])
+            .finalize(),
+    );
+    (cipher_key.into(), hmac_key)
+}
+
+// Every block has its own HMAC key, the header uses the block index u64::MAX
+fn block_mac(hmac_key: &[u8; 64], index: u64, parts: &[&[u8]]) -> Hmac<Sha256> {
+    let block_key = Sha512::new()
+        .chain_update(index.to_le_bytes())
+        .chain_update(hmac_key)
+        .finalize();
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&block_key).expect("any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+fn decrypt_payload(
+    cipher_id: &[u8],
+    key: &[u8; 32],
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, VaultError> {
+    if cipher_id == CIPHER_AES256 {
+        cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+            .map_err(|_| invalid("the encryption IV has the wrong length"))?
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| invalid("the payload could not be decrypted"))
+    } else if cipher_id == CIPHER_CHACHA20 {
+        let mut data = ciphertext.to_vec();
+        ChaCha20::new_from_slices(key, iv)
+            .map_err(|_| invalid("the encryption IV has the wrong length"))?
+            .apply_keystream(&mut data);
+        Ok(data)
+    } else {
+        Err(invalid("unsupported cipher, only AES-256 and ChaCha20 are"))
+    }
+}
+
+// Key and nonce of the inner stream are derived from the key in the inner header
+fn inner_stream(key: &[u8]) -> ChaCha20 {
+    let hash = Sha512::digest(key);
+    ChaCha20::new_from_slices(&hash[..32], &hash[32..44]).expect("slices have fixed lengths")
+}
+
+// Protected values have to be decrypted in document order, they share one key stream
+fn parse_xml(xml: &[u8], mut stream: Option<&mut ChaCha20>) -> Result<Node, VaultError> {
+    let malformed = |e: &dyn std::fmt::Display| invalid(&format!("malformed XML: {}", e));
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    // the bottom node collects the document element
+    let mut stack: Vec<Node> = vec![Node::default()];
+
+    loop {
+        match reader
+            .read_event_into(&mut buf)
+            .map_err(|e| malformed(&e))?
+        {
+            Event::Start(start) => stack.push(node_from_start(&start)?),
+            Event::Empty(start) => {
+                let node = node_from_start(&start)?;
+                stack.last_mut().expect("bottom node").children.push(node);
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| malformed(&e))?;
+                stack.last_mut().expect("bottom node").text.push_str(&text);
+            }
+            Event::CData(data) => {
+                let text = String::from_utf8_lossy(&data).into_owned();
+                stack.last_mut().expect("bottom node").text.push_str(&text);
+            }
+            Event::End(_) => {
+                if stack.len() < 2 {
+                    return Err(malformed(&"unexpected closing tag"));
+                }
+                let mut node = stack.pop().expect("checked above");
+                if node.attribute("Protected") == Some("True")
+                    && let Some(stream) = stream.as_deref_mut()
+                {
+                    let mut value = BASE64
+                        .decode(node.text.trim())
+                        .map_err(|_| malformed(&"protected value is not base64"))?;
+                    stream.apply_keystream(&mut value);
+                    node.text = String::from_utf8(value)
+                        .map_err(|_| invalid("a protected value could not be decrypted"))?;
+                }
+                stack.last_mut().expect("bottom node").children.push(node);
+            }
+            Event::Eof => break,
+            _ => { /* declaration, comments */ }
+        }
+        buf.clear();
+    }
+
+    if stack.len() != 1 {
+        return Err(malformed(&"unclosed element"));
+    }
+    stack
+        .pop()
+        .and_then(|bottom| bottom.children.into_iter().next())
+        .ok_or_else(|| malformed(&"empty document"))
+}
+
+fn node_from_start(start: &BytesStart) -> Result<Node, VaultError> {
+    let mut attributes = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| invalid(&format!("malformed XML: {}", e)))?;
+        let value = attribute
+            .unescape_value()
+            .map_err(|e| invalid(&format!("malformed XML: {}", e)))?;
+        attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            value.into_owned(),
+        ));
+    }
+    Ok(Node {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attributes,
+        ..Node::default()
+    })
+}
+
+fn database_from_xml(document: &Node) -> Result<KdbxImport, VaultError> {
+    if document.name != "KeePassFile" {
+        return Err(invalid("the payload is not a KeePass XML document"));
+    }
+    let meta = document.child("Meta");
+    let name = meta
+        .and_then(|meta| meta.text_of("DatabaseName"))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("KeePass");
+    let mut vault = Vault::new(name.to_string());
+    if let Some(max) = meta
+        .and_then(|meta| meta.text_of("HistoryMaxItems"))
+        .and_then(|max| max.trim().parse::<usize>().ok())
+    {
+        vault.settings.history_count = max;
+    }
+    let recycle_bin = meta
+        .filter(|meta| meta.text_of("RecycleBinEnabled") != Some("False"))
+        .and_then(|meta| meta.text_of("RecycleBinUUID"));
+
+    let root = document
+        .child("Root")
+        .and_then(|root| root.child("Group"))
+        .ok_or_else(|| invalid("the database has no root group"))?;
+
+    let mut warnings = Vec::new();
+    read_group(root, None, recycle_bin, &mut vault, &mut warnings)?;
+    Ok(KdbxImport { vault, warnings })
+}
+
+fn read_group(
+    group: &Node,
+    folder: Option<&str>,
+    recycle_bin: Option<&str>,
+    vault: &mut Vault,
+    warnings: &mut Vec<String>,
+) -> Result<(), VaultError> {
+    for node in group.children("Entry") {
+        let mut entry = entry_from_xml(node, folder, vault.settings.history_count, warnings);
+        let name = entry.get_entry_name().clone();
+        if vault.entryname_exists(&name) {
+            let unique = (2..)
+                .map(|n| format!("{} ({})", name, n))
+                .find(|candidate| !vault.entryname_exists(candidate))
+                .expect("some number is free");
+            warnings.push(format!(
+                "'{}' exists more than once, imported as '{}'",
+                name, unique
+            ));
+            entry.entryname = unique;
+        }
+        vault.add_entry(entry)?;
+    }
+
+    for child in group.children("Group") {
+        let count = count_entries(child);
+        if recycle_bin.is_some() && child.text_of("UUID") == recycle_bin {
+            if count > 0 {
+                warnings.push(format!(
+                    "{} entries in the recycle bin were not imported",
+                    count
+                ));
+            }
+            continue;
+        }
+        let name = child.text_of("Name").unwrap_or("Group").replace('/', "-");
+        let path = match folder {
+            Some(folder) => format!("{}/{}", folder, name),
+            None => name,
+        };
+        if count == 0 {
+            warnings.push(format!("empty group '{}' was not imported", path));
+            continue;
+        }
+        read_group(child, Some(&path), recycle_bin, vault, warnings)?;
+    }
+    Ok(())
+}
+
+fn count_entries(group: &Node) -> usize {
+    group.children("Entry").count() + group.children("Group").map(count_entries).sum::<usize>()
+}
+
+fn entry_from_xml(
+    node: &Node,
+    folder: Option<&str>,
+    history_limit: usize,
+    warnings: &mut Vec<String>,
+) -> Entry {
+    let strings = entry_strings(node);
+    let get = |key: &str| {
+        strings
+            .iter()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, value, _)| value.clone())
+            .filter(|value| !value.is_empty())
+    };
+    let custom_data: HashMap<String, String> = node
+        .child("CustomData")
+        .map(|data| {
+            data.children("Item")
+                .filter_map(|item| {
+                    Some((
+                        item.text_of("Key")?.to_string(),
+                        item.text_of("Value")?.to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let url = get("URL");
+    let name = get("Title")
+        .or_else(|| url.as_deref().and_then(name_from_url))
+        .unwrap_or_else(|| "Untitled".to_string());
+    let mut entry = Entry::new(name, get("UserName"), get("Password"), url, get("Notes"));
+    let name = entry.get_entry_name().clone();
+
+    // KeePassXC keeps an otpauth:// URI, older plugins a seed and "period;digits"
+    let totp = match (get("otp"), get("TOTP Seed")) {
+        (Some(uri), _) => Some(Totp::parse(&uri)),
+        (None, Some(seed)) => {
+            let settings = get("TOTP Settings").unwrap_or_default();
+            let mut parts = settings.split(';');
+            let period = parts.next().and_then(|p| p.trim().parse().ok());
+            let digits = parts.next().and_then(|d| d.trim().parse().ok());
+            Some(Totp::parse(&seed).and_then(|totp| totp.with_options(digits, period, None)))
+        }
+        (None, None) => None,
+    };
+    match totp {
+        Some(Ok(totp)) => entry.set_totp(totp),
+        Some(Err(e)) => warnings.push(format!("TOTP of '{}' was not imported: {}", name, e)),
+        None => {}
+    }
+
+    for field in custom_fields(&strings, &custom_data) {
+        match field {
+            Ok(field) => entry.set_field(field),
+            Err(e) => warnings.push(format!("a field of '{}' was not imported: {}", name, e)),
+        }
+    }
+    if let Some(folder) = folder {
+        entry.set_folder(folder);
+    }
+    for tag in node.text_of("Tags").unwrap_or_default().split([';', ',']) {
+        if !tag.trim().is_empty() {
+            entry.add_tag(tag);
+        }
+    }
+    for binary in node.children("Binary") {
+        warnings.push(format!(
+            "attachment '{}' of '{}' was not imported",
+            binary.text_of("Key").unwrap_or_default(),
+            name
+        ));
+    }
+
+    let times = node.child("Times");
+    let time = |key: &str| times.and_then(|t| t.text_of(key)).and_then(parse_time);
+    if times.and_then(|t| t.text_of("Expires")) == Some("True")
+        && let Some(expiry) = time("ExpiryTime")
+    {
+        entry.set_expiry(expiry.with_timezone(&Local).date_naive());
+    }
+
+    let history = history_from_xml(node, time("LastModificationTime"), &custom_data);
+    // without the date from a round trip, the password changed when the newest older version with
+    // another password was replaced
+    let password_changed = custom_data
+        .get(PASSWORD_CHANGED_KEY)
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|| {
+            history
+                .iter()
+                .find(|version| version.password.as_deref() != entry.password())
+                .map(|version| version.replaced)
+        })
+        .or(time("CreationTime"));
+    entry.set_history(history, history_limit);
+
+    // KeePass sets the access time when the entry is created, that is no use
+    let last_used = time("LastAccessTime").filter(|used| Some(*used) != time("CreationTime"));
+    entry.set_dates(
+        time("CreationTime"),
+        time("LastModificationTime"),
+        password_changed.filter(|_| entry.password().is_some()),
+        last_used,
+    );
+    entry
+}
+
+// Key, value and whether the value was protected
+fn entry_strings(node: &Node) -> Vec<(String, String, bool)> {
+    node.children("String")
+        .filter_map(|string| {
+            let value = string.child("Value")?;
+            Some((
+                string.text_of("Key")?.to_string(),
+                value.text.clone(),
+                value.attribute("Protected") == Some("True"),
+            ))
+        })
+        .collect()
+}
+
+fn custom_fields(
+    strings: &[(String, String, bool)],
+    custom_data: &HashMap<String, String>,
+) -> Vec<Result<CustomField, VaultError>> {
+    strings
+        .iter()
+        .filter(|(key, _, _)| !STANDARD_KEYS.contains(&key.as_str()))
+        .map(|(key, value, protected)| {
+            let stored_type = custom_data
+                .get(&format!("{}{}", FIELD_TYPE_KEY_PREFIX, key))
+                .and_then(|t| <FieldType as clap::ValueEnum>::from_str(t, true).ok());
+            let field_type = match stored_type {
+                Some(field_type) => field_type,
+                None if value.contains('\n') => FieldType::Multiline,
+                None if *protected => FieldType::Hidden,
+                None => FieldType::Text,
+            };
+            CustomField::new(key.clone(), field_type, value.clone())
+        })
+        .collect()
+}
+
+// KDBX lists the versions oldest first, each with the time it was made. The model keeps them
+// newest first with the time they were replaced, i.e. when the next version was made.
+fn history_from_xml(
+    node: &Node,
+    modified: Option<DateTime<Utc>>,
+    custom_data: &HashMap<String, String>,
+) -> Vec<EntryVersion> {
+    let Some(history) = node.child("History") else {
+        return Vec::new();
+    };
+    let versions: Vec<&Node> = history.children("Entry").collect();
+    let made = |version: &Node| {
+        version
+            .child("Times")
+            .and_then(|t| t.text_of("LastModificationTime"))
+            .and_then(parse_time)
+    };
+
+    let mut result = Vec::new();
+    for (i, version) in versions.iter().enumerate() {
+        let strings = entry_strings(version);
+        let get = |key: &str| {
+            strings
+                .iter()
+                .find(|(k, _, _)| k == key)
+                .map(|(_, value, _)| value.clone())
+                .filter(|value| !value.is_empty())
+        };
+        let replaced = match versions.get(i + 1) {
+            Some(next) => made(next),
+            None => modified,
+        };
+        result.push(EntryVersion {
+            replaced: replaced.unwrap_or_else(Utc::now),
+            username: get("UserName"),
+            password: get("Password"),
+            url: get("URL"),
+            notes: get("Notes"),
+            fields: custom_fields(&strings, custom_data)
+                .into_iter()
+                .flatten()
+                .collect(),
+        });
+    }
+    result.reverse();
+    result
+}
+
+// Base64 of the seconds since 0001-01-01 in KDBX 4, ISO 8601 in older files
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if text.contains('-') {
+        return DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|date| date.with_timezone(&Utc));
+    }
+    let bytes: [u8; 8] = BASE64.decode(text).ok()?.try_into().ok()?;
+    DateTime::from_timestamp(i64::from_le_bytes(bytes) - SECONDS_BEFORE_UNIX_EPOCH, 0)
+}
+
+fn format_time(date: DateTime<Utc>) -> String {
+    BASE64.encode((date.timestamp() + SECONDS_BEFORE_UNIX_EPOCH).to_le_bytes())
+}
+
+fn random_uuid() -> String {
+    let mut uuid = [0u8; 16];
+    rand::rng().fill(&mut uuid[..]);
+    BASE64.encode(uuid)
+}
+
+fn vault_to_xml(vault: &Vault, stream: &mut ChaCha20) -> String {
+    let mut tree = GroupTree::default();
+    for entry in vault.get_entries() {
+        let mut group = &mut tree;
+        for segment in entry.get_folder().into_iter().flat_map(|f| f.split('/')) {
+            group = group.groups.entry(segment.to_string()).or_default();
+        }
+        group.entries.push(entry);
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n");
+    xml.push_str("<KeePassFile>\n<Meta>\n");
+    push_element(&mut xml, "Generator", "rustpass");
+    push_element(&mut xml, "DatabaseName", vault.get_name());
+    push_element(
+        &mut xml,
+        "HistoryMaxItems",
+        &vault.settings.history_count.to_string(),
+    );
+    xml.push_str("</Meta>\n<Root>\n");
+    write_group(&mut xml, vault.get_name(), &tree, stream);
+    xml.push_str("</Root>\n</KeePassFile>\n");
+    xml
+}
+
+fn write_group(xml: &mut String, name: &str, group: &GroupTree, stream: &mut ChaCha20) {
+    xml.push_str("<Group>\n");
+    push_element(xml, "UUID", &random_uuid());
+    push_element(xml, "Name", name);
+    for entry in &group.entries {
+        write_entry(xml, entry, stream);
+    }
+    for (name, child) in &group.groups {
+        write_group(xml, name, child, stream);
+    }
+    xml.push_str("</Group>\n");
+}
+
+fn write_entry(xml: &mut String, entry: &Entry, stream: &mut ChaCha20) {
+    let uuid = random_uuid();
+    xml.push_str("<Entry>\n");
+    push_element(xml, "UUID", &uuid);
+    if !entry.get_tags().is_empty() {
+        push_element(xml, "Tags", &entry.get_tags().join(";"));
+    }
+
+    let now = Utc::now();
+    xml.push_str("<Times>\n");
+    let created = entry.created().unwrap_or(now);
+    push_element(xml, "CreationTime", &format_time(created));
+    push_element(
+        xml,
+        "LastModificationTime",
+        &format_time(entry.modified().unwrap_or(now)),
+    );
+    push_element(
+        xml,
+        "LastAccessTime",
+        &format_time(entry.last_used().unwrap_or(created)),
+    );
+    let expiry = entry
+        .expires()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.with_timezone(&Utc));
+    push_element(xml, "ExpiryTime", &format_time(expiry.unwrap_or(now)));
+    push_element(
+        xml,
+        "Expires",
+        if expiry.is_some() { "True" } else { "False" },
+    );
+    push_element(xml, "UsageCount", "0");
+    xml.push_str("</Times>\n");
+
+    let mut custom_data = Vec::new();
+    if let Some(changed) = entry.password_changed() {
+        custom_data.push((PASSWORD_CHANGED_KEY.to_string(), changed.to_rfc3339()));
+    }
+    for field in entry.get_fields() {
+        if !matches!(field.field_type(), FieldType::Text | FieldType::Hidden) {
+            custom_data.push((
+                format!("{}{}", FIELD_TYPE_KEY_PREFIX, field_key(field)),
+                field.field_type().to_string(),
+            ));
+        }
+    }
+    if !custom_data.is_empty() {
+        xml.push_str("<CustomData>\n");
+        for (key, value) in &custom_data {
+            xml.push_str("<Item>");
+            push_element(xml, "Key", key);
+            push_element(xml, "Value", value);
+            xml.push_str("</Item>\n");
+        }
+        xml.push_str("</CustomData>\n");
+    }
+
+    let current = entry.snapshot();
+    write_strings(xml, entry.get_entry_name(), &current, stream);
+    if let Some(totp) = entry.get_totp() {
+        push_string(
+            xml,
+            "otp",
+            &totp.to_uri(entry.get_entry_name()),
+            true,
+            stream,
+        );
+    }
+
+    let history = entry.get_history();
+    if !history.is_empty() {
+        xml.push_str("<History>\n");
+        // oldest first, each version made when the one before it was replaced
+        for (i, version) in history.iter().enumerate().rev() {
+            let made = history
+                .get(i + 1)
+                .map(|older| older.replaced)
+                .unwrap_or(created);
+            xml.push_str("<Entry>\n");
+            push_element(xml, "UUID", &uuid);
+            xml.push_str("<Times>\n");
+            push_element(xml, "CreationTime", &format_time(created));
+            push_element(xml, "LastModificationTime", &format_time(made));
+            push_element(xml, "LastAccessTime", &format_time(made));
+            push_element(xml, "ExpiryTime", &format_time(made));
+            push_element(xml, "Expires", "False");
+            push_element(xml, "UsageCount", "0");
+            xml.push_str("</Times>\n");
+            write_strings(xml, entry.get_entry_name(), version, stream);
+            xml.push_str("</Entry>\n");
+        }
+        xml.push_str("</History>\n");
+    }
+    xml.push_str("</Entry>\n");
+}
+
+fn write_strings(xml: &mut String, title: &str, values: &EntryVersion, stream: &mut ChaCha20) {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    push_string(xml, "Title", title, false, stream);
+    push_string(xml, "UserName", &text(&values.username), false, stream);
+    push_string(xml, "Password", &text(&values.password), true, stream);
+    push_string(xml, "URL", &text(&values.url), false, stream);
+    push_string(xml, "Notes", &text(&values.notes), false, stream);
+    for field in &values.fields {
+        push_string(
+            xml,
+            &field_key(field),
+            field.value(),
+            field.is_hidden(),
+            stream,
+        );
+    }
+}
+
+// Custom fields must not use the key of a standard string
+fn field_key(field: &CustomField) -> String {
+    if STANDARD_KEYS.contains(&field.name()) {
+        format!("{} (field)", field.name())
+    } else {
+        field.name().to_string()
+    }
+}
+
+fn push_string(xml: &mut String, key: &str, value: &str, protected: bool, stream: &mut ChaCha20) {
+    xml.push_str("<String>");
+    push_element(xml, "Key", key);
+    if protected {
+        let mut data = value.as_bytes().to_vec();
+        stream.apply_keystream(&mut data);
+        xml.push_str("<Value Protected=\"True\">");
+        xml.push_str(&BASE64.encode(data));
+        xml.push_str("</Value>");
+    } else {
+        push_element(xml, "Value", value);
+    }
+    xml.push_str("</String>\n");
+}
+
+fn push_element(xml: &mut String, name: &str, text: &str) {
+    xml.push('<');
+    xml.push_str(name);
+    xml.push('>');
+    xml.push_str(&escape(text));
+    xml.push_str("</");
+    xml.push_str(name);
+    xml.push_str(">\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    // Small Argon2 parameters, the tests are about the format and not about the key derivation
+    fn test_vault() -> Vault {
+        let mut vault = Vault::new("Team".to_string());
+        vault.settings.kdf = KdfSettings {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        let mut github = Entry::new(
+            "GitHub".to_string(),
+            Some("alice".to_string()),
+            Some("old-password".to_string()),
+            Some("https://github.com".to_string()),
+            Some("line one\nline <two> & more".to_string()),
+        );
+        let previous = github.snapshot();
+        github.set_password("new-password".to_string());
+        github.keep_version(previous, 10);
+        github.set_folder("work/dev");
+        github.add_tag("prod");
+        github.set_totp(Totp::parse("JBSWY3DPEHPK3PXP").unwrap());
+        github.set_field(
+            CustomField::new("PIN".to_string(), FieldType::Hidden, "1234".to_string()).unwrap(),
+        );
+        github.set_field(
+            CustomField::new(
+                "Recovery".to_string(),
+                FieldType::Email,
+                "backup@example.com".to_string(),
+            )
+            .unwrap(),
+        );
+        github.set_expiry(NaiveDate::from_ymd_opt(2030, 1, 31).unwrap());
+        vault.add_entry(github).unwrap();
+        vault
+            .add_entry(Entry::new("Bare".to_string(), None, None, None, None))
+            .unwrap();
+        vault
+    }
+
+    #[test]
+    fn test_round_trip_keeps_entries() {
+        let vault = test_vault();
+        let password: SecretString = "kdbx-password".into();
+        let bytes = write(&vault, &password, None).unwrap();
+        assert!(is_kdbx(&bytes));
+
+        let imported = read(&bytes, &password, None).unwrap();
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(imported.vault.get_name(), "Team");
+        assert_eq!(imported.vault.get_entries().len(), 2);
+
+        let original = &vault.get_entries()[0];
+        let mut imported_vault = imported.vault;
+        let github = imported_vault
+            .get_entry_by_name(&"GitHub".to_string())
+            .unwrap()
+            .clone();
+        assert_eq!(github.get_entry_name(), "GitHub");
+        assert_eq!(github.password(), Some("new-password"));
+        assert_eq!(github.get_notes(), original.get_notes());
+        assert_eq!(github.get_folder(), Some("work/dev"));
+        assert_eq!(github.get_tags(), original.get_tags());
+        assert_eq!(github.get_totp(), original.get_totp());
+        assert_eq!(github.get_fields(), original.get_fields());
+        assert_eq!(github.expires(), original.expires());
+        assert_eq!(github.get_history().len(), 1);
+        assert_eq!(
+            github.get_history()[0].password.as_deref(),
+            Some("old-password")
+        );
+        // KDBX only stores whole seconds
+        assert_eq!(
+            github.created().map(|d| d.timestamp()),
+            original.created().map(|d| d.timestamp())
+        );
+        assert_eq!(
+            github.password_changed().map(|d| d.timestamp()),
+            original.password_changed().map(|d| d.timestamp())
+        );
+        let bare = imported_vault.get_entry_by_name(&"Bare".to_string());
+        assert_eq!(bare.unwrap().get_folder(), None);
+    }
+
+    #[test]
+    fn test_wrong_password_or_key_file_is_rejected() {
+        let vault = test_vault();
+        let password: SecretString = "kdbx-password".into();
+        let key_file = b"any file works as a key file";
+        let bytes = write(&vault, &password, Some(key_file)).unwrap();
+
+        assert!(read(&bytes, &password, Some(key_file)).is_ok());
+        assert!(matches!(
+            read(&bytes, &password, None),
+            Err(VaultError::InvalidKey)
+        ));
+        assert!(matches!(
+            read(&bytes, &"wrong".into(), Some(key_file)),
+            Err(VaultError::InvalidKey)
+        ));
+
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 40;
+        corrupt[last] ^= 1;
+        assert!(matches!(
+            read(&corrupt, &password, Some(key_file)),
+            Err(VaultError::InvalidImport(_))
+        ));
+    }
+
+    #[test]
+    fn test_keepass_key_files() {
+        let hex_key = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let xml_key = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<KeyFile><Meta><Version>2.0</Version></Meta>\
+             <Key><Data Hash=\"0\">0011 2233 4455 6677 8899 AABB CCDD EEFF\n\
+             0011 2233 4455 6677 8899 AABB CCDD EEFF</Data></Key></KeyFile>";
+        let expected = decode_hex(hex_key).unwrap();
+        assert_eq!(key_file_key(hex_key.as_bytes()).unwrap().to_vec(), expected);
+        assert_eq!(key_file_key(xml_key.as_bytes()).unwrap().to_vec(), expected);
+        assert_eq!(key_file_key(&expected).unwrap().to_vec(), expected);
+        assert_eq!(
+            key_file_key(b"other").unwrap().to_vec(),
+            Sha256::digest(b"other").to_vec()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+/*what belongs here:
+- Key files as a second factor next to the master password
+- Creating, loading and checking key files
+
+A key file can be any file, e.g. one kept on a USB stick. Its SHA-256 digest is appended to the
+master password before the key derivation, so the vault only opens with both.
+The vault header only records that a key file is needed. Nothing derived from the key file is
+stored, so a wrong key file can only be told apart from a wrong password by the Argon2 key
+derivation, like any other guess.
+*/
+
+use enc_file::{HashAlg, hash_bytes, to_hex_lower};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::errors::VaultError;
+
+// Number of random bytes in a generated key file
+const KEY_FILE_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct KeyFile {
+    digest: SecretString,
+}
+
+impl KeyFile {
+    /// Reads an existing key file.
+    pub fn load(path: &Path) -> Result<KeyFile, VaultError> {
+        let contents = fs::read(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => VaultError::KeyFileNotFound(path.to_path_buf()),
+            _ => VaultError::IoError(e),
+        })?;
+        if contents.is_empty() {
+            return Err(VaultError::InvalidKeyFile);
+        }
+        Ok(KeyFile::from_bytes(&contents))
+    }
+
+    /// Writes a new key file with random contents, an existing file is never overwritten.
+    pub fn generate(path: &Path) -> Result<KeyFile, VaultError> {
+        let mut contents = [0u8; KEY_FILE_LEN];
+        rand::rng().fill(&mut contents[..]);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => VaultError::FileExists,
+            _ => VaultError::IoError(e),
+        })?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+
+        Ok(KeyFile::from_bytes(&contents))
+    }
+
+    fn from_bytes(contents: &[u8]) -> KeyFile {
+        KeyFile {
+            digest: to_hex_lower(&hash_bytes(contents, HashAlg::Sha256)).into(),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// The secret the vault is actually encrypted with: the password, plus the key file digest if one is used.
+pub fn combine_with_password(password: &SecretString, key_file: Option<&KeyFile>) -> SecretString {
+    match key_file {
+        Some(key_file) => format!(
+            "{}\u{0}{}",
+            password.expose_secret(),
+            key_file.digest.expose_secret()
+        )
+        .into(),
+        None => password.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.key");
+
+        let generated = KeyFile::generate(&path).unwrap();
+        let loaded = KeyFile::load(&path).unwrap();
+        assert_eq!(
+            generated.digest.expose_secret(),
+            loaded.digest.expose_secret()
+        );
+        assert_eq!(fs::read(&path).unwrap().len(), KEY_FILE_LEN);
+    }
+
+    #[test]
+    fn test_generate_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("existing.key");
+        fs::write(&path, b"keep me").unwrap();
+
+        assert!(matches!(
+            KeyFile::generate(&path),
+            Err(VaultError::FileExists)
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"keep me");
+    }
+
+    #[test]
+    fn test_empty_key_file_is_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.key");
+        fs::write(&path, b"").unwrap();
+
+        assert!(matches!(
+            KeyFile::load(&path),
+            Err(VaultError::InvalidKeyFile)
+        ));
+    }
+
+    #[test]
+    fn test_key_file_changes_the_secret() {
+        let password: SecretString = "password".into();
+        let key_file = KeyFile::from_bytes(b"some key file");
+        let other = KeyFile::from_bytes(b"another key file");
+
+        let combined = combine_with_password(&password, Some(&key_file));
+        assert_ne!(combined.expose_secret(), password.expose_secret());
+        assert_ne!(
+            combined.expose_secret(),
+            combine_with_password(&password, Some(&other)).expose_secret()
+        );
+        assert_eq!(
+            combine_with_password(&password, None).expose_secret(),
+            password.expose_secret()
+        );
+    }
+
+    #[test]
+    fn test_missing_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.key");
+
+        assert!(matches!(
+            KeyFile::load(&path),
+            Err(VaultError::KeyFileNotFound(missing)) if missing == path
+        ));
+    }
+}
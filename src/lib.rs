@@ -1,12 +1,30 @@
+pub mod agent;
+pub mod audit;
+pub mod bitwarden;
+pub mod breach;
 pub mod cli;
+pub mod config;
 pub mod crypto;
 pub mod errors;
+pub mod export;
 pub mod extension_server;
+pub mod generator;
+pub mod import;
+pub mod kdbx;
+pub mod keyfile;
+pub mod merge;
+pub mod oneshot;
+pub mod search;
 pub mod session;
+pub mod totp;
 pub mod vault_entry_manager;
 pub mod vault_file_manager;
+pub mod vault_format;
 
 pub use errors::{SessionError, VaultError};
-pub use session::{Session, active_session, create_new_vault};
-pub use vault_entry_manager::{Entry, Vault};
+pub use session::{
+    Session, SessionHolder, Sessions, active_session, create_new_vault,
+    create_new_vault_with_options, spawn_autolock,
+};
+pub use vault_entry_manager::{CustomField, Entry, FieldType, Vault};
 pub use vault_file_manager::{close_vault, delete_vault_file, get_vault_path, open_vault};
@@ -1,27 +1,33 @@
 use password_manager::*;
 
-use crate::session::Session;
+use crate::audit::AuditOptions;
+use crate::config::ConfigOverrides;
+use crate::session::{Session, Sessions};
 use crate::vault_file_manager::*;
 use clap::Parser;
 use cli::*;
-use rand::Rng;
 use std::io::{self, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 fn main() {
+    // Any arguments run a single command and exit, e.g. `pw get github --copy`.
+    if std::env::args_os().len() > 1 {
+        std::process::exit(oneshot::run(std::env::args_os()));
+    }
+
+    // invalid values in the config file are reported before anything else happens
+    if let Err(e) = config::init(ConfigOverrides::default()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
     intro_animation();
 
-    let current_session = Arc::new(Mutex::new(None::<Session>));
+    let current_session = Arc::new(Mutex::new(Sessions::default()));
 
-    let mut rng = rand::rng();
-    let token: String = (0..32)
-        .map(|_| {
-            let idx = rng.random_range(0..16);
-            format!("{:x}", idx)
-        })
-        .collect();
+    let token = extension_server::generate_token();
     println!(
         "\n🔒 Extension Token (store in extension settings): {}\n",
         token
@@ -33,66 +39,59 @@ fn main() {
     });
 
     // Background thread for AutoLock
-    // just clones the Arc (which is a pointer), not the entire session!
-    let autolock_session_arc = current_session.clone();
-    thread::spawn(move || {
-        loop {
-            thread::sleep(Duration::from_secs(1));
-            if let Ok(mut session_guard) = autolock_session_arc.lock() {
-                if let Some(session) = session_guard.as_mut() {
-                    if session.opened_vault.is_some() {
-                        // Check for timeout (wished_timeout minutes)
-                        if session.check_timeout(Duration::from_secs(session.wished_timeout)) {
-                            let name = session.vault_name.clone();
-                            // Attempt to end session
-                            if session.end_session().is_ok() {
-                                handle_command_clear();
-                                println!(
-                                    "\n\nYou have been logged out. Last used vault was: '{}'.",
-                                    name
-                                );
-                                io::stdout().flush().unwrap();
-                            }
-                        }
-                    } else {
-                        /* Do nothing */
-                    }
-                }
-            } else {
-                // try again
-                continue;
-            }
+    spawn_autolock(current_session.clone(), |name, was_current| {
+        if was_current {
+            handle_command_clear();
+            println!(
+                "\n\nYou have been logged out. Last used vault was: '{}'.",
+                name
+            );
+        } else {
+            println!("\n\nVault '{}' was locked after inactivity.", name);
         }
+        io::stdout().flush().unwrap();
     });
 
+    // Current vault before a command on `vault:entry` switched away from it.
+    let mut switch_back: Option<String> = None;
+
     'interactive_shell: loop {
+        if let Some(name) = switch_back.take()
+            && let Ok(mut sessions) = current_session.lock()
+        {
+            // fails only if it was locked in the meantime
+            let _ = sessions.switch_to(&name);
+        }
+
         println!("___________________");
 
-        if let Ok(session_guard) = current_session.lock() {
+        if let Ok(sessions) = current_session.lock() {
             println!(
                 "Current vault: {}",
-                match &*session_guard {
-                    Some(session) => {
-                        match &session.opened_vault {
-                            Some(v) => v.get_name(),
-                            None => "None",
-                        }
-                    }
-                    None => "None",
-                }
+                sessions.active_name().as_deref().unwrap_or("None")
             );
+            let others = other_unlocked(&sessions);
+            if !others.is_empty() {
+                println!("Also unlocked: {}", others.join(", "));
+            }
         }
         println!("What action do you want to do? ");
 
-        if let Ok(session_guard) = current_session.lock() {
+        if let Ok(sessions) = current_session.lock() {
             if !check_vaults_exist() {
                 eprintln!(
                     "\nHint: There are currently no vaults at all, consider using 'init' to create one!"
                 );
-            } else if !active_session(&session_guard) {
-                eprintln!(
-                    "\nHint: There are currently no vaults open, consider using 'open <vault-name>'!"
-                );
+            } else if !active_session(&sessions.active) {
+                if sessions.unlocked_names().is_empty() {
+                    eprintln!(
+                        "\nHint: There are currently no vaults open, consider using 'open <vault-name>'!"
+                    );
+                } else {
+                    eprintln!(
+                        "\nHint: The current vault is closed, consider using 'use <vault-name>' to switch to an unlocked one!"
+                    );
+                }
             }
         }
 
@@ -118,7 +117,7 @@ fn main() {
         let mut args_with_prog = vec!["pw".to_string()];
         args_with_prog.extend(args);
 
-        let cli = match CLI::try_parse_from(args_with_prog) {
+        let mut cli = match CLI::try_parse_from(args_with_prog) {
             Ok(cli) => cli,
             Err(e) => {
                 println!("Error: {}", e);
@@ -126,14 +125,52 @@ fn main() {
             }
         };
 
-        if let Ok(mut session_guard) = current_session.lock() {
-            if let Some(session) = session_guard.as_mut() {
+        if let Ok(mut sessions_guard) = current_session.lock() {
+            let sessions = &mut *sessions_guard;
+
+            // `vault:entry` runs the command on another unlocked vault, so does restore-backup on one
+            let mut target_vaults = match &mut cli.command {
+                CommandCLI::RestoreBackup { name: Some(name) } if sessions.is_unlocked(name) => {
+                    vec![name.clone()]
+                }
+                command => {
+                    let mut vaults = Vec::new();
+                    for name in command.entry_names_mut() {
+                        if let Some((vault, entry)) = sessions.split_vault_entry(name) {
+                            *name = entry;
+                            vaults.push(vault);
+                        }
+                    }
+                    vaults
+                }
+            };
+            target_vaults.sort();
+            target_vaults.dedup();
+            if target_vaults.len() > 1 {
+                println!("Error: All entries have to be in the same vault!");
+                continue 'interactive_shell;
+            }
+            let target_vault = target_vaults.pop();
+            if let Some(vault) = target_vault
+                && sessions.active_name().as_ref() != Some(&vault)
+            {
+                switch_back = sessions.active_name();
+                let _ = sessions.switch_to(&vault);
+            }
+
+            if let Some(session) = sessions.active.as_mut() {
                 session.update_activity();
             }
+            let session_guard = &mut sessions.active;
 
             match cli.command {
-                CommandCLI::Init { name } => {
-                    match handle_command_init(name) {
+                CommandCLI::Init {
+                    name,
+                    dir,
+                    kdf,
+                    key_file,
+                } => {
+                    match handle_command_init(name, dir, kdf, key_file) {
                         Ok(()) => { /* Do nothing */ }
                         Err(VaultError::NameExists) => {
                             println!();
@@ -154,23 +191,25 @@ fn main() {
                     url,
                     notes,
                     password,
+                    extra,
                 } => {
-                    if !active_session(&session_guard) {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
                     match handle_command_add(
-                        &mut session_guard,
+                        session_guard,
                         name,
                         username,
                         url,
                         notes,
                         password,
+                        extra,
                     ) {
                         Ok(()) => {
-                            try_save(&mut session_guard);
+                            try_save(session_guard);
                         }
                         Err(SessionError::VaultError(VaultError::NoVaultOpen)) => {
                             println!("Error: {}", VaultError::NoVaultOpen);
@@ -183,15 +222,20 @@ fn main() {
                     continue 'interactive_shell;
                 }
 
-                CommandCLI::Get { name, show, copy } => {
-                    if !active_session(&session_guard) {
+                CommandCLI::Get {
+                    name,
+                    show,
+                    clipboard,
+                    folder,
+                } => {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                         continue 'interactive_shell;
                     }
 
-                    match handle_command_get(&mut session_guard, name, show, copy) {
+                    match handle_command_get(session_guard, name, show, clipboard, folder) {
                         Ok(()) => { /* Do nothing */ }
                         Err(SessionError::VaultError(VaultError::NoVaultOpen)) => {
                             println!("No vault is active! Use init or open <vault-name>!");
@@ -206,14 +250,37 @@ fn main() {
                     }
                 }
 
-                CommandCLI::Getall { show } => {
-                    if !active_session(&session_guard) {
+                CommandCLI::Search {
+                    query,
+                    show,
+                    clipboard,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_search(session_guard, query.join(" "), show, clipboard) {
+                        Ok(()) => { /* Do nothing */ }
+                        Err(SessionError::VaultError(VaultError::NoVaultOpen)) => {
+                            println!("No vault is active! Use init or open <vault-name>!");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Getall { show, list } => {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
-                    match handle_command_getall(&mut session_guard, show) {
+                    match handle_command_getall(session_guard, show, &list) {
                         Ok(()) => { /* Do nothing */ }
                         Err(SessionError::VaultError(VaultError::NoVaultOpen)) => {
                             println!("No vault is active! Use init or open <vault-name>!");
@@ -229,16 +296,16 @@ fn main() {
                     }
                 }
 
-                CommandCLI::Delete { name } => {
-                    if !active_session(&session_guard) {
+                CommandCLI::Delete { name, folder } => {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
-                    match handle_command_delete(&mut session_guard, name) {
+                    match handle_command_delete(session_guard, name, folder) {
                         Ok(()) => {
-                            try_save(&mut session_guard);
+                            try_save(session_guard);
                         }
                         Err(SessionError::VaultError(VaultError::NoVaultOpen)) => {
                             println!("No vault is active! Use init or open <vault-name>!");
@@ -252,15 +319,38 @@ fn main() {
                     }
                 }
 
+                CommandCLI::Move {
+                    source,
+                    destination,
+                    folder,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_move(session_guard, source, destination, folder) {
+                        Ok(()) => {
+                            try_save(session_guard);
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
                 CommandCLI::Deletevault {} => {
-                    if !active_session(&session_guard) {
+                    if !active_session(session_guard) {
                         println!("Due to RustPass's logic, you have to open your vault first!");
                         println!("Hint: Consider using open <vault-name>!");
                     }
 
-                    match handle_command_deletevault(&mut session_guard) {
+                    match handle_command_deletevault(session_guard) {
                         Ok(()) => {
                             *session_guard = None;
+                            activate_next(sessions);
                         }
                         Err(SessionError::VaultError(VaultError::AnyhowError(ref e)))
                             if e.to_string() == "Cancelled." =>
@@ -278,27 +368,61 @@ fn main() {
                     }
                 }
 
-                CommandCLI::Generate { length, no_symbols } => {
-                    match handle_command_generate(length, no_symbols) {
-                        Ok(generated_pw) => {
-                            println!("{}", generated_pw)
+                CommandCLI::Totp {
+                    name,
+                    clipboard,
+                    setup,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    let modifies_entry = setup.modifies_entry();
+                    match handle_command_totp(session_guard, name, clipboard, setup) {
+                        Ok(()) => {
+                            if modifies_entry {
+                                try_save(session_guard);
+                            }
+                        }
+                        Err(SessionError::VaultError(VaultError::EntryNotFound)) => {
+                            // name is already printed in cli.rs
                         }
                         Err(e) => {
-                            println!("Error: {}", e)
+                            println!("Error: {}", e);
                         }
                     }
                 }
 
-                CommandCLI::ChangeMaster {} => {
-                    if !active_session(&session_guard) {
+                CommandCLI::Generate {
+                    options,
+                    policy,
+                    clear_after,
+                } => match handle_command_generate(session_guard, options, policy, clear_after) {
+                    Ok(generated_pw) => {
+                        println!("{}", generated_pw)
+                    }
+                    Err(e) => {
+                        println!("Error: {}", e)
+                    }
+                },
+
+                CommandCLI::ChangeMaster {
+                    key_file,
+                    remove_key_file,
+                } => {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
-                    match handle_command_change_master(&mut session_guard) {
+                    match handle_command_change_master(session_guard, key_file, remove_key_file) {
                         Ok(()) => {
                             *session_guard = None;
+                            activate_next(sessions);
                         }
                         Err(e) => {
                             println!("Error: {}", e);
@@ -307,16 +431,16 @@ fn main() {
                 }
 
                 CommandCLI::Edit { name } => {
-                    if !active_session(&session_guard) {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
-                    match handle_command_edit(&mut session_guard, name) {
+                    match handle_command_edit(session_guard, name) {
                         Ok(()) => {
                             /* Save, even though vault did not change, just to be sure. */
-                            try_save(&mut session_guard);
+                            try_save(session_guard);
                         }
                         Err(e) => {
                             println!("Error: {}", e)
@@ -324,33 +448,200 @@ fn main() {
                     }
                 }
 
-                CommandCLI::Open { name, timeout } => {
-                    match handle_command_open(name, &mut session_guard, &timeout) {
+                CommandCLI::History { name, show } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    if let Err(e) = handle_command_history(session_guard, name, show) {
+                        println!("Error: {}", e);
+                    }
+                }
+
+                CommandCLI::Import {
+                    file,
+                    format,
+                    key_file,
+                    on_conflict,
+                    dry_run,
+                    yes,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_import(
+                        session_guard,
+                        file,
+                        format,
+                        key_file,
+                        on_conflict,
+                        dry_run,
+                        yes,
+                    ) {
+                        Ok(()) => {
+                            if !dry_run {
+                                try_save(session_guard);
+                            }
+                        }
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            println!("\nImport cancelled.");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Merge {
+                    file,
+                    key_file,
+                    show,
+                    dry_run,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_merge(session_guard, file, key_file, show, dry_run) {
+                        Ok(true) => try_save(session_guard),
+                        Ok(false) => {}
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            println!("\nMerge cancelled.");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Audit {
+                    max_age,
+                    min_score,
+                    breach_db,
+                    json,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    let options = AuditOptions {
+                        min_score,
+                        max_age_days: max_age,
+                        breach_db,
+                    };
+                    if let Err(e) = handle_command_audit(session_guard, options, json) {
+                        println!("Error: {}", e);
+                    }
+                }
+
+                CommandCLI::Export {
+                    file,
+                    format,
+                    key_file,
+                    selection,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_export(session_guard, file, format, key_file, selection) {
+                        Ok(()) => {}
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            println!("\nExport cancelled.");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Restore { name, version } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_restore(session_guard, name, version) {
+                        Ok(()) => {
+                            try_save(session_guard);
+                        }
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            println!("\nRestore cancelled.");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Open {
+                    name,
+                    file,
+                    timeout,
+                    key_file,
+                } => match vault_name_or_file(name, file) {
+                    Ok(name) if sessions.active_name().as_ref() == Some(&name) => {
+                        println!();
+                        println!("Error opening vault: Vault '{}' already opened!", name);
+                    }
+                    // unlocked in the background, no need to ask for the password again
+                    Ok(name) if sessions.is_unlocked(&name) => {
+                        if let Err(e) = handle_command_use(sessions, name) {
+                            println!("Error: {}", e);
+                        }
+                    }
+                    name => match name
+                        .map_err(SessionError::VaultError)
+                        .and_then(|name| handle_command_open(name, &timeout, key_file))
+                    {
                         Ok(session) => {
                             if session.opened_vault.is_none() {
                                 println!("Something went wrong!");
                             }
-                            *session_guard = Some(session);
+                            sessions.activate(session);
                         }
                         Err(SessionError::VaultError(VaultError::InvalidKey)) => {
                             println!("Error: Invalid password!")
                         }
+                        Err(SessionError::VaultError(VaultError::KeyFileRequired)) => {
+                            println!("Error: {}", VaultError::KeyFileRequired);
+                            println!("Hint: Use open <vault-name> --key-file <path>!");
+                        }
                         Err(e) => {
                             println!("Error opening vault: {}", e);
                         }
-                    }
-                }
+                    },
+                },
                 CommandCLI::Close { force } => {
-                    if !active_session(&session_guard) {
+                    if !active_session(session_guard) {
                         println!(
                             "There is no session active right now, consider using open <vault-name>!"
                         );
                     }
 
-                    match handle_command_close(&mut session_guard, force) {
+                    match handle_command_close(session_guard, force) {
                         Ok(LoopCommand::Continue) => {
                             // if the user says yes to closing.
                             *session_guard = None;
+                            activate_next(sessions);
                         }
                         Ok(LoopCommand::Cancel) => { /* Do nothing */ }
                         Err(e) => {
@@ -359,8 +650,145 @@ fn main() {
                     }
                 }
 
+                CommandCLI::Use { name } => {
+                    if let Err(e) = handle_command_use(sessions, name) {
+                        println!("Error: {}", e);
+                    }
+                }
+
+                CommandCLI::CopyEntry {
+                    names,
+                    to,
+                    on_conflict,
+                } => {
+                    if let Err(e) = handle_command_copy_entry(sessions, names, to, on_conflict) {
+                        println!("Error: {}", e);
+                    }
+                }
+
+                CommandCLI::MoveEntry {
+                    names,
+                    to,
+                    on_conflict,
+                } => {
+                    if let Err(e) = handle_command_move_entry(sessions, names, to, on_conflict) {
+                        println!("Error: {}", e);
+                    }
+                }
+
                 CommandCLI::Vaults {} => {
-                    handle_command_vaults(&session_guard);
+                    handle_command_vaults(
+                        sessions.active_name().as_deref(),
+                        &other_unlocked(sessions),
+                    );
+                }
+
+                CommandCLI::Rekdf { kdf } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_rekdf(session_guard, kdf) {
+                        Ok(()) => {
+                            try_save(session_guard);
+                        }
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            /* Nothing to change */
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::RestoreBackup { name } => {
+                    match handle_command_restore_backup(session_guard, name) {
+                        Ok(()) => {
+                            // the restored vault was closed if it was unlocked
+                            if sessions.active.is_none() && switch_back.is_none() {
+                                activate_next(sessions);
+                            }
+                        }
+                        Err(SessionError::VaultError(VaultError::ActionCancelled)) => {
+                            println!("\nRestore cancelled.");
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Settings {
+                    backups,
+                    history,
+                    clipboard_timeout,
+                } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    match handle_command_settings(
+                        session_guard,
+                        backups,
+                        history,
+                        clipboard_timeout,
+                    ) {
+                        Ok(()) => {
+                            if backups.is_some() || history.is_some() || clipboard_timeout.is_some()
+                            {
+                                try_save(session_guard);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Policy { action } => {
+                    if !active_session(session_guard) {
+                        println!(
+                            "There is no session active right now, consider using open <vault-name>!"
+                        );
+                        continue 'interactive_shell;
+                    }
+
+                    let modifies_vault = !matches!(action, PolicyCommand::List {});
+                    match handle_command_policy(session_guard, action) {
+                        Ok(()) => {
+                            if modifies_vault {
+                                try_save(session_guard);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                        }
+                    }
+                }
+
+                CommandCLI::Agent { action } => {
+                    // serving would block the shell until the agent stops
+                    if matches!(action, AgentCommand::Serve {}) {
+                        println!(
+                            "Error: 'agent serve' is only available from the command line, use 'agent start' instead!"
+                        );
+                        continue 'interactive_shell;
+                    }
+                    if let Err(e) = handle_command_agent(action, None) {
+                        println!("Error: {}", e);
+                    }
+                }
+
+                CommandCLI::Config { action } => {
+                    if let Err(e) = handle_command_config(action) {
+                        println!("Error: {}", e);
+                    }
                 }
 
                 CommandCLI::Clear {} => {
@@ -370,21 +798,15 @@ fn main() {
                 CommandCLI::Quit { force } => {
                     match handle_command_quit(force) {
                         Ok(LoopCommand::Continue) => {
-                            if let Some(session) = &mut *session_guard {
-                                match session.end_session() {
-                                    Ok(()) => { /* Do nothing */ }
-                                    Err(SessionError::SessionInactive) => { /* Ignore */ }
-                                    Err(e) => {
-                                        println!("Error: {}", e);
-
-                                        // Continue needs to be called exactly here -> updating activity here
-                                        if let Some(session) = session_guard.as_mut() {
-                                            session.update_activity();
-                                        }
-
-                                        continue 'interactive_shell;
-                                    }
+                            if let Err(e) = sessions.end_all() {
+                                println!("Error: {}", e);
+
+                                // Continue needs to be called exactly here -> updating activity here
+                                if let Some(session) = sessions.active.as_mut() {
+                                    session.update_activity();
                                 }
+
+                                continue 'interactive_shell;
                             }
                             break 'interactive_shell;
                         }
@@ -398,7 +820,7 @@ fn main() {
                 }
             }
 
-            if let Some(session) = session_guard.as_mut() {
+            if let Some(session) = sessions.active.as_mut() {
                 session.update_activity();
             }
 
@@ -407,6 +829,23 @@ fn main() {
     }
 }
 
+// Names of the unlocked vaults besides the current one.
+fn other_unlocked(sessions: &Sessions) -> Vec<String> {
+    let current = sessions.active_name();
+    sessions
+        .unlocked_names()
+        .into_iter()
+        .filter(|name| Some(name) != current.as_ref())
+        .collect()
+}
+
+// After the current vault was closed the most recently used unlocked one takes its place.
+fn activate_next(sessions: &mut Sessions) {
+    if let Some(name) = sessions.activate_most_recent() {
+        println!("Current vault is now '{}'.", name);
+    }
+}
+
 fn try_save(current_session: &mut Option<Session>) {
     if let Some(session) = current_session {
         let spinner = spinner();
@@ -0,0 +1,500 @@
+/*what belongs here:
+- Merging two copies of a vault that were changed independently, e.g. on two laptops
+- Matching entries by their id, so renamed entries are still recognised
+
+Values with a history (username, password, URL, notes, custom fields) are merged three-way: the
+newest version both copies share is the common ancestor, a value changed in only one copy is
+taken from that copy. If both copies changed a value differently or there is no common version,
+it is a conflict. Name, folder, tags, TOTP and expiry have no history, entries record when they
+were changed instead. The newest change both copies know is the common point, a value changed
+after it in only one copy is taken from that copy, changed in both it is a conflict. Deletions
+are known from the ids the vault records for deleted entries, an entry that was changed after it
+was deleted in the other copy is a conflict.
+*/
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+use crate::vault_entry_manager::{Entry, EntryVersion, Vault};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    // the opened vault
+    Local,
+    // the copy merged into it
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub enum MergeItem {
+    // same in both copies, or only changed in the opened vault
+    Keep(Entry),
+    // values taken from the other copy
+    Update {
+        entry: Entry,
+        changed: Vec<&'static str>,
+    },
+    // only in the other copy
+    Add(Entry),
+    // deleted in the other copy and not changed here since
+    Delete(Entry),
+    Conflict(Box<MergeConflict>),
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    pub name: String,
+    // values both copies changed differently, empty if one copy deleted the entry
+    pub values: Vec<&'static str>,
+    // the merged entry if the conflict is resolved for the opened vault or the other copy,
+    // None if the entry is deleted then
+    pub keep_local: Option<Entry>,
+    pub keep_other: Option<Entry>,
+    // the copy whose entry was modified last
+    pub suggested: Side,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergePlan {
+    // entries of the opened vault first, then those only in the other copy
+    pub items: Vec<MergeItem>,
+    // deleted ids of both copies
+    pub removed: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl MergeConflict {
+    pub fn resolve(self, side: Side) -> Option<Entry> {
+        match side {
+            Side::Local => self.keep_local,
+            Side::Other => self.keep_other,
+        }
+    }
+}
+
+impl MergePlan {
+    pub fn conflicts(&self) -> impl Iterator<Item = &MergeConflict> {
+        self.items.iter().filter_map(|item| match item {
+            MergeItem::Conflict(conflict) => Some(conflict.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Whether merging changes the opened vault, also if only histories, dates or the
+    /// deleted ids of the other copy are merged into it.
+    pub fn changes_vault(&self, vault: &Vault) -> bool {
+        self.removed != vault.removed
+            || self.items.iter().any(|item| match item {
+                MergeItem::Keep(entry) => !vault.entries.contains(entry),
+                _ => true,
+            })
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Decides for every entry of both copies what the merged vault contains.
+pub fn plan_merge(local: &Vault, other: &Vault) -> MergePlan {
+    let limit = local.settings.history_count;
+    let mut items = Vec::new();
+
+    for entry in &local.entries {
+        let item = match other.entries.iter().find(|o| o.id() == entry.id()) {
+            Some(other_entry) => merge_entry(entry, other_entry, limit),
+            None => match other.removed.get(entry.id()) {
+                Some(removed) if entry.modified().is_none_or(|m| m <= *removed) => {
+                    MergeItem::Delete(entry.clone())
+                }
+                Some(_) => MergeItem::Conflict(Box::new(MergeConflict {
+                    name: entry.get_entry_name().clone(),
+                    values: Vec::new(),
+                    keep_local: Some(entry.clone()),
+                    keep_other: None,
+                    suggested: Side::Local,
+                })),
+                None => MergeItem::Keep(entry.clone()),
+            },
+        };
+        items.push(item);
+    }
+
+    for entry in &other.entries {
+        if local.entries.iter().any(|l| l.id() == entry.id()) {
+            continue;
+        }
+        match local.removed.get(entry.id()) {
+            // deleted here and not changed in the other copy since, it stays deleted
+            Some(removed) if entry.modified().is_none_or(|m| m <= *removed) => {}
+            Some(_) => items.push(MergeItem::Conflict(Box::new(MergeConflict {
+                name: entry.get_entry_name().clone(),
+                values: Vec::new(),
+                keep_local: None,
+                keep_other: Some(entry.clone()),
+                suggested: Side::Other,
+            }))),
+            None => items.push(MergeItem::Add(entry.clone())),
+        }
+    }
+
+    let mut removed = local.removed.clone();
+    for (id, when) in &other.removed {
+        let known = removed.entry(id.clone()).or_insert(*when);
+        *known = (*known).max(*when);
+    }
+    MergePlan { items, removed }
+}
+
+/// Writes the merged entries into the vault. Conflicts are resolved with `choices` in the order
+/// of `MergePlan::conflicts`, missing choices take the suggested side. Entries whose name is
+/// taken by another entry are renamed, e.g. 'GitHub (2)'. Returns the renamed entries.
+pub fn apply_merge(vault: &mut Vault, plan: MergePlan, choices: &[Side]) -> Vec<(String, String)> {
+    let mut choices = choices.iter();
+    let mut removed = plan.removed;
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut renamed = Vec::new();
+
+    for item in plan.items {
+        let entry = match item {
+            MergeItem::Keep(entry) | MergeItem::Update { entry, .. } | MergeItem::Add(entry) => {
+                entry
+            }
+            MergeItem::Delete(_) => continue,
+            MergeItem::Conflict(conflict) => {
+                let id = conflict
+                    .keep_local
+                    .as_ref()
+                    .or(conflict.keep_other.as_ref())
+                    .map(|entry| entry.id().to_string())
+                    .unwrap_or_default();
+                let side = choices.next().copied().unwrap_or(conflict.suggested);
+                match conflict.resolve(side) {
+                    Some(entry) => entry,
+                    None => {
+                        removed.entry(id).or_insert_with(Utc::now);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let mut entry = entry;
+        let name = entry.get_entry_name().clone();
+        if entries.iter().any(|e| *e.get_entry_name() == name) {
+            let new_name = (2..)
+                .map(|n| format!("{} ({})", name, n))
+                .find(|candidate| !entries.iter().any(|e| e.get_entry_name() == candidate))
+                .expect("some number is free");
+            entry.rename(new_name.clone());
+            renamed.push((name, new_name));
+        }
+        removed.remove(entry.id());
+        entries.push(entry);
+    }
+
+    vault.entries = entries;
+    vault.removed = removed;
+    renamed
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+// Both copies of the entry with the same id.
+fn merge_entry(local: &Entry, other: &Entry, limit: usize) -> MergeItem {
+    let mine = local.snapshot();
+    let theirs = other.snapshot();
+    let mut merged = local.clone();
+    merged.merge_history(other, limit);
+    merged.merge_dates(other);
+
+    let mut changed: Vec<&'static str> = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut metadata_conflicts = Vec::new();
+    for value in local.changed_metadata(other) {
+        match changed_since_common(local.metadata_changes(value), other.metadata_changes(value)) {
+            (false, true) => {
+                merged.take_value(value, other);
+                changed.push(value);
+            }
+            (true, false) => { /* only changed here */ }
+            // changed in both copies, or by a version that did not record the changes yet
+            _ => metadata_conflicts.push(value),
+        }
+    }
+
+    let differing = mine.changed_values(&theirs);
+    if !differing.is_empty() {
+        let base = common_ancestor(local, other);
+        for value in differing {
+            match &base {
+                Some(base) if !base.changed_values(&mine).contains(&value) => {
+                    merged.take_value(value, other);
+                    changed.push(value);
+                }
+                Some(base) if !base.changed_values(&theirs).contains(&value) => {
+                    /* only changed here */
+                }
+                _ => conflicts.push(value),
+            }
+        }
+    }
+
+    if conflicts.is_empty() && metadata_conflicts.is_empty() {
+        let merged = keep_replaced_values(merged, local, other, limit);
+        return if changed.is_empty() {
+            MergeItem::Keep(merged)
+        } else {
+            MergeItem::Update {
+                entry: merged,
+                changed,
+            }
+        };
+    }
+
+    let mut keep_other = merged.clone();
+    for value in &conflicts {
+        keep_other.take_value(value, other);
+    }
+    for value in &metadata_conflicts {
+        keep_other.take_value(value, other);
+        keep_other.resolve_metadata(value, local);
+        merged.resolve_metadata(value, other);
+    }
+    conflicts.extend(metadata_conflicts);
+    MergeItem::Conflict(Box::new(MergeConflict {
+        name: local.get_entry_name().clone(),
+        values: conflicts,
+        keep_local: Some(keep_replaced_values(merged, local, other, limit)),
+        keep_other: Some(keep_replaced_values(keep_other, local, other, limit)),
+        suggested: if other.modified() > local.modified() {
+            Side::Other
+        } else {
+            Side::Local
+        },
+    }))
+}
+
+// Whether the opened vault and the other copy changed a value without history since the newest
+// change both know of, from the change times of both (newest first).
+fn changed_since_common(local: &[DateTime<Utc>], other: &[DateTime<Utc>]) -> (bool, bool) {
+    let common = local.iter().find(|change| other.contains(change));
+    (local.first() != common, other.first() != common)
+}
+
+// The newest version of the history values both copies share.
+fn common_ancestor(local: &Entry, other: &Entry) -> Option<EntryVersion> {
+    let mine = local.snapshot();
+    let theirs = other.snapshot();
+    // one copy only continued where the other one stopped
+    if other.get_history().iter().any(|v| v.same_values(&mine)) {
+        return Some(mine);
+    }
+    if local.get_history().iter().any(|v| v.same_values(&theirs)) {
+        return Some(theirs);
+    }
+    local
+        .get_history()
+        .iter()
+        .find(|v| other.get_history().iter().any(|w| w.same_values(v)))
+        .cloned()
+}
+
+// Values of the opened vault that were replaced stay in the history, unless it has them already.
+fn keep_replaced_values(mut merged: Entry, local: &Entry, other: &Entry, limit: usize) -> Entry {
+    let mine = local.snapshot();
+    if !other.get_history().iter().any(|v| v.same_values(&mine)) {
+        merged.keep_version(mine, limit);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A vault with one entry and a copy of it, as if the file was copied to another laptop.
+    fn vault_and_copy() -> (Vault, Vault) {
+        let mut vault = Vault::new("test".to_string());
+        vault
+            .add_entry(Entry::new(
+                "GitHub".to_string(),
+                Some("alice".to_string()),
+                Some("old-password".to_string()),
+                Some("https://github.com".to_string()),
+                None,
+            ))
+            .unwrap();
+        let copy = vault.clone();
+        (vault, copy)
+    }
+
+    fn edit(vault: &mut Vault, name: &str, change: impl FnOnce(&mut Entry)) {
+        let limit = vault.settings.history_count;
+        let entry = vault.get_entry_by_name(&name.to_string()).unwrap();
+        let previous = entry.snapshot();
+        change(entry);
+        entry.keep_version(previous, limit);
+    }
+
+    #[test]
+    fn test_changes_of_both_copies_are_merged() {
+        let (mut local, mut other) = vault_and_copy();
+        edit(&mut local, "GitHub", |e| e.set_username("bob".to_string()));
+        edit(&mut other, "GitHub", |e| {
+            e.set_url("https://github.com/login".to_string())
+        });
+
+        let plan = plan_merge(&local, &other);
+        assert_eq!(plan.conflicts().count(), 0);
+        assert!(
+            matches!(&plan.items[0], MergeItem::Update { changed, .. } if *changed == vec!["url"])
+        );
+
+        apply_merge(&mut local, plan, &[]);
+        let entry = &local.entries[0];
+        assert_eq!(entry.username(), Some("bob"));
+        assert_eq!(entry.url(), Some("https://github.com/login"));
+        assert_eq!(entry.password(), Some("old-password"));
+    }
+
+    #[test]
+    fn test_same_value_changed_in_both_copies_is_a_conflict() {
+        let (mut local, mut other) = vault_and_copy();
+        edit(&mut local, "GitHub", |e| e.set_password("mine".to_string()));
+        edit(&mut other, "GitHub", |e| {
+            e.set_password("theirs".to_string())
+        });
+
+        let plan = plan_merge(&local, &other);
+        let conflicts: Vec<_> = plan.conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].values, vec!["password"]);
+        assert_eq!(conflicts[0].suggested, Side::Other);
+
+        apply_merge(&mut local, plan, &[Side::Other]);
+        let entry = &local.entries[0];
+        assert_eq!(entry.password(), Some("theirs"));
+        // the replaced password is kept in the history
+        assert!(
+            entry
+                .get_history()
+                .iter()
+                .any(|v| v.password.as_deref() == Some("mine"))
+        );
+    }
+
+    #[test]
+    fn test_values_without_history_are_merged_one_by_one() {
+        let (mut local, mut other) = vault_and_copy();
+        let totp = crate::totp::Totp::parse("JBSWY3DPEHPK3PXP").unwrap();
+        edit(&mut local, "GitHub", |e| e.set_totp(totp.clone()));
+        // modified later, but only the tags
+        edit(&mut other, "GitHub", |e| e.add_tag("dev"));
+
+        let plan = plan_merge(&local, &other);
+        assert_eq!(plan.conflicts().count(), 0);
+        apply_merge(&mut local, plan, &[]);
+        let entry = &local.entries[0];
+        assert_eq!(entry.get_totp().as_ref(), Some(&totp));
+        assert_eq!(entry.get_tags(), &vec!["dev".to_string()]);
+    }
+
+    #[test]
+    fn test_totp_changed_in_both_copies_is_a_conflict() {
+        let (mut local, mut other) = vault_and_copy();
+        let mine = crate::totp::Totp::parse("JBSWY3DPEHPK3PXP").unwrap();
+        let theirs = crate::totp::Totp::parse("GEZDGNBVGY3TQOJQ").unwrap();
+        edit(&mut local, "GitHub", |e| e.set_totp(mine.clone()));
+        edit(&mut other, "GitHub", |e| e.set_totp(theirs));
+
+        let plan = plan_merge(&local, &other);
+        let conflicts: Vec<_> = plan.conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].values, vec!["totp"]);
+
+        apply_merge(&mut local, plan, &[Side::Local]);
+        assert_eq!(local.entries[0].get_totp().as_ref(), Some(&mine));
+        // resolved, merging the same copy again keeps the choice
+        let plan = plan_merge(&local, &other);
+        assert_eq!(plan.conflicts().count(), 0);
+        assert!(!plan.changes_vault(&local));
+    }
+
+    #[test]
+    fn test_deletions_alone_change_the_vault() {
+        let (local, mut other) = vault_and_copy();
+        other
+            .add_entry(Entry::new("Temp".to_string(), None, None, None, None))
+            .unwrap();
+        other.remove_entry_by_name(&"Temp".to_string());
+
+        let plan = plan_merge(&local, &other);
+        assert!(
+            plan.items
+                .iter()
+                .all(|item| matches!(item, MergeItem::Keep(_)))
+        );
+        assert!(plan.changes_vault(&local));
+    }
+
+    #[test]
+    fn test_entries_are_matched_by_id() {
+        let (mut local, mut other) = vault_and_copy();
+        // renamed in the other copy, and a new entry that takes the old name
+        edit(&mut other, "GitHub", |e| {
+            e.rename("GitHub work".to_string())
+        });
+        other
+            .add_entry(Entry::new("GitHub".to_string(), None, None, None, None))
+            .unwrap();
+        local
+            .add_entry(Entry::new(
+                "GitHub work".to_string(),
+                None,
+                None,
+                None,
+                None,
+            ))
+            .unwrap();
+
+        let plan = plan_merge(&local, &other);
+        assert!(
+            matches!(&plan.items[0], MergeItem::Update { changed, .. } if *changed == vec!["name"])
+        );
+        assert!(matches!(&plan.items[2], MergeItem::Add(_)));
+
+        let renamed = apply_merge(&mut local, plan, &[]);
+        let names: Vec<_> = local.entries.iter().map(Entry::get_entry_name).collect();
+        assert_eq!(names, vec!["GitHub work", "GitHub work (2)", "GitHub"]);
+        assert_eq!(renamed.len(), 1);
+    }
+
+    #[test]
+    fn test_deletions() {
+        let (mut local, mut other) = vault_and_copy();
+        local
+            .add_entry(Entry::new("Mail".to_string(), None, None, None, None))
+            .unwrap();
+        other.entries = local.entries.clone();
+
+        // deleted in the other copy, unchanged here: deleted
+        other.remove_entry_by_name(&"GitHub".to_string());
+        // deleted here, but changed in the other copy afterwards: a conflict
+        local.remove_entry_by_name(&"Mail".to_string());
+        edit(&mut other, "Mail", |e| {
+            e.set_notes("still needed".to_string())
+        });
+
+        let plan = plan_merge(&local, &other);
+        assert!(matches!(&plan.items[0], MergeItem::Delete(_)));
+        let conflict = plan.conflicts().next().unwrap();
+        assert!(conflict.keep_local.is_none());
+
+        apply_merge(&mut local, plan, &[Side::Other]);
+        let names: Vec<_> = local.entries.iter().map(Entry::get_entry_name).collect();
+        assert_eq!(names, vec!["Mail"]);
+        assert_eq!(local.removed.len(), 1);
+    }
+}
@@ -0,0 +1,654 @@
+/*what belongs here:
+- Running a single command from the shell (`pw get github --copy`) without the REPL
+- Resolving which vault to use and where the master password comes from
+- Routing entry commands through the agent, if it holds the vault unlocked
+
+The process exits with 0 on success, 1 if the command failed and 2 on invalid arguments.
+*/
+
+use crate::agent::{self, AgentAction, AgentResponse, EntryUpdate};
+use crate::audit::AuditOptions;
+use crate::cli::*;
+use crate::config::{self, ConfigOverrides};
+use crate::errors::{SessionError, VaultError};
+use crate::keyfile::KeyFile;
+use crate::session::Session;
+use crate::vault_entry_manager::{Entry, Vault};
+use crate::vault_file_manager::{list_vaults, vault_exists};
+
+use anyhow::anyhow;
+use clap::Parser;
+use secrecy::SecretString;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const VAULT_ENV: &str = "PW_VAULT";
+pub const MASTER_PASSWORD_ENV: &str = "PW_MASTER_PASSWORD";
+pub const KEY_FILE_ENV: &str = "PW_KEY_FILE";
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Parses `args` (including the program name), runs the command once and returns the exit code.
+pub fn run<I, T>(args: I) -> i32
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = match OneShotCLI::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return e.exit_code();
+        }
+    };
+
+    let overrides = ConfigOverrides {
+        config_file: cli.config.clone(),
+        vault_dir: cli.vault_dir.clone(),
+    };
+    if let Err(e) = config::init(overrides) {
+        eprintln!("Error: {}", e);
+        return 1;
+    }
+
+    match execute(cli) {
+        Ok(()) => 0,
+        Err(SessionError::VaultError(VaultError::InvalidKey)) => {
+            eprintln!("Error: Invalid password!");
+            1
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+/// Picks the vault for a one-shot command: `--vault`, then `$PW_VAULT`, then the only existing vault.
+/// A path to a `.psdb` file works as well, e.g. `--vault /mnt/usb/work.psdb`.
+pub fn resolve_vault_name(requested: Option<String>) -> Result<String, VaultError> {
+    if let Some(name) = requested.or_else(|| std::env::var(VAULT_ENV).ok()) {
+        if name.ends_with(".psdb") {
+            return vault_name_or_file(None, Some(PathBuf::from(name)));
+        }
+        if !vault_exists(&name)? {
+            return Err(VaultError::VaultDoesNotExist);
+        }
+        return Ok(name);
+    }
+
+    let mut vaults = list_vaults()?;
+    match vaults.len() {
+        0 => Err(VaultError::VaultDoesNotExist),
+        1 => Ok(vaults.remove(0)),
+        _ => Err(VaultError::AnyhowError(anyhow!(
+            "Several vaults exist, choose one with --vault <name> or ${}",
+            VAULT_ENV
+        ))),
+    }
+}
+
+/// Reads the master password from the given fd, `$PW_MASTER_PASSWORD` or a TTY prompt (in that order).
+pub fn read_master_password(
+    vault_name: &str,
+    password_fd: Option<i32>,
+) -> Result<SecretString, VaultError> {
+    if let Some(fd) = password_fd {
+        return read_password_from_fd(fd);
+    }
+    if let Ok(password) = std::env::var(MASTER_PASSWORD_ENV) {
+        return Ok(password.into());
+    }
+    let password =
+        rpassword::prompt_password(format!("Enter master password for '{}': ", vault_name))?;
+    Ok(password.into())
+}
+
+/// Key file for vaults that require one, taken from `$PW_KEY_FILE`.
+pub fn key_file_path() -> Option<PathBuf> {
+    std::env::var_os(KEY_FILE_ENV).map(PathBuf::from)
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn execute(cli: OneShotCLI) -> Result<(), SessionError> {
+    match cli.command {
+        CommandCLI::Init {
+            name,
+            dir,
+            kdf,
+            key_file,
+        } => handle_command_init(name, dir, kdf, key_file).map_err(SessionError::VaultError),
+
+        // Policies are stored in the vault, only then it has to be opened.
+        CommandCLI::Generate {
+            options,
+            policy: None,
+            clear_after,
+        } => {
+            handle_command_generate(&None, options, None, clear_after)?;
+            wait_for_clipboard_clear();
+            Ok(())
+        }
+
+        CommandCLI::Vaults {} => {
+            handle_command_vaults(None, &[]);
+            Ok(())
+        }
+
+        CommandCLI::Config { action } => {
+            handle_command_config(action).map_err(SessionError::VaultError)
+        }
+
+        CommandCLI::Agent { action } => {
+            handle_command_agent(action, cli.password_fd).map_err(SessionError::VaultError)
+        }
+
+        // Restoring only swaps files, the master password is not needed.
+        CommandCLI::RestoreBackup { name } => {
+            let vault_name = match name {
+                Some(name) => name,
+                None => resolve_vault_name(cli.vault).map_err(SessionError::VaultError)?,
+            };
+            handle_command_restore_backup(&mut None, Some(vault_name))
+        }
+
+        CommandCLI::Open { .. }
+        | CommandCLI::Close { .. }
+        | CommandCLI::Use { .. }
+        | CommandCLI::CopyEntry { .. }
+        | CommandCLI::MoveEntry { .. }
+        | CommandCLI::Clear {}
+        | CommandCLI::Quit { .. } => {
+            Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+                "This command is only available in the interactive shell (run 'pw' without arguments)"
+            ))))
+        }
+
+        command => {
+            if supported_by_agent(&command)
+                && let Some(vault_name) = agent::unlocked_vault()
+                && cli
+                    .vault
+                    .as_ref()
+                    .is_none_or(|requested| *requested == vault_name)
+            {
+                return run_via_agent(vault_name, command);
+            }
+
+            let vault_name = resolve_vault_name(cli.vault).map_err(SessionError::VaultError)?;
+            let key_file = key_file_path()
+                .map(|path| KeyFile::load(&path))
+                .transpose()
+                .map_err(SessionError::VaultError)?;
+            let master = read_master_password(&vault_name, cli.password_fd)
+                .map_err(SessionError::VaultError)?;
+
+            let mut session = Session::new(vault_name);
+            session.start_session_with_key_file(master, key_file)?;
+            let mut current_session = Some(session);
+
+            run_vault_command(&mut current_session, command)
+        }
+    }
+}
+
+// Runs a command that needs an unlocked vault and writes the vault back if it was changed.
+fn run_vault_command(
+    current_session: &mut Option<Session>,
+    command: CommandCLI,
+) -> Result<(), SessionError> {
+    let modifies_vault = match &command {
+        CommandCLI::Add { .. }
+        | CommandCLI::Delete { .. }
+        | CommandCLI::Edit { .. }
+        | CommandCLI::Restore { .. }
+        | CommandCLI::Move { .. }
+        | CommandCLI::Rekdf { .. } => true,
+        // copying records when the entry was last used
+        CommandCLI::Get { clipboard, .. } | CommandCLI::Search { clipboard, .. } => clipboard.copy,
+        CommandCLI::Settings {
+            backups,
+            history,
+            clipboard_timeout,
+        } => backups.is_some() || history.is_some() || clipboard_timeout.is_some(),
+        CommandCLI::Policy { action } => !matches!(action, PolicyCommand::List {}),
+        CommandCLI::Import { dry_run, .. } | CommandCLI::Merge { dry_run, .. } => !*dry_run,
+        CommandCLI::Totp { setup, .. } => setup.modifies_entry(),
+        _ => false,
+    };
+
+    match command {
+        CommandCLI::Add {
+            name,
+            username,
+            url,
+            notes,
+            password,
+            extra,
+        } => handle_command_add(current_session, name, username, url, notes, password, extra)?,
+        CommandCLI::Get {
+            name,
+            show,
+            clipboard,
+            folder,
+        } => handle_command_get(current_session, name, show, clipboard, folder)?,
+        CommandCLI::Search {
+            query,
+            show,
+            clipboard,
+        } => handle_command_search(current_session, query.join(" "), show, clipboard)?,
+        CommandCLI::Getall { show, list } => handle_command_getall(current_session, show, &list)?,
+        CommandCLI::Delete { name, folder } => {
+            handle_command_delete(current_session, name, folder)?
+        }
+        CommandCLI::Move {
+            source,
+            destination,
+            folder,
+        } => handle_command_move(current_session, source, destination, folder)?,
+        CommandCLI::Edit { name } => handle_command_edit(current_session, name)?,
+        CommandCLI::Totp {
+            name,
+            clipboard,
+            setup,
+        } => handle_command_totp(current_session, name, clipboard, setup)?,
+        CommandCLI::Rekdf { kdf } => handle_command_rekdf(current_session, kdf)?,
+        CommandCLI::History { name, show } => handle_command_history(current_session, name, show)?,
+        CommandCLI::Restore { name, version } => {
+            handle_command_restore(current_session, name, version)?
+        }
+        CommandCLI::Settings {
+            backups,
+            history,
+            clipboard_timeout,
+        } => handle_command_settings(current_session, backups, history, clipboard_timeout)?,
+        CommandCLI::Import {
+            file,
+            format,
+            key_file,
+            on_conflict,
+            dry_run,
+            yes,
+        } => handle_command_import(
+            current_session,
+            file,
+            format,
+            key_file,
+            on_conflict,
+            dry_run,
+            yes,
+        )?,
+        CommandCLI::Merge {
+            file,
+            key_file,
+            show,
+            dry_run,
+        } => {
+            handle_command_merge(current_session, file, key_file, show, dry_run)?;
+        }
+        CommandCLI::Audit {
+            max_age,
+            min_score,
+            breach_db,
+            json,
+        } => handle_command_audit(
+            current_session,
+            AuditOptions {
+                min_score,
+                max_age_days: max_age,
+                breach_db,
+            },
+            json,
+        )?,
+        CommandCLI::Export {
+            file,
+            format,
+            key_file,
+            selection,
+        } => handle_command_export(current_session, file, format, key_file, selection)?,
+        CommandCLI::Policy { action } => handle_command_policy(current_session, action)?,
+        CommandCLI::Generate {
+            options,
+            policy,
+            clear_after,
+        } => {
+            handle_command_generate(current_session, options, policy, clear_after)?;
+        }
+        // Both end the session themselves.
+        CommandCLI::Deletevault {} => handle_command_deletevault(current_session)?,
+        CommandCLI::ChangeMaster {
+            key_file,
+            remove_key_file,
+        } => handle_command_change_master(current_session, key_file, remove_key_file)?,
+        _ => unreachable!("handled in execute()"),
+    }
+
+    if modifies_vault && let Some(session) = current_session.as_mut() {
+        session.end_session()?;
+    }
+
+    wait_for_clipboard_clear();
+    Ok(())
+}
+
+fn supported_by_agent(command: &CommandCLI) -> bool {
+    matches!(
+        command,
+        CommandCLI::Add { .. }
+            | CommandCLI::Get { .. }
+            | CommandCLI::Search { .. }
+            | CommandCLI::Getall { .. }
+            | CommandCLI::Audit { .. }
+            | CommandCLI::Delete { .. }
+            | CommandCLI::Edit { .. }
+            | CommandCLI::Move { .. }
+            | CommandCLI::History { .. }
+            | CommandCLI::Restore { .. }
+            | CommandCLI::Totp { .. }
+    )
+}
+
+// The handlers run against a copy of the agent's entries, every change is then sent back to the agent.
+// The master password stays in the agent, --show is verified by the agent as well.
+fn run_via_agent(vault_name: String, command: CommandCLI) -> Result<(), SessionError> {
+    let response = agent::request(AgentAction::List).map_err(SessionError::VaultError)?;
+    let mut vault = vault_from_agent(vault_name.clone(), response);
+    let entries = vault.entries.clone();
+
+    match command {
+        CommandCLI::Get {
+            name,
+            show,
+            clipboard,
+            folder,
+        } => {
+            if show {
+                verify_with_agent(&vault_name)?;
+            }
+            display_entry(&mut vault, name, show, clipboard, folder.as_deref())?;
+            // copying records when the entry was last used, the agent keeps that as well
+            for action in update_actions(&entries, vault.get_entries()) {
+                agent::request(action).map_err(SessionError::VaultError)?;
+            }
+            wait_for_clipboard_clear();
+            return Ok(());
+        }
+        CommandCLI::Search {
+            query,
+            show,
+            clipboard,
+        } => {
+            if show {
+                verify_with_agent(&vault_name)?;
+            }
+            search_entries(&mut vault, &query.join(" "), show, clipboard)?;
+            for action in update_actions(&entries, vault.get_entries()) {
+                agent::request(action).map_err(SessionError::VaultError)?;
+            }
+            wait_for_clipboard_clear();
+            return Ok(());
+        }
+        CommandCLI::Getall { show, list } => {
+            if show {
+                verify_with_agent(&vault_name)?;
+            }
+            return display_entries(&vault, show, &list);
+        }
+        CommandCLI::History { name, show } => {
+            if show {
+                verify_with_agent(&vault_name)?;
+            }
+            return display_history(&mut vault, name, show);
+        }
+        CommandCLI::Audit {
+            max_age,
+            min_score,
+            breach_db,
+            json,
+        } => {
+            let options = AuditOptions {
+                min_score,
+                max_age_days: max_age,
+                breach_db,
+            };
+            return print_audit(&vault, options, json);
+        }
+        CommandCLI::Totp {
+            ref name,
+            clipboard,
+            ref setup,
+        } if !setup.modifies_entry() => {
+            let mut scratch = Session::new(vault_name);
+            scratch.opened_vault = Some(vault);
+            handle_command_totp(&mut Some(scratch), name.clone(), clipboard, setup.clone())?;
+            wait_for_clipboard_clear();
+            return Ok(());
+        }
+        _ => { /* changes the vault, see below */ }
+    }
+
+    let mut scratch = Session::new(vault_name);
+    scratch.opened_vault = Some(vault);
+    let mut current_session = Some(scratch);
+
+    let actions: Vec<AgentAction> = match command {
+        CommandCLI::Add {
+            name,
+            username,
+            url,
+            notes,
+            password,
+            extra,
+        } => {
+            handle_command_add(
+                &mut current_session,
+                name,
+                username,
+                url,
+                notes,
+                password,
+                extra,
+            )?;
+            changed_entries(&current_session)
+                .last()
+                .cloned()
+                .map(|entry| AgentAction::Add { entry })
+                .into_iter()
+                .collect()
+        }
+        CommandCLI::Delete { name, folder } => {
+            handle_command_delete(&mut current_session, name.clone(), folder)?;
+            let still_exists = changed_entries(&current_session)
+                .iter()
+                .any(|e| *e.get_entry_name() == name);
+            (!still_exists)
+                .then_some(AgentAction::Delete { name })
+                .into_iter()
+                .collect()
+        }
+        CommandCLI::Edit { name } => {
+            handle_command_edit(&mut current_session, name)?;
+            update_actions(&entries, changed_entries(&current_session))
+        }
+        CommandCLI::Restore { name, version } => {
+            handle_command_restore(&mut current_session, name, version)?;
+            update_actions(&entries, changed_entries(&current_session))
+        }
+        CommandCLI::Move {
+            source,
+            destination,
+            folder,
+        } => {
+            handle_command_move(&mut current_session, source, destination, folder)?;
+            update_actions(&entries, changed_entries(&current_session))
+        }
+        CommandCLI::Totp {
+            name,
+            clipboard,
+            setup,
+        } => {
+            handle_command_totp(&mut current_session, name, clipboard, setup)?;
+            update_actions(&entries, changed_entries(&current_session))
+        }
+        _ => unreachable!("only entry commands are routed through the agent"),
+    };
+
+    if !actions.is_empty() {
+        for action in actions {
+            agent::request(action).map_err(SessionError::VaultError)?;
+        }
+        println!("Vault saved by agent.");
+    }
+    Ok(())
+}
+
+// Copy of the agent's vault the handlers run against, without its settings edits would drop old versions
+// and ignore the site policies
+fn vault_from_agent(vault_name: String, response: AgentResponse) -> Vault {
+    let mut vault = Vault::new(vault_name);
+    vault.entries = response.entries;
+    if let Some(settings) = response.settings {
+        vault.settings = settings;
+    }
+    vault
+}
+
+// Updates for the entries a handler changed in place (the order of the entries stays the same)
+fn update_actions(before: &[Entry], after: &[Entry]) -> Vec<AgentAction> {
+    let mut updates: Vec<EntryUpdate> = before
+        .iter()
+        .zip(after)
+        .filter(|(old, new)| old != new)
+        .map(|(old, new)| EntryUpdate {
+            name: old.get_entry_name().clone(),
+            entry: new.clone(),
+        })
+        .collect();
+
+    match updates.len() {
+        0 => Vec::new(),
+        1 => {
+            let update = updates.remove(0);
+            vec![AgentAction::Update {
+                name: update.name,
+                entry: update.entry,
+            }]
+        }
+        _ => vec![AgentAction::UpdateMany { updates }],
+    }
+}
+
+fn changed_entries(current_session: &Option<Session>) -> &[Entry] {
+    current_session
+        .as_ref()
+        .and_then(|s| s.opened_vault.as_ref())
+        .map(|v| v.get_entries().as_slice())
+        .unwrap_or(&[])
+}
+
+fn verify_with_agent(vault_name: &str) -> Result<(), SessionError> {
+    let master =
+        rpassword::prompt_password(format!("Enter master password for '{}': ", vault_name))?;
+    match agent::request(AgentAction::Verify { password: master }) {
+        Ok(_) => Ok(()),
+        // the agent answers with the message of the error
+        Err(VaultError::AgentError(message)) if message == VaultError::InvalidKey.to_string() => {
+            Err(SessionError::VaultError(VaultError::InvalidKey))
+        }
+        Err(e) => Err(SessionError::VaultError(e)),
+    }
+}
+
+#[cfg(unix)]
+fn read_password_from_fd(fd: i32) -> Result<SecretString, VaultError> {
+    use std::io::{BufRead, BufReader};
+
+    // Only the first line is used, so `echo "$PW" | pw --password-fd 0 ...` works as expected.
+    let file = std::fs::File::open(format!("/dev/fd/{fd}"))?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']).to_string();
+    Ok(password.into())
+}
+
+#[cfg(not(unix))]
+fn read_password_from_fd(_fd: i32) -> Result<SecretString, VaultError> {
+    Err(VaultError::AnyhowError(anyhow!(
+        "--password-fd is not supported on this platform, use ${} instead",
+        MASTER_PASSWORD_ENV
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{PasswordPolicy, policy_for_url};
+
+    #[test]
+    fn test_invalid_arguments_exit_code() {
+        assert_eq!(run(["pw", "does-not-exist"]), 2);
+    }
+
+    #[test]
+    fn test_repl_only_command_fails() {
+        assert_eq!(run(["pw", "clear"]), 1);
+    }
+
+    #[test]
+    fn test_unknown_vault_fails() {
+        let result = resolve_vault_name(Some("oneshot_missing_vault".to_string()));
+        assert!(matches!(result, Err(VaultError::VaultDoesNotExist)));
+    }
+
+    #[test]
+    fn test_edit_via_agent_keeps_history_limit() {
+        let mut agent_vault = Vault::new("oneshot_history".to_string());
+        agent_vault.settings.history_count = 25;
+        let entry = Entry::new(
+            "GitHub".to_string(),
+            None,
+            Some("password0".to_string()),
+            None,
+            None,
+        );
+        agent_vault.add_entry(entry).unwrap();
+        let mut session = Session::new("oneshot_history".to_string());
+        session.opened_vault = Some(agent_vault);
+        let (response, _) = agent::dispatch(AgentAction::List, &mut Some(session));
+
+        let mut vault = vault_from_agent("oneshot_history".to_string(), response);
+        let limit = vault.settings.history_count;
+        let entry = vault.get_entry_by_name(&"GitHub".to_string()).unwrap();
+        for i in 1..=20 {
+            let previous = entry.snapshot();
+            entry.set_password(format!("password{}", i));
+            entry.keep_version(previous, limit);
+        }
+        assert_eq!(entry.get_history().len(), 20);
+    }
+
+    #[test]
+    fn test_vault_via_agent_keeps_policies() {
+        let mut agent_vault = Vault::new("oneshot_policies".to_string());
+        let policy = PasswordPolicy {
+            length: 32,
+            ..PasswordPolicy::default()
+        };
+        agent_vault
+            .settings
+            .policies
+            .insert("github.com".to_string(), policy.clone());
+        let mut session = Session::new("oneshot_policies".to_string());
+        session.opened_vault = Some(agent_vault);
+        let (response, _) = agent::dispatch(AgentAction::List, &mut Some(session));
+
+        let vault = vault_from_agent("oneshot_policies".to_string(), response);
+        let site_policy = policy_for_url(&vault.settings.policies, "https://github.com/login");
+        assert_eq!(site_policy, Some((&"github.com".to_string(), &policy)));
+    }
+}
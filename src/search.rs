@@ -0,0 +1,235 @@
+/*what belongs here:
+- Parsing search queries like `github user:alice`
+- Ranked fuzzy matching of entries against such a query
+
+Only name, username, URL, notes, tags and folder are searched. Passwords, TOTP secrets and
+custom fields are never part of a search, so a query cannot be used to probe secret values.
+*/
+
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use crate::errors::VaultError;
+use crate::vault_entry_manager::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchField {
+    Name,
+    Username,
+    Url,
+    Notes,
+    Tags,
+    Folder,
+}
+
+// Every term has to match, a term without a field matches any field
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    terms: Vec<(Option<SearchField>, String)>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Result<SearchQuery, VaultError> {
+        let mut terms = Vec::new();
+
+        for token in query.split_whitespace() {
+            let term = match token.split_once(':') {
+                Some((qualifier, value)) if !value.is_empty() => {
+                    match SearchField::from_qualifier(qualifier)? {
+                        Some(field) => (Some(field), value.to_string()),
+                        // e.g. a URL like https://github.com
+                        None => (None, token.to_string()),
+                    }
+                }
+                _ => (None, token.to_string()),
+            };
+            terms.push(term);
+        }
+
+        if terms.is_empty() {
+            return Err(VaultError::AnyhowError(anyhow::anyhow!(
+                "The search query is empty"
+            )));
+        }
+        Ok(SearchQuery { terms })
+    }
+}
+
+impl SearchField {
+    const ALL: [SearchField; 6] = [
+        SearchField::Name,
+        SearchField::Username,
+        SearchField::Url,
+        SearchField::Notes,
+        SearchField::Tags,
+        SearchField::Folder,
+    ];
+
+    // None if the text before the colon is not a qualifier at all
+    fn from_qualifier(qualifier: &str) -> Result<Option<SearchField>, VaultError> {
+        let field = match qualifier.to_ascii_lowercase().as_str() {
+            "name" => SearchField::Name,
+            "user" | "username" => SearchField::Username,
+            "url" => SearchField::Url,
+            "notes" | "note" => SearchField::Notes,
+            "tag" | "tags" => SearchField::Tags,
+            "folder" => SearchField::Folder,
+            "pw" | "password" | "totp" | "field" => {
+                return Err(VaultError::AnyhowError(anyhow::anyhow!(
+                    "'{}' cannot be searched, secrets are never part of a search",
+                    qualifier
+                )));
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(field))
+    }
+
+    // Matches in the name count most, notes least
+    fn weight(&self) -> i64 {
+        match self {
+            SearchField::Name => 3,
+            SearchField::Username | SearchField::Url | SearchField::Tags => 2,
+            SearchField::Notes | SearchField::Folder => 1,
+        }
+    }
+
+    fn values<'a>(&self, entry: &'a Entry) -> Vec<&'a str> {
+        match self {
+            SearchField::Name => vec![entry.get_entry_name().as_str()],
+            SearchField::Username => entry.username().into_iter().collect(),
+            SearchField::Url => entry.url().into_iter().collect(),
+            SearchField::Notes => entry.get_notes().as_deref().into_iter().collect(),
+            SearchField::Tags => entry.get_tags().iter().map(|t| t.as_str()).collect(),
+            SearchField::Folder => entry.get_folder().into_iter().collect(),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Entries matching every term of the query, best match first.
+pub fn search<'a>(entries: &'a [Entry], query: &SearchQuery) -> Vec<(&'a Entry, i64)> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+
+    let mut results: Vec<(&Entry, i64)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let mut total = 0;
+            for (field, pattern) in &query.terms {
+                let fields = match field {
+                    Some(field) => vec![*field],
+                    None => SearchField::ALL.to_vec(),
+                };
+                total += best_score(&matcher, entry, &fields, pattern)?;
+            }
+            Some((entry, total))
+        })
+        .collect();
+
+    // stable sort, equal scores keep the order of the vault
+    results.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    results
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn best_score(
+    matcher: &SkimMatcherV2,
+    entry: &Entry,
+    fields: &[SearchField],
+    pattern: &str,
+) -> Option<i64> {
+    fields
+        .iter()
+        .flat_map(|field| {
+            field
+                .values(entry)
+                .into_iter()
+                .filter_map(|value| matcher.fuzzy_match(value, pattern))
+                .map(|score| score * field.weight())
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        let mut github = Entry::new(
+            "GitHub".to_string(),
+            Some("alice".to_string()),
+            Some("secret-password".to_string()),
+            Some("https://github.com".to_string()),
+            None,
+        );
+        github.add_tag("dev");
+        let gitlab = Entry::new(
+            "GitLab work".to_string(),
+            Some("bob".to_string()),
+            None,
+            Some("https://gitlab.example.com".to_string()),
+            Some("alice has the admin account".to_string()),
+        );
+        let mail = Entry::new(
+            "Mail".to_string(),
+            Some("alice@example.com".to_string()),
+            None,
+            None,
+            None,
+        );
+        vec![github, gitlab, mail]
+    }
+
+    fn names(results: Vec<(&Entry, i64)>) -> Vec<String> {
+        results
+            .iter()
+            .map(|(e, _)| e.get_entry_name().clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzzy_search_is_ranked() {
+        let entries = entries();
+        let results = search(&entries, &SearchQuery::parse("ghub").unwrap());
+        assert_eq!(names(results)[0], "GitHub");
+
+        let results = search(&entries, &SearchQuery::parse("git").unwrap());
+        assert_eq!(names(results).len(), 2);
+    }
+
+    #[test]
+    fn test_field_qualifiers() {
+        let entries = entries();
+        // alice is also mentioned in the notes of GitLab, but not as its username
+        let results = search(&entries, &SearchQuery::parse("user:alice").unwrap());
+        assert_eq!(names(results), vec!["GitHub", "Mail"]);
+
+        let results = search(
+            &entries,
+            &SearchQuery::parse("user:alice url:github").unwrap(),
+        );
+        assert_eq!(names(results), vec!["GitHub"]);
+
+        let results = search(&entries, &SearchQuery::parse("tag:dev").unwrap());
+        assert_eq!(names(results), vec!["GitHub"]);
+
+        // an unknown qualifier is just part of the term
+        let results = search(&entries, &SearchQuery::parse("https://github").unwrap());
+        assert_eq!(names(results)[0], "GitHub");
+    }
+
+    #[test]
+    fn test_passwords_are_never_searched() {
+        let entries = entries();
+        assert!(search(&entries, &SearchQuery::parse("secret-password").unwrap()).is_empty());
+        assert!(SearchQuery::parse("password:secret").is_err());
+        assert!(SearchQuery::parse("pw:secret").is_err());
+        assert!(SearchQuery::parse("   ").is_err());
+    }
+}
@@ -1,7 +1,17 @@
+use crate::config;
 use crate::errors::{SessionError, VaultError};
+use crate::import::{ConflictPolicy, ImportAction, PlannedEntry, apply_import, plan_import};
+use crate::keyfile::KeyFile;
 use crate::vault_entry_manager::*;
-use crate::vault_file_manager::{close_vault, initialize_vault, open_vault};
+use crate::vault_file_manager::{
+    close_vault_with_key_file, initialize_vault, open_vault_with_key_file, read_vault_file,
+};
+use crate::vault_format::KdfSettings;
+use anyhow::anyhow;
 use secrecy::{ExposeSecret, SecretString};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
@@ -9,6 +19,7 @@ pub struct Session {
     pub vault_name: String,
     pub opened_vault: Option<Vault>,
     master_password: Option<SecretString>,
+    key_file: Option<KeyFile>,
     pub last_activity: Instant,
     pub wished_timeout: u64,
 }
@@ -25,9 +36,100 @@ pub fn active_session(option_session: &Option<Session>) -> bool {
     }
 }
 
+/// All vaults unlocked in the interactive shell. Commands run against the active one, the
+/// others stay unlocked in the background, each with its own auto-lock timer.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    pub active: Option<Session>,
+    pub background: Vec<Session>,
+}
+
+/// What the auto-lock thread and the extension server need from whoever holds the sessions:
+/// a single `Option<Session>` in the agent, `Sessions` in the interactive shell.
+pub trait SessionHolder: Send + 'static {
+    /// Every session, the active one first.
+    fn sessions(&self) -> Vec<&Session>;
+    fn sessions_mut(&mut self) -> Vec<&mut Session>;
+    /// Name of the unlocked vault commands run against.
+    fn active_name(&self) -> Option<String>;
+}
+
+impl SessionHolder for Option<Session> {
+    fn sessions(&self) -> Vec<&Session> {
+        self.iter().collect()
+    }
+
+    fn sessions_mut(&mut self) -> Vec<&mut Session> {
+        self.iter_mut().collect()
+    }
+
+    fn active_name(&self) -> Option<String> {
+        self.as_ref()
+            .filter(|session| session.is_unlocked())
+            .map(|session| session.vault_name.clone())
+    }
+}
+
+impl SessionHolder for Sessions {
+    fn sessions(&self) -> Vec<&Session> {
+        self.active.iter().chain(self.background.iter()).collect()
+    }
+
+    fn sessions_mut(&mut self) -> Vec<&mut Session> {
+        self.active
+            .iter_mut()
+            .chain(self.background.iter_mut())
+            .collect()
+    }
+
+    fn active_name(&self) -> Option<String> {
+        self.active.active_name()
+    }
+}
+
+/// Spawns the background thread that ends each session after its `wished_timeout` seconds of
+/// inactivity. `on_lock` is called with the name of the vault that was closed and whether it
+/// was the active one.
+pub fn spawn_autolock<H, F>(current_session: Arc<Mutex<H>>, on_lock: F)
+where
+    H: SessionHolder,
+    F: Fn(&str, bool) + Send + 'static,
+{
+    // just clones the Arc (which is a pointer), not the entire session!
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            if let Ok(mut session_guard) = current_session.lock() {
+                let active_name = session_guard.active_name();
+                for session in session_guard.sessions_mut() {
+                    if session.opened_vault.is_some()
+                        && session.check_timeout(Duration::from_secs(session.wished_timeout))
+                    {
+                        let name = session.vault_name.clone();
+                        // Attempt to end session
+                        if session.end_session().is_ok() {
+                            on_lock(&name, active_name.as_ref() == Some(&name));
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
 pub fn create_new_vault(vault_name: String, master: SecretString) -> Result<(), VaultError> {
-    let new_vault = initialize_vault(vault_name)?;
-    close_vault(&new_vault, master)?;
+    create_new_vault_with_options(vault_name, master, KdfSettings::default(), None)
+}
+
+pub fn create_new_vault_with_options(
+    vault_name: String,
+    master: SecretString,
+    kdf: KdfSettings,
+    key_file: Option<&KeyFile>,
+) -> Result<(), VaultError> {
+    let mut new_vault = initialize_vault(vault_name)?;
+    new_vault.settings.kdf = kdf;
+    close_vault_with_key_file(&new_vault, master, key_file)?;
     Ok(())
 }
 
@@ -37,11 +139,16 @@ impl Session {
             vault_name,
             opened_vault: None,
             master_password: None,
+            key_file: None,
             last_activity: Instant::now(),
-            wished_timeout: 300,
+            wished_timeout: config::current().session_timeout,
         }
     }
 
+    pub fn is_unlocked(&self) -> bool {
+        self.opened_vault.is_some() && self.master_password.is_some()
+    }
+
     pub fn update_activity(&mut self) {
         self.last_activity = Instant::now();
     }
@@ -53,22 +160,31 @@ impl Session {
     //assumption: vault already exists in memory
     //notes: authentication of the vault + store password
     pub fn start_session(&mut self, master: SecretString) -> Result<(), SessionError> {
+        self.start_session_with_key_file(master, None)
+    }
+
+    pub fn start_session_with_key_file(
+        &mut self,
+        master: SecretString,
+        key_file: Option<KeyFile>,
+    ) -> Result<(), SessionError> {
         //check if a vault is already open
         if self.opened_vault.is_some() {
             return Err(SessionError::SessionActive);
         }
 
         let master_for_session = master.clone();
-        let vault = open_vault(self.vault_name.clone(), master);
+        let vault = open_vault_with_key_file(self.vault_name.clone(), master, key_file.as_ref());
 
         match vault {
             Ok(vault) => {
                 self.master_password = Some(master_for_session);
+                self.key_file = key_file;
                 self.opened_vault = Some(vault);
                 self.last_activity = Instant::now();
                 Ok(())
             }
-            Err(_) => Err(SessionError::VaultError(VaultError::InvalidKey)),
+            Err(e) => Err(SessionError::VaultError(e)),
         }
     }
 
@@ -86,13 +202,18 @@ impl Session {
             .take()
             .ok_or(SessionError::SessionInactive)?;
 
-        close_vault(&vault, master).map_err(SessionError::VaultError)?;
+        let key_file = self.key_file.take();
+
+        close_vault_with_key_file(&vault, master, key_file.as_ref())
+            .map_err(SessionError::VaultError)?;
         Ok(())
     }
 
     pub fn save(&mut self) -> Result<(), SessionError> {
+        let key_file = self.key_file.clone();
         let (vault, master) = self.session_state()?;
-        close_vault(vault, master).map_err(SessionError::VaultError)?;
+        close_vault_with_key_file(vault, master, key_file.as_ref())
+            .map_err(SessionError::VaultError)?;
         Ok(())
     }
 
@@ -113,6 +234,30 @@ impl Session {
         Ok(())
     }
 
+    //the new key file (or none) is used from the next save on
+    pub fn change_key_file(&mut self, key_file: Option<KeyFile>) {
+        self.key_file = key_file;
+    }
+
+    pub fn uses_key_file(&self) -> bool {
+        self.key_file.is_some()
+    }
+
+    /// Decrypts another copy of the vault at `path` with the master password of the session and,
+    /// unless `key_file` is given, the key file of the session.
+    pub fn read_copy(
+        &self,
+        path: &Path,
+        key_file: Option<&KeyFile>,
+    ) -> Result<Vault, SessionError> {
+        let master = self
+            .master_password
+            .clone()
+            .ok_or(SessionError::SessionInactive)?;
+        read_vault_file(path, master, key_file.or(self.key_file.as_ref()))
+            .map_err(SessionError::VaultError)
+    }
+
     //this function does 3 things:
     //1. It checks whether the session is active
     //2. It gives controlled access to the vault (vault remains owned by session, giving the caller a mutable reference to the vault)
@@ -135,6 +280,178 @@ impl Session {
     }
 }
 
+impl Sessions {
+    /// Names of the unlocked vaults, the active one first.
+    pub fn unlocked_names(&self) -> Vec<String> {
+        self.sessions()
+            .into_iter()
+            .filter(|session| session.is_unlocked())
+            .map(|session| session.vault_name.clone())
+            .collect()
+    }
+
+    pub fn is_unlocked(&self, name: &str) -> bool {
+        self.unlocked_names()
+            .iter()
+            .any(|unlocked| unlocked == name)
+    }
+
+    /// Makes a newly opened session the active one, the previously active one stays unlocked.
+    pub fn activate(&mut self, session: Session) {
+        self.park_active();
+        self.active = Some(session);
+    }
+
+    /// Makes the unlocked vault `name` the active one.
+    pub fn switch_to(&mut self, name: &str) -> Result<(), SessionError> {
+        if self.active_name().as_deref() == Some(name) {
+            return Ok(());
+        }
+        let index = self
+            .background
+            .iter()
+            .position(|session| session.vault_name == name && session.is_unlocked())
+            .ok_or_else(|| {
+                SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+                    "Vault '{}' is not unlocked, open it first",
+                    name
+                )))
+            })?;
+        let session = self.background.remove(index);
+        self.activate(session);
+        Ok(())
+    }
+
+    /// Moves the active session to the background, locked sessions are dropped.
+    pub fn park_active(&mut self) {
+        if let Some(session) = self.active.take()
+            && session.is_unlocked()
+        {
+            self.background.push(session);
+        }
+        self.background.retain(Session::is_unlocked);
+    }
+
+    /// Makes the most recently used background vault the active one, e.g. after the active
+    /// vault was closed. Returns its name.
+    pub fn activate_most_recent(&mut self) -> Option<String> {
+        self.background.retain(Session::is_unlocked);
+        let index = self
+            .background
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, session)| session.last_activity)
+            .map(|(index, _)| index)?;
+        let session = self.background.remove(index);
+        let name = session.vault_name.clone();
+        self.active = Some(session);
+        Some(name)
+    }
+
+    /// Splits `vault:entry` if `vault` is unlocked, any other name is an entry of the active vault.
+    pub fn split_vault_entry(&self, name: &str) -> Option<(String, String)> {
+        let (vault, entry) = name.split_once(':')?;
+        (!entry.is_empty() && self.is_unlocked(vault))
+            .then(|| (vault.to_string(), entry.to_string()))
+    }
+
+    /// Copies the named entries of the active vault into the unlocked vault `to`, with `remove`
+    /// they are moved. `to` is saved before anything is removed from the active vault and each
+    /// vault is put back as it was if its save fails, so an entry is never missing from both.
+    pub fn transfer_entries(
+        &mut self,
+        names: &[String],
+        to: &str,
+        policy: ConflictPolicy,
+        remove: bool,
+    ) -> Result<Vec<PlannedEntry>, SessionError> {
+        let source = self
+            .active
+            .as_mut()
+            .filter(|session| session.is_unlocked())
+            .ok_or(SessionError::SessionInactive)?;
+        if source.vault_name == to {
+            return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+                "The entries are already in vault '{}'",
+                to
+            ))));
+        }
+        let target = self
+            .background
+            .iter_mut()
+            .find(|session| session.vault_name == to && session.is_unlocked())
+            .ok_or_else(|| {
+                SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+                    "Vault '{}' is not unlocked, open it first",
+                    to
+                )))
+            })?;
+
+        let source_vault = source
+            .opened_vault
+            .as_mut()
+            .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+        let mut entries: Vec<Entry> = Vec::new();
+        for name in names {
+            let entry = source_vault
+                .entries
+                .iter()
+                .find(|entry| entry.get_entry_name() == name)
+                .ok_or(SessionError::VaultError(VaultError::EntryNotFound))?;
+            if !entries.iter().any(|added| added.get_entry_name() == name) {
+                entries.push(entry.clone());
+            }
+        }
+
+        let target_vault = target
+            .opened_vault
+            .as_mut()
+            .ok_or(SessionError::VaultError(VaultError::NoVaultOpen))?;
+        let plan = plan_import(target_vault, entries, policy);
+        let summary = plan.clone();
+        let target_before = target_vault.clone();
+        let saved = apply_import(target_vault, plan)
+            .map_err(SessionError::VaultError)
+            .and_then(|_| target.save());
+        if let Err(e) = saved {
+            target.opened_vault = Some(target_before);
+            return Err(e);
+        }
+
+        if remove {
+            let source_before = source_vault.clone();
+            for planned in &summary {
+                match &planned.action {
+                    ImportAction::Skip => { /* stays where it is */ }
+                    ImportAction::Rename { from } => source_vault.remove_entry_by_name(from),
+                    _ => source_vault.remove_entry_by_name(planned.entry.get_entry_name()),
+                }
+            }
+            if let Err(e) = source.save() {
+                source.opened_vault = Some(source_before);
+                return Err(SessionError::VaultError(VaultError::AnyhowError(anyhow!(
+                    "The entries were copied to '{}' but could not be removed from '{}': {}",
+                    to,
+                    source.vault_name,
+                    e
+                ))));
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Saves and closes every unlocked vault, e.g. on quit. Stops at the first one that fails.
+    pub fn end_all(&mut self) -> Result<(), SessionError> {
+        for session in self.sessions_mut() {
+            match session.end_session() {
+                Ok(()) | Err(SessionError::SessionInactive) => { /* Do nothing */ }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,6 +479,7 @@ mod tests {
             vault_name: vault_name.clone(),
             opened_vault: None,
             master_password: None,
+            key_file: None,
             last_activity: Instant::now(),
             wished_timeout: 300,
         };
@@ -188,6 +506,7 @@ mod tests {
             vault_name: vault_name.clone(),
             opened_vault: None,
             master_password: None,
+            key_file: None,
             last_activity: Instant::now(),
             wished_timeout: 300,
         };
@@ -211,6 +530,7 @@ mod tests {
             vault_name: vault_name.clone(),
             opened_vault: None,
             master_password: None,
+            key_file: None,
             last_activity: Instant::now(),
             wished_timeout: 300,
         };
@@ -234,6 +554,7 @@ mod tests {
             vault_name: vault_name.clone(),
             opened_vault: None,
             master_password: None,
+            key_file: None,
             last_activity: Instant::now(),
             wished_timeout: 300,
         };
@@ -252,4 +573,87 @@ mod tests {
 
         let _ = delete_vault_file(&vault_name);
     }
+
+    fn unlocked_session(name: &str) -> Session {
+        let mut session = Session::new(name.to_string());
+        session.opened_vault = Some(Vault::new(name.to_string()));
+        session.master_password = Some(SecretString::new("password123".to_string().into()));
+        session
+    }
+
+    #[test]
+    fn test_several_unlocked_vaults() {
+        let mut sessions = Sessions::default();
+        sessions.activate(unlocked_session("personal"));
+        sessions.activate(unlocked_session("team"));
+        assert_eq!(sessions.active_name().as_deref(), Some("team"));
+        assert_eq!(sessions.unlocked_names(), vec!["team", "personal"]);
+
+        sessions.switch_to("personal").unwrap();
+        assert_eq!(sessions.active_name().as_deref(), Some("personal"));
+        assert!(sessions.switch_to("missing").is_err());
+
+        assert_eq!(
+            sessions.split_vault_entry("team:github"),
+            Some(("team".to_string(), "github".to_string()))
+        );
+        // not an unlocked vault, e.g. a URL
+        assert_eq!(sessions.split_vault_entry("https://github.com"), None);
+
+        // closing the current vault hands over to the other one
+        sessions.active = None;
+        assert_eq!(sessions.activate_most_recent().as_deref(), Some("team"));
+        assert!(sessions.background.is_empty());
+    }
+
+    #[test]
+    fn test_move_entries_between_vaults() {
+        let master_pw = SecretString::new("password123".to_string().into());
+        let mut sessions = Sessions::default();
+        for name in ["test_vault_move_team", "test_vault_move_personal"] {
+            create_new_vault(name.to_string(), master_pw.clone()).unwrap();
+            let mut session = Session::new(name.to_string());
+            session.start_session(master_pw.clone()).unwrap();
+            let (vault, _master) = session.session_state().unwrap();
+            vault
+                .add_entry(Entry::new("GitHub".to_string(), None, None, None, None))
+                .unwrap();
+            sessions.activate(session);
+        }
+        let (vault, _master) = sessions.active.as_mut().unwrap().session_state().unwrap();
+        vault
+            .add_entry(Entry::new("Mail".to_string(), None, None, None, None))
+            .unwrap();
+
+        let names = ["GitHub".to_string(), "Mail".to_string()];
+        let plan = sessions
+            .transfer_entries(&names, "test_vault_move_team", ConflictPolicy::Rename, true)
+            .unwrap();
+        assert_eq!(plan[0].entry.get_entry_name(), "GitHub (2)");
+
+        let (vault, _master) = sessions.active.as_mut().unwrap().session_state().unwrap();
+        assert!(vault.entries.is_empty());
+
+        // the target vault was saved
+        let mut reopened = Session::new("test_vault_move_team".to_string());
+        reopened.start_session(master_pw.clone()).unwrap();
+        let (vault, _master) = reopened.session_state().unwrap();
+        let names: Vec<_> = vault.entries.iter().map(Entry::get_entry_name).collect();
+        assert_eq!(names, vec!["GitHub", "GitHub (2)", "Mail"]);
+
+        // entries that are not in the current vault are not transferred at all
+        assert!(
+            sessions
+                .transfer_entries(
+                    &["Mail".to_string()],
+                    "test_vault_move_team",
+                    ConflictPolicy::Skip,
+                    false
+                )
+                .is_err()
+        );
+
+        let _ = delete_vault_file("test_vault_move_team");
+        let _ = delete_vault_file("test_vault_move_personal");
+    }
 }
@@ -0,0 +1,388 @@
+/*what belongs here:
+- TOTP secrets stored in entries (RFC 6238)
+- Parsing `otpauth://totp/...` URIs and plain base32 secrets
+- Computing the current one-time code
+
+The secret is kept as normalised base32 (upper case, no spaces or padding) inside the encrypted vault.
+*/
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::errors::VaultError;
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+const MAX_PERIOD: u64 = 3600;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawTotp")]
+pub struct Totp {
+    secret: String,
+    digits: u32,
+    period: u64,
+    algorithm: TotpAlgorithm,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issuer: Option<String>,
+}
+
+// Totp as read from a vault, an import or the agent, before it is validated
+#[derive(Deserialize)]
+struct RawTotp {
+    secret: String,
+    #[serde(default = "default_digits")]
+    digits: u32,
+    #[serde(default = "default_period")]
+    period: u64,
+    #[serde(default)]
+    algorithm: TotpAlgorithm,
+    #[serde(default)]
+    issuer: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Totp {
+    /// Accepts an `otpauth://totp/...` URI or a base32 secret (spaces and lower case are fine).
+    pub fn parse(input: &str) -> Result<Totp, VaultError> {
+        let input = input.trim();
+        if input.to_ascii_lowercase().starts_with("otpauth://") {
+            return Totp::from_uri(input);
+        }
+        Totp::new(
+            input,
+            DEFAULT_DIGITS,
+            DEFAULT_PERIOD,
+            TotpAlgorithm::default(),
+        )
+    }
+
+    pub fn new(
+        secret: &str,
+        digits: u32,
+        period: u64,
+        algorithm: TotpAlgorithm,
+    ) -> Result<Totp, VaultError> {
+        let totp = Totp {
+            secret: normalize_secret(secret),
+            digits,
+            period,
+            algorithm,
+            issuer: None,
+        };
+        totp.validate()?;
+        Ok(totp)
+    }
+
+    /// Overrides digits, period and algorithm, e.g. for a plain secret of a non-default setup.
+    pub fn with_options(
+        mut self,
+        digits: Option<u32>,
+        period: Option<u64>,
+        algorithm: Option<TotpAlgorithm>,
+    ) -> Result<Totp, VaultError> {
+        self.digits = digits.unwrap_or(self.digits);
+        self.period = period.unwrap_or(self.period);
+        self.algorithm = algorithm.unwrap_or(self.algorithm);
+        self.validate()?;
+        Ok(self)
+    }
+
+    pub fn digits(&self) -> u32 {
+        self.digits
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    pub fn algorithm(&self) -> TotpAlgorithm {
+        self.algorithm
+    }
+
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// `otpauth://totp/...` URI with all settings, as understood by authenticator apps and KeePassXC.
+    pub fn to_uri(&self, label: &str) -> String {
+        let mut uri = Url::parse("otpauth://totp/").expect("static URI is valid");
+        uri.set_path(label);
+        uri.query_pairs_mut()
+            .append_pair("secret", &self.secret)
+            .append_pair("digits", &self.digits.to_string())
+            .append_pair("period", &self.period.to_string())
+            .append_pair("algorithm", &self.algorithm.to_string());
+        if let Some(issuer) = &self.issuer {
+            uri.query_pairs_mut().append_pair("issuer", issuer);
+        }
+        uri.to_string()
+    }
+
+    /// Current code and the number of seconds it stays valid.
+    pub fn current_code(&self) -> Result<(String, u64), VaultError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| VaultError::InvalidTotp("system clock is before 1970".to_string()))?
+            .as_secs();
+        Ok((self.code_at(now)?, self.period - now % self.period))
+    }
+
+    pub fn code_at(&self, unix_time: u64) -> Result<String, VaultError> {
+        self.validate()?;
+        let key = decode_secret(&self.secret)?;
+        let counter = (unix_time / self.period).to_be_bytes();
+
+        let hash = match self.algorithm {
+            TotpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(&key, &counter),
+            TotpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(&key, &counter),
+            TotpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(&key, &counter),
+        };
+
+        // dynamic truncation (RFC 4226, section 5.3)
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        let code = binary as u64 % 10u64.pow(self.digits);
+        Ok(format!("{:0width$}", code, width = self.digits as usize))
+    }
+
+    fn from_uri(input: &str) -> Result<Totp, VaultError> {
+        let uri = Url::parse(input)
+            .map_err(|_| VaultError::InvalidTotp("malformed otpauth:// URI".to_string()))?;
+        match uri.host_str() {
+            Some(kind) if kind.eq_ignore_ascii_case("totp") => { /* supported */ }
+            Some(kind) if kind.eq_ignore_ascii_case("hotp") => {
+                return Err(VaultError::InvalidTotp(
+                    "counter based HOTP is not supported".to_string(),
+                ));
+            }
+            _ => {
+                return Err(VaultError::InvalidTotp(
+                    "otpauth:// URI is neither totp nor hotp".to_string(),
+                ));
+            }
+        }
+
+        let mut secret = None;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut algorithm = TotpAlgorithm::default();
+        let mut issuer = None;
+
+        for (key, value) in uri.query_pairs() {
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(value.into_owned()),
+                "digits" => digits = parse_number(&value, "digits")?,
+                "period" => period = parse_number(&value, "period")?,
+                "algorithm" => {
+                    algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => TotpAlgorithm::Sha1,
+                        "SHA256" => TotpAlgorithm::Sha256,
+                        "SHA512" => TotpAlgorithm::Sha512,
+                        other => {
+                            return Err(VaultError::InvalidTotp(format!(
+                                "unknown algorithm '{}'",
+                                other
+                            )));
+                        }
+                    }
+                }
+                "issuer" => issuer = Some(value.into_owned()),
+                _ => { /* ignore unknown parameters like `image` */ }
+            }
+        }
+
+        let secret = secret
+            .ok_or_else(|| VaultError::InvalidTotp("URI has no secret parameter".to_string()))?;
+        let mut totp = Totp::new(&secret, digits, period, algorithm)?;
+        totp.issuer = issuer;
+        Ok(totp)
+    }
+
+    fn validate(&self) -> Result<(), VaultError> {
+        if self.secret.is_empty() {
+            return Err(VaultError::InvalidTotp("secret is empty".to_string()));
+        }
+        decode_secret(&self.secret)?;
+        if !(6..=8).contains(&self.digits) {
+            return Err(VaultError::InvalidTotp(
+                "digits must be between 6 and 8".to_string(),
+            ));
+        }
+        if !(1..=MAX_PERIOD).contains(&self.period) {
+            return Err(VaultError::InvalidTotp(format!(
+                "period must be between 1 and {} seconds",
+                MAX_PERIOD
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<RawTotp> for Totp {
+    type Error = VaultError;
+
+    fn try_from(raw: RawTotp) -> Result<Totp, VaultError> {
+        let totp = Totp {
+            secret: raw.secret,
+            digits: raw.digits,
+            period: raw.period,
+            algorithm: raw.algorithm,
+            issuer: raw.issuer,
+        };
+        totp.validate()?;
+        Ok(totp)
+    }
+}
+
+impl std::fmt::Display for TotpAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TotpAlgorithm::Sha1 => write!(f, "SHA1"),
+            TotpAlgorithm::Sha256 => write!(f, "SHA256"),
+            TotpAlgorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+fn default_digits() -> u32 {
+    DEFAULT_DIGITS
+}
+
+fn default_period() -> u64 {
+    DEFAULT_PERIOD
+}
+
+fn normalize_secret(secret: &str) -> String {
+    secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, VaultError> {
+    base32::decode(base32::Alphabet::Rfc4648 { padding: false }, secret)
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| VaultError::InvalidTotp("secret is not valid base32".to_string()))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, VaultError> {
+    value
+        .parse()
+        .map_err(|_| VaultError::InvalidTotp(format!("{} is not a number", name)))
+}
+
+fn hmac_digest<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <M as hmac::digest::KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rfc_totp(key: &[u8], algorithm: TotpAlgorithm) -> Totp {
+        let secret = base32::encode(base32::Alphabet::Rfc4648 { padding: false }, key);
+        Totp::new(&secret, 8, 30, algorithm).unwrap()
+    }
+
+    // Test vectors from RFC 6238, appendix B
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = rfc_totp(b"12345678901234567890", TotpAlgorithm::Sha1);
+        let sha256 = rfc_totp(b"12345678901234567890123456789012", TotpAlgorithm::Sha256);
+        let sha512 = rfc_totp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            TotpAlgorithm::Sha512,
+        );
+
+        assert_eq!(sha1.code_at(59).unwrap(), "94287082");
+        assert_eq!(sha256.code_at(59).unwrap(), "46119246");
+        assert_eq!(sha512.code_at(59).unwrap(), "90693936");
+        assert_eq!(sha1.code_at(1111111109).unwrap(), "07081804");
+        assert_eq!(sha256.code_at(20000000000).unwrap(), "77737706");
+    }
+
+    #[test]
+    fn test_parse_uri() {
+        let totp = Totp::parse(
+            "otpauth://totp/ACME:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME&digits=8&period=60&algorithm=SHA256",
+        )
+        .unwrap();
+        assert_eq!(totp.digits(), 8);
+        assert_eq!(totp.period(), 60);
+        assert_eq!(totp.algorithm(), TotpAlgorithm::Sha256);
+        assert_eq!(totp.issuer(), Some("ACME"));
+        assert_eq!(
+            Totp::parse(&totp.to_uri("ACME:alice@example.com")).unwrap(),
+            totp
+        );
+    }
+
+    #[test]
+    fn test_parse_plain_secret() {
+        let totp = Totp::parse("jbsw y3dp ehpk 3pxp").unwrap();
+        assert_eq!(totp, Totp::parse("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(totp.digits(), DEFAULT_DIGITS);
+        assert_eq!(totp.period(), DEFAULT_PERIOD);
+    }
+
+    #[test]
+    fn test_invalid_input_is_rejected() {
+        assert!(matches!(
+            Totp::parse("not base32 !"),
+            Err(VaultError::InvalidTotp(_))
+        ));
+        assert!(Totp::parse("otpauth://hotp/x?secret=JBSWY3DPEHPK3PXP&counter=1").is_err());
+        assert!(Totp::parse("otpauth://totp/x?digits=6").is_err());
+        assert!(
+            Totp::parse("JBSWY3DPEHPK3PXP")
+                .unwrap()
+                .with_options(Some(12), None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_stored_totp_is_validated() {
+        let stored: Totp = serde_json::from_str(r#"{"secret":"JBSWY3DPEHPK3PXP"}"#).unwrap();
+        assert_eq!(stored.period(), DEFAULT_PERIOD);
+        assert!(
+            serde_json::from_str::<Totp>(r#"{"secret":"JBSWY3DPEHPK3PXP","period":0}"#).is_err()
+        );
+        assert!(
+            serde_json::from_str::<Totp>(r#"{"secret":"JBSWY3DPEHPK3PXP","digits":40}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_code_is_padded() {
+        let totp = Totp::parse("JBSWY3DPEHPK3PXP").unwrap();
+        let (code, remaining) = totp.current_code().unwrap();
+        assert_eq!(code.len(), 6);
+        assert!((1..=30).contains(&remaining));
+    }
+}
@@ -1,19 +1,143 @@
+use crate::config;
 use crate::errors::VaultError;
+use crate::generator::PasswordPolicy;
+use crate::totp::Totp;
+use crate::vault_format::KdfSettings;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use url::Url;
+
+// Change times kept per value without history, enough to find one both copies of a vault know
+const MAX_METADATA_CHANGES: usize = 20;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vault {
     pub name: String,
     pub entries: Vec<Entry>,
+    #[serde(default)]
+    pub settings: VaultSettings,
+    // Ids of deleted entries and when they were deleted, so a merge does not bring them back
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub removed: BTreeMap<String, DateTime<Utc>>,
+}
+
+// Per vault settings, stored encrypted together with the entries
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VaultSettings {
+    // Number of encrypted backups kept next to the vault, 0 disables backups
+    pub backup_count: usize,
+    // Argon2id parameters used whenever the vault is saved
+    pub kdf: KdfSettings,
+    // Number of earlier versions kept per entry, 0 disables the history
+    pub history_count: usize,
+    // Seconds until a copied password is cleared from the clipboard, None uses the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clipboard_timeout: Option<u64>,
+    // Named password policies of `generate`, see generator.rs
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub policies: BTreeMap<String, PasswordPolicy>,
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        VaultSettings {
+            backup_count: 5,
+            kdf: KdfSettings::default(),
+            history_count: 10,
+            clipboard_timeout: None,
+            policies: BTreeMap::new(),
+        }
+    }
+}
+
+impl VaultSettings {
+    /// The clipboard timeout of the vault, else the one of the config.
+    pub fn effective_clipboard_timeout(&self) -> u64 {
+        self.clipboard_timeout
+            .unwrap_or_else(|| config::current().clipboard_timeout)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Entry {
+    // Stable identity across renames and copies of the vault, assigned when the vault is loaded
+    // for entries created before ids existed
+    #[serde(default)]
+    id: String,
     pub entryname: String,
     username: Option<String>,
     password: Option<String>,
     url: Option<String>,
     notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totp: Option<Totp>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<CustomField>,
+    // Earlier versions, newest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<EntryVersion>,
+    // Timestamps are missing for entries created before they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_changed: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used: Option<DateTime<Utc>>,
+    // The credentials should be replaced after this day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<NaiveDate>,
+    // Path like work/aws/prod, None is the top level of the vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // When name, folder, tags, TOTP and expiry were changed, newest first. They have no history,
+    // merging two copies of the vault uses these times to tell which copy changed them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata_changed: BTreeMap<String, Vec<DateTime<Utc>>>,
+}
+
+// Values of an entry before it was changed. Name and TOTP secret are not part of the history.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EntryVersion {
+    // when these values were replaced
+    pub replaced: DateTime<Utc>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<CustomField>,
+}
+
+// Additional field of an entry, e.g. a PIN, a security question or recovery codes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CustomField {
+    name: String,
+    value: String,
+    #[serde(rename = "type", default)]
+    field_type: FieldType,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    Text,
+    // masked like the password, only shown with --show
+    #[value(alias = "secret")]
+    Hidden,
+    Url,
+    Email,
+    // YYYY-MM-DD
+    Date,
+    Multiline,
 }
 
 impl Entry {
@@ -24,15 +148,32 @@ impl Entry {
         url: Option<String>,
         notes: Option<String>,
     ) -> Entry {
+        let now = Utc::now();
         Entry {
+            id: new_entry_id(),
             entryname: name,
             username: user,
+            password_changed: pw.as_ref().map(|_| now),
             password: pw,
             url,
             notes,
+            totp: None,
+            fields: Vec::new(),
+            history: Vec::new(),
+            created: Some(now),
+            modified: Some(now),
+            last_used: None,
+            expires: None,
+            folder: None,
+            tags: Vec::new(),
+            metadata_changed: BTreeMap::new(),
         }
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn get_entry_name(&self) -> &String {
         &self.entryname
     }
@@ -65,44 +206,576 @@ impl Entry {
         &self.notes
     }
 
+    pub fn get_totp(&self) -> &Option<Totp> {
+        &self.totp
+    }
+
+    pub fn get_fields(&self) -> &Vec<CustomField> {
+        &self.fields
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<&CustomField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        self.created
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    pub fn password_changed(&self) -> Option<DateTime<Utc>> {
+        self.password_changed
+    }
+
+    pub fn last_used(&self) -> Option<DateTime<Utc>> {
+        self.last_used
+    }
+
+    pub fn expires(&self) -> Option<NaiveDate> {
+        self.expires
+    }
+
+    /// Expired on the expiry day itself, in local time.
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|day| day <= Local::now().date_naive())
+    }
+
+    pub fn get_folder(&self) -> Option<&str> {
+        self.folder.as_deref()
+    }
+
+    /// True if the entry is in the folder or one of its subfolders, every entry is in the top level.
+    pub fn in_folder(&self, folder: &str) -> bool {
+        match (normalize_folder(folder), &self.folder) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(wanted), Some(own)) => *own == wanted || own.starts_with(&format!("{}/", wanted)),
+        }
+    }
+
+    pub fn get_tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag.trim()))
+    }
+
+    pub fn get_history(&self) -> &Vec<EntryVersion> {
+        &self.history
+    }
+
+    /// Current values, to be passed to `keep_version` after the entry was changed.
+    pub fn snapshot(&self) -> EntryVersion {
+        EntryVersion {
+            replaced: Utc::now(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            url: self.url.clone(),
+            notes: self.notes.clone(),
+            fields: self.fields.clone(),
+        }
+    }
+
+    /// Adds the values from before a change to the history, unless nothing changed.
+    pub fn keep_version(&mut self, mut previous: EntryVersion, limit: usize) {
+        if previous.same_values(&self.snapshot()) {
+            return;
+        }
+        previous.replaced = Utc::now();
+        self.history.insert(0, previous);
+        self.truncate_history(limit);
+    }
+
+    /// Rolls the entry back to `history[index]`, the current values become the newest version.
+    pub fn restore_version(&mut self, index: usize, limit: usize) -> Result<(), VaultError> {
+        let version = self
+            .history
+            .get(index)
+            .cloned()
+            .ok_or(VaultError::VersionNotFound)?;
+        let previous = self.snapshot();
+
+        if self.password != version.password {
+            self.password_changed = Some(Utc::now());
+        }
+        self.username = version.username;
+        self.password = version.password;
+        self.url = version.url;
+        self.notes = version.notes;
+        self.fields = version.fields;
+        self.mark_modified();
+
+        self.keep_version(previous, limit);
+        Ok(())
+    }
+
+    /// Replaces all values with those of `other` (e.g. an imported entry), name, creation date
+    /// and history stay. The replaced values become the newest version.
+    pub fn overwrite_with(&mut self, other: Entry, limit: usize) {
+        let previous = self.snapshot();
+        if self.password != other.password {
+            self.password_changed = other.password.as_ref().map(|_| Utc::now());
+        }
+        for value in self.changed_metadata(&other) {
+            if value != "name" {
+                self.record_metadata_change(value);
+            }
+        }
+        self.username = other.username;
+        self.password = other.password;
+        self.url = other.url;
+        self.notes = other.notes;
+        self.totp = other.totp;
+        self.fields = other.fields;
+        self.expires = other.expires;
+        self.folder = other.folder;
+        self.tags = other.tags;
+        self.mark_modified();
+
+        self.keep_version(previous, limit);
+    }
+
+    /// Replaces the history (newest first), e.g. with the one of an imported entry.
+    pub fn set_history(&mut self, history: Vec<EntryVersion>, limit: usize) {
+        self.history = history;
+        self.truncate_history(limit);
+    }
+
+    /// Dates taken over from another password manager, `None` keeps the current value.
+    /// Has to be called after all other changes, they would mark the entry as modified again.
+    pub fn set_dates(
+        &mut self,
+        created: Option<DateTime<Utc>>,
+        modified: Option<DateTime<Utc>>,
+        password_changed: Option<DateTime<Utc>>,
+        last_used: Option<DateTime<Utc>>,
+    ) {
+        self.created = created.or(self.created);
+        self.modified = modified.or(self.modified);
+        self.password_changed = password_changed.or(self.password_changed);
+        self.last_used = last_used.or(self.last_used);
+    }
+
+    /// Takes one of the values listed by `EntryVersion::changed_values` or `changed_metadata`
+    /// from `other`, values without history together with their change times.
+    pub fn take_value(&mut self, value: &str, other: &Entry) {
+        match value {
+            "username" => self.username = other.username.clone(),
+            "password" => {
+                self.password = other.password.clone();
+                self.password_changed = other.password_changed;
+            }
+            "url" => self.url = other.url.clone(),
+            "notes" => self.notes = other.notes.clone(),
+            "fields" => self.fields = other.fields.clone(),
+            "name" => self.entryname = other.entryname.clone(),
+            "folder" => self.folder = other.folder.clone(),
+            "tags" => self.tags = other.tags.clone(),
+            "totp" => self.totp = other.totp.clone(),
+            "expires" => self.expires = other.expires,
+            _ => return,
+        }
+        if let Some(changes) = other.metadata_changed.get(value) {
+            self.metadata_changed
+                .insert(value.to_string(), changes.clone());
+        }
+    }
+
+    /// Names of the values without a history (name, folder, tags, TOTP, expiry) that differ.
+    pub fn changed_metadata(&self, other: &Entry) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.entryname != other.entryname {
+            changed.push("name");
+        }
+        if self.folder != other.folder {
+            changed.push("folder");
+        }
+        if self.tags != other.tags {
+            changed.push("tags");
+        }
+        if self.totp != other.totp {
+            changed.push("totp");
+        }
+        if self.expires != other.expires {
+            changed.push("expires");
+        }
+        changed
+    }
+
+    /// When a value listed by `changed_metadata` was changed, newest first.
+    pub fn metadata_changes(&self, value: &str) -> &[DateTime<Utc>] {
+        self.metadata_changed
+            .get(value)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Records a conflict about a value without history as resolved now, after the changes of
+    /// both copies. Merging the same copies again then keeps the chosen value.
+    pub fn resolve_metadata(&mut self, value: &str, other: &Entry) {
+        let mut changes: Vec<DateTime<Utc>> = self
+            .metadata_changes(value)
+            .iter()
+            .chain(other.metadata_changes(value))
+            .copied()
+            .collect();
+        changes.sort_by_key(|change| std::cmp::Reverse(*change));
+        changes.dedup();
+        changes.insert(0, Utc::now());
+        changes.truncate(MAX_METADATA_CHANGES);
+        self.metadata_changed.insert(value.to_string(), changes);
+    }
+
+    /// Adds the versions of `other` that this entry does not know yet, newest first.
+    pub fn merge_history(&mut self, other: &Entry, limit: usize) {
+        for version in &other.history {
+            if !self
+                .history
+                .iter()
+                .any(|known| known.replaced == version.replaced && known.same_values(version))
+            {
+                self.history.push(version.clone());
+            }
+        }
+        self.history
+            .sort_by_key(|version| std::cmp::Reverse(version.replaced));
+        self.truncate_history(limit);
+    }
+
+    /// Earliest creation, latest modification and latest use of both entries.
+    pub fn merge_dates(&mut self, other: &Entry) {
+        self.created = match (self.created, other.created) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.modified = self.modified.max(other.modified);
+        self.last_used = self.last_used.max(other.last_used);
+    }
+
+    pub fn truncate_history(&mut self, limit: usize) {
+        self.history.truncate(limit);
+    }
+
     pub fn set_name(&mut self, vault: &Vault, name: String) -> Result<(), VaultError> {
         if vault.entryname_exists(&name) {
             return Err(VaultError::NameExists);
         }
-        self.entryname = name;
+        self.rename(name);
         Ok(())
     }
 
+    // unlike set_name, the caller makes sure the name is not taken
+    pub fn rename(&mut self, name: String) {
+        if self.entryname != name {
+            self.entryname = name;
+            self.record_metadata_change("name");
+        }
+    }
+
     pub fn set_username(&mut self, user: String) {
         self.username = Some(user);
+        self.mark_modified();
     }
 
     pub fn set_password(&mut self, password: String) {
+        if self.password.as_ref() != Some(&password) {
+            self.password_changed = Some(Utc::now());
+        }
         self.password = Some(password);
+        self.mark_modified();
     }
 
     pub fn set_url(&mut self, url: String) {
         self.url = Some(url);
+        self.mark_modified();
     }
 
     pub fn set_notes(&mut self, notes: String) {
         self.notes = Some(notes);
+        self.mark_modified();
+    }
+
+    pub fn set_totp(&mut self, totp: Totp) {
+        if self.totp.as_ref() != Some(&totp) {
+            self.totp = Some(totp);
+            self.record_metadata_change("totp");
+        }
+    }
+
+    // replaces a field with the same name, otherwise the field is appended
+    pub fn set_field(&mut self, field: CustomField) {
+        match self.fields.iter_mut().find(|f| f.name == field.name) {
+            Some(existing) => *existing = field,
+            None => self.fields.push(field),
+        }
+        self.mark_modified();
     }
 
     pub fn remove_username(&mut self) {
         self.username = None;
+        self.mark_modified();
     }
 
     pub fn remove_password(&mut self) {
+        if self.password.is_some() {
+            self.password_changed = Some(Utc::now());
+        }
         self.password = None;
+        self.mark_modified();
     }
 
     pub fn remove_url(&mut self) {
         self.url = None;
+        self.mark_modified();
     }
 
     pub fn remove_notes(&mut self) {
         self.notes = None;
+        self.mark_modified();
+    }
+
+    pub fn remove_totp(&mut self) {
+        if self.totp.take().is_some() {
+            self.record_metadata_change("totp");
+        }
+    }
+
+    pub fn remove_field(&mut self, name: &str) -> Result<(), VaultError> {
+        let count = self.fields.len();
+        self.fields.retain(|field| field.name != name);
+        if self.fields.len() == count {
+            return Err(VaultError::InvalidField(format!(
+                "'{}' does not exist",
+                name
+            )));
+        }
+        self.mark_modified();
+        Ok(())
+    }
+
+    // "" or "/" moves the entry to the top level
+    pub fn set_folder(&mut self, folder: &str) {
+        let folder = normalize_folder(folder);
+        if self.folder != folder {
+            self.folder = folder;
+            self.record_metadata_change("folder");
+        }
+    }
+
+    // tags are compared case-insensitively, so adding a tag twice keeps one
+    pub fn add_tag(&mut self, tag: &str) {
+        let tag = tag.trim();
+        if !tag.is_empty() && !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+            self.record_metadata_change("tags");
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let count = self.tags.len();
+        self.tags.retain(|t| !t.eq_ignore_ascii_case(tag.trim()));
+        let removed = self.tags.len() != count;
+        if removed {
+            self.record_metadata_change("tags");
+        }
+        removed
+    }
+
+    // replaces all tags at once, e.g. when editing them
+    pub fn set_tags(&mut self, tags: &[String]) {
+        let previous = std::mem::take(&mut self.tags);
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !self.has_tag(tag) {
+                self.tags.push(tag.to_string());
+            }
+        }
+        if self.tags != previous {
+            self.record_metadata_change("tags");
+        }
+    }
+
+    pub fn set_expiry(&mut self, day: NaiveDate) {
+        if self.expires != Some(day) {
+            self.expires = Some(day);
+            self.record_metadata_change("expires");
+        }
+    }
+
+    pub fn remove_expiry(&mut self) {
+        if self.expires.take().is_some() {
+            self.record_metadata_change("expires");
+        }
+    }
+
+    // e.g. when the credentials were copied or filled in by the extension
+    pub fn mark_used(&mut self) {
+        self.last_used = Some(Utc::now());
+    }
+
+    pub fn mark_modified(&mut self) {
+        self.modified = Some(Utc::now());
+    }
+
+    // for one of the values listed by changed_metadata, called after it was changed
+    fn record_metadata_change(&mut self, value: &str) {
+        let changes = self.metadata_changed.entry(value.to_string()).or_default();
+        changes.insert(0, Utc::now());
+        changes.truncate(MAX_METADATA_CHANGES);
+        self.mark_modified();
+    }
+}
+
+// 128 random bits as hex
+fn new_entry_id() -> String {
+    let bytes: [u8; 16] = rand::rng().random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Canonical form of a folder path: no empty segments and no leading or trailing slashes.
+/// The top level of the vault is None.
+pub fn normalize_folder(folder: &str) -> Option<String> {
+    let segments: Vec<&str> = folder
+        .split('/')
+        .map(|segment| segment.trim())
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("/"))
+    }
+}
+
+impl EntryVersion {
+    pub fn same_values(&self, other: &EntryVersion) -> bool {
+        self.changed_values(other).is_empty()
+    }
+
+    /// Names of the values that differ between the two versions.
+    pub fn changed_values(&self, other: &EntryVersion) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.username != other.username {
+            changed.push("username");
+        }
+        if self.password != other.password {
+            changed.push("password");
+        }
+        if self.url != other.url {
+            changed.push("url");
+        }
+        if self.notes != other.notes {
+            changed.push("notes");
+        }
+        if self.fields != other.fields {
+            changed.push("fields");
+        }
+        changed
+    }
+}
+
+impl CustomField {
+    pub fn new(
+        name: String,
+        field_type: FieldType,
+        value: String,
+    ) -> Result<CustomField, VaultError> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(VaultError::InvalidField("name is empty".to_string()));
+        }
+        field_type.validate(&value)?;
+        Ok(CustomField {
+            name,
+            value,
+            field_type,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        self.field_type
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.field_type == FieldType::Hidden
+    }
+}
+
+impl FieldType {
+    pub fn validate(&self, value: &str) -> Result<(), VaultError> {
+        match self {
+            FieldType::Text | FieldType::Hidden => {
+                if value.contains('\n') {
+                    return Err(VaultError::InvalidField(
+                        "use the multiline type for values with line breaks".to_string(),
+                    ));
+                }
+            }
+            FieldType::Multiline => { /* anything goes */ }
+            FieldType::Url => {
+                if Url::parse(value).is_err() && Url::parse(&format!("https://{}", value)).is_err()
+                {
+                    return Err(VaultError::InvalidField(format!(
+                        "'{}' is not a valid URL",
+                        value
+                    )));
+                }
+            }
+            FieldType::Email => {
+                let valid = match value.split_once('@') {
+                    Some((local, domain)) => {
+                        !local.is_empty()
+                            && domain.contains('.')
+                            && !domain.starts_with('.')
+                            && !domain.ends_with('.')
+                            && !domain.contains('@')
+                            && !value.contains(char::is_whitespace)
+                    }
+                    None => false,
+                };
+                if !valid {
+                    return Err(VaultError::InvalidField(format!(
+                        "'{}' is not a valid email address",
+                        value
+                    )));
+                }
+            }
+            FieldType::Date => {
+                if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+                    return Err(VaultError::InvalidField(format!(
+                        "'{}' is not a date in the format YYYY-MM-DD",
+                        value
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FieldType::Text => write!(f, "text"),
+            FieldType::Hidden => write!(f, "hidden"),
+            FieldType::Url => write!(f, "url"),
+            FieldType::Email => write!(f, "email"),
+            FieldType::Date => write!(f, "date"),
+            FieldType::Multiline => write!(f, "multiline"),
+        }
     }
 }
 
@@ -111,6 +784,8 @@ impl Vault {
         Vault {
             name,
             entries: vec![],
+            settings: VaultSettings::default(),
+            removed: BTreeMap::new(),
         }
     }
 
@@ -126,10 +801,15 @@ impl Vault {
         self.name = name;
     }
 
-    pub fn add_entry(&mut self, entry: Entry) -> Result<(), VaultError> {
+    pub fn add_entry(&mut self, mut entry: Entry) -> Result<(), VaultError> {
         if self.entryname_exists(&entry.entryname) {
             return Err(VaultError::NameExists);
         }
+        // e.g. the same export imported twice, ids stay unique within a vault
+        if entry.id.is_empty() || self.entries.iter().any(|value| value.id == entry.id) {
+            entry.id = new_entry_id();
+        }
+        self.removed.remove(&entry.id);
         self.entries.push(entry);
         Ok(())
     }
@@ -145,11 +825,31 @@ impl Vault {
     }
 
     pub fn remove_entry_by_name(&mut self, name: &String) {
-        self.entries.retain(|value| value.entryname != *name);
+        self.remove_entries(|value| value.entryname == *name);
     }
 
     pub fn remove_entry_by_entry(&mut self, entry: Entry) {
-        self.entries.retain(|value| *value != entry);
+        self.remove_entries(|value| *value == entry);
+    }
+
+    /// Gives entries loaded from a vault written before ids existed an id. It is derived from
+    /// name and creation date, so every copy of the vault assigns the same one.
+    pub fn assign_missing_ids(&mut self) {
+        for entry in &mut self.entries {
+            if entry.id.is_empty() {
+                let created = entry.created.map(|c| c.to_rfc3339()).unwrap_or_default();
+                let hash = Sha256::digest(format!("{}\0{}", entry.entryname, created).as_bytes());
+                entry.id = hash.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+            }
+        }
+    }
+
+    fn remove_entries(&mut self, matches: impl Fn(&Entry) -> bool) {
+        let now = Utc::now();
+        for entry in self.entries.iter().filter(|value| matches(value)) {
+            self.removed.insert(entry.id.clone(), now);
+        }
+        self.entries.retain(|value| !matches(value));
     }
 
     pub fn get_entries(&self) -> &Vec<Entry> {
@@ -1,21 +1,42 @@
+use chrono::{Local, NaiveDateTime};
 use directories::ProjectDirs;
 use secrecy::SecretString;
 use std::fs::{self, File};
 
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::str;
+use std::sync::Mutex;
+use tempfile::NamedTempFile;
 
+use crate::config;
 use crate::crypto;
-use crate::errors::VaultError;
+use crate::errors::{CryptoError, VaultError};
+use crate::kdbx::{self, KdbxImport};
+use crate::keyfile::{self, KeyFile};
 use crate::vault_entry_manager::Vault;
+use crate::vault_format::{self, VaultFile, VaultHeader};
+
+// Timestamp in the file name of a backup: `<vault>.<timestamp>.bak`
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%6f";
+
+// Vault files outside the vault directories (`open --file`, `init --dir`), by vault name
+static VAULT_FILES: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+
+/// An encrypted copy of a vault file, taken right before the vault was overwritten.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultBackup {
+    pub path: PathBuf,
+    pub created: NaiveDateTime,
+}
 
 //----------------------------------------------------------------------------
 // Public functions
 //----------------------------------------------------------------------------
 
 pub fn initialize_vault(name: String) -> Result<Vault, VaultError> {
-    let path = get_vaults_dir()?.join(format!("{name}.psdb"));
+    let path = get_vault_path(&name)?;
     if path.exists() {
         return Err(VaultError::FileExists);
     }
@@ -24,45 +45,73 @@ pub fn initialize_vault(name: String) -> Result<Vault, VaultError> {
 }
 
 //encrypts file with a master password -> use session.rs to remember the master password temporarily. must always be called with the correct master from the session
+//refuses to silently drop the key file of a vault that requires one
 pub fn close_vault(vault: &Vault, password: SecretString) -> Result<(), VaultError> {
-    let encrypted_vault = crypto::encrypt_vault(&password, vault.to_json())?;
-    let path = get_vaults_dir()?.join(format!("{}.psdb", vault.name));
-    let mut file = File::create(path)?;
-    file.write_all(&encrypted_vault)?;
-    Ok(())
+    let path = get_vault_path(&vault.name)?;
+    if path.exists()
+        && let VaultFile::Current { header, .. } = vault_format::parse(&read_file_to_bytes(&path)?)?
+        && header.requires_key_file()
+    {
+        return Err(VaultError::KeyFileRequired);
+    }
+    write_vault_file(&path, vault, &password, None)
+}
+
+/// Like `close_vault`, the vault then requires `key_file` (or no key file at all for `None`).
+pub fn close_vault_with_key_file(
+    vault: &Vault,
+    password: SecretString,
+    key_file: Option<&KeyFile>,
+) -> Result<(), VaultError> {
+    let path = get_vault_path(&vault.name)?;
+    write_vault_file(&path, vault, &password, key_file)
 }
 
 //opens the vault + checks if master password was correct by successfully encrypting the file
+//header-less vaults of the first format are upgraded to the current format right away
 pub fn open_vault(file_name: String, password: SecretString) -> Result<Vault, VaultError> {
-    let path = get_vaults_dir()?.join(format!("{file_name}.psdb"));
+    open_vault_with_key_file(file_name, password, None)
+}
 
-    let encrypted_bytes = read_file_to_bytes(&path)?;
-    let decrypted_json = crypto::decrypt_vault(password, &encrypted_bytes)?;
-    let vault = vault_from_json(&decrypted_json)?; //deleted mut
+/// Opens a vault that may require a key file.
+pub fn open_vault_with_key_file(
+    file_name: String,
+    password: SecretString,
+    key_file: Option<&KeyFile>,
+) -> Result<Vault, VaultError> {
+    let path = get_vault_path(&file_name)?;
+
+    let (mut vault, legacy) = decrypt_vault_file(&path, &password, key_file)?;
+    // a copied or renamed file is saved back to itself, not to the vault it was copied from
+    vault.set_name(file_name);
+    if legacy {
+        migrate_legacy_vault(&path, &vault, &password)?;
+    }
     Ok(vault)
 }
 
-/// Checks if any vaults exists in the vault folder.
-pub fn check_vaults_exist() -> bool {
-    let vaults_dir = match get_vaults_dir() {
-        Ok(dir) => dir,
-        Err(_) => return false,
-    };
+/// Decrypts the vault file at `path` without changing it, e.g. another copy of a vault to merge.
+pub fn read_vault_file(
+    path: &Path,
+    password: SecretString,
+    key_file: Option<&KeyFile>,
+) -> Result<Vault, VaultError> {
+    decrypt_vault_file(path, &password, key_file).map(|(vault, _)| vault)
+}
 
-    fs::read_dir(vaults_dir)
-        .map(|mut entries| {
-            entries.any(|e| {
-                e.map(|entry| {
-                    entry
-                        .path()
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| ext == "psdb")
-                        .unwrap_or(false)
-                })
-                .unwrap_or(false)
-            })
-        })
+/// Reads the unencrypted header of a vault file, `None` for vaults without a header.
+pub fn read_vault_header(name: &str) -> Result<Option<VaultHeader>, VaultError> {
+    let file_bytes = read_file_to_bytes(&get_vault_path(name)?)?;
+    match vault_format::parse(&file_bytes)? {
+        VaultFile::Current { header, .. } => Ok(Some(header)),
+        VaultFile::Legacy { .. } => Ok(None),
+    }
+}
+
+/// Checks if any vaults exists in the vault folders.
+pub fn check_vaults_exist() -> bool {
+    list_vaults()
+        .map(|vaults| !vaults.is_empty())
         .unwrap_or(false)
 }
 
@@ -78,31 +127,123 @@ pub fn change_master_pw(
     Ok(())
 }
 
+/// Names of the vaults in all vault directories and of the registered vault files.
+/// A name found in several places is listed once, `get_vault_path` picks the first one.
 pub fn list_vaults() -> Result<Vec<String>, VaultError> {
     let mut vector = Vec::new();
-    let vaults_dir = get_vaults_dir()?;
-    let entries = fs::read_dir(vaults_dir)?;
+    for (_, names) in list_vaults_by_dir()? {
+        for name in names {
+            if !vector.contains(&name) {
+                vector.push(name);
+            }
+        }
+    }
+    Ok(vector)
+}
 
-    for entry in entries {
-        let unwrapped_entry = entry?;
-        let entry_path = unwrapped_entry.path();
+/// The vaults of every directory, in the order they are searched: the registered vault files
+/// (by their directory), the vault directory, then `vault_dirs` of the config.
+pub fn list_vaults_by_dir() -> Result<Vec<(PathBuf, Vec<String>)>, VaultError> {
+    let mut dirs: Vec<(PathBuf, Vec<String>)> = Vec::new();
+    for (name, path) in lock_vault_files().iter() {
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        match dirs.iter_mut().find(|(known, _)| *known == dir) {
+            Some((_, names)) => names.push(name.clone()),
+            None => dirs.push((dir, vec![name.clone()])),
+        }
+    }
+    for dir in get_vault_dirs()? {
+        let names = vaults_in_dir(&dir)?;
+        match dirs.iter_mut().find(|(known, _)| *known == dir) {
+            Some((_, known_names)) => {
+                for name in names {
+                    if !known_names.contains(&name) {
+                        known_names.push(name);
+                    }
+                }
+            }
+            None => dirs.push((dir, names)),
+        }
+    }
+    Ok(dirs)
+}
 
-        let is_psdb = entry_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext == "psdb")
-            .unwrap_or(false);
+/// Makes the vault file at `path` available under its file name, even outside the vault
+/// directories, for as long as the process runs. Returns the vault name.
+pub fn register_vault_file(path: &Path) -> Result<String, VaultError> {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("psdb") {
+        return Err(VaultError::AnyhowError(anyhow::anyhow!(
+            "{:?} is not a vault file (*.psdb)",
+            path
+        )));
+    }
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or(VaultError::InvalidVaultName)?
+        .to_string();
+    // the name ends up in backup file names and `vault:entry`
+    check_vault_name(&name)?;
+    let path = std::path::absolute(path)?;
 
-        if is_psdb && let Some(file_name) = entry_path.file_stem().and_then(|s| s.to_str()) {
-            let string_entry = file_name.to_string();
-            vector.push(string_entry);
-        }
+    let taken_by = match registered_vault_file(&name) {
+        Some(registered) => Some(registered),
+        None => Some(get_vault_path(&name)?).filter(|other| other.exists()),
+    };
+    if let Some(other) = taken_by
+        && std::path::absolute(&other)? != path
+    {
+        return Err(VaultError::AnyhowError(anyhow::anyhow!(
+            "A vault named '{}' already exists at {:?}, rename the file to open it",
+            name,
+            other
+        )));
     }
-    Ok(vector)
+    lock_vault_files().insert(name.clone(), path);
+    Ok(name)
+}
+
+/// Vault names are used in file names, so only letters, digits, '_' and '-' are allowed.
+pub fn check_vault_name(vault_name: &str) -> Result<(), VaultError> {
+    if vault_name.len() > 64 {
+        return Err(VaultError::InvalidVaultName);
+    }
+    if vault_name.is_empty() {
+        return Err(VaultError::InvalidVaultName);
+    }
+    if !vault_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(VaultError::InvalidVaultName);
+    }
+
+    Ok(())
 }
 
+/// Undoes `register_vault_file`, e.g. when creating the vault failed.
+pub fn forget_vault_file(name: &str) {
+    lock_vault_files().remove(name);
+}
+
+/// The path a vault was registered with by `register_vault_file`, if any.
+pub fn registered_vault_file(name: &str) -> Option<PathBuf> {
+    lock_vault_files().get(name).cloned()
+}
+
+/// The registered file of the vault, else the first vault directory that contains it,
+/// else where a new vault of that name is created.
 pub fn get_vault_path(name: &str) -> Result<PathBuf, VaultError> {
-    Ok(get_vaults_dir()?.join(format!("{name}.psdb")))
+    if let Some(path) = registered_vault_file(name) {
+        return Ok(path);
+    }
+    let file_name = format!("{name}.psdb");
+    let dirs = get_vault_dirs()?;
+    Ok(dirs
+        .iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dirs[0].join(&file_name)))
 }
 
 pub fn vault_exists(name: &str) -> Result<bool, VaultError> {
@@ -113,25 +254,191 @@ pub fn delete_vault_file(name: &str) -> Result<(), VaultError> {
     let path = get_vault_path(name)?;
     if path.exists() {
         fs::remove_file(path)?;
+        for backup in list_backups(name)? {
+            fs::remove_file(backup.path)?;
+        }
+        lock_vault_files().remove(name);
         Ok(())
     } else {
         Err(VaultError::VaultDoesNotExist)
     }
 }
 
-//----------------------------------------------------------------------------
-// Internal helper functions (private)
-//----------------------------------------------------------------------------
+/// Lists the backups of a vault, newest first.
+pub fn list_backups(name: &str) -> Result<Vec<VaultBackup>, VaultError> {
+    let backups_dir = get_backups_dir(name)?;
+    let mut backups = Vec::new();
 
-fn get_vaults_dir() -> Result<PathBuf, VaultError> {
-    let proj_dirs = ProjectDirs::from("", "", "password_manager").ok_or_else(|| {
+    for entry in fs::read_dir(backups_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        let Some((vault_name, timestamp)) = file_name
+            .strip_suffix(".bak")
+            .and_then(|stem| stem.rsplit_once('.'))
+        else {
+            continue;
+        };
+        if vault_name != name {
+            continue;
+        }
+        if let Ok(created) = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT) {
+            backups.push(VaultBackup { path, created });
+        }
+    }
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    Ok(backups)
+}
+
+/// Replaces the vault file with the given backup. The current file is backed up first,
+/// so a restore can be undone with another restore.
+pub fn restore_backup(name: &str, backup: &VaultBackup) -> Result<(), VaultError> {
+    let path = get_vault_path(name)?;
+    let backup_bytes = read_file_to_bytes(&backup.path)?;
+    // refuse to restore something that would not open anyway
+    vault_format::parse(&backup_bytes)?;
+
+    if path.exists() {
+        create_backup(&path, name)?;
+    }
+    write_atomically(&path, &backup_bytes)
+}
+
+/// Checks the signature of a file, without needing its password.
+pub fn is_kdbx_file(path: &Path) -> bool {
+    let mut signature = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok()
+        && kdbx::is_kdbx(&signature)
+}
+
+/// Reads a KeePass database, `key_file` is the path of its KeePass key file if it uses one.
+pub fn read_kdbx_file(
+    path: &Path,
+    password: &SecretString,
+    key_file: Option<&Path>,
+) -> Result<KdbxImport, VaultError> {
+    let bytes = fs::read(path)?;
+    let key_file = key_file.map(fs::read).transpose()?;
+    kdbx::read(&bytes, password, key_file.as_deref())
+}
+
+/// Writes the vault as a KeePass database, protected by `password` and optionally a key file.
+pub fn write_kdbx_file(
+    vault: &Vault,
+    path: &Path,
+    password: &SecretString,
+    key_file: Option<&Path>,
+) -> Result<(), VaultError> {
+    let key_file = key_file.map(fs::read).transpose()?;
+    let bytes = kdbx::write(vault, password, key_file.as_deref())?;
+    write_atomically(path, &bytes)
+}
+
+/// Writes the vault in the `.psdb` format, protected by `passphrase` instead of the master password.
+/// `import` reads it back, no backups are taken of an existing file.
+pub fn write_encrypted_export(
+    vault: &Vault,
+    path: &Path,
+    passphrase: &SecretString,
+) -> Result<(), VaultError> {
+    let kdf = vault.settings.kdf;
+    kdf.validate()?;
+    let encrypted_vault = crypto::encrypt_vault(passphrase, vault.to_json(), kdf.to_params())?;
+    let file_bytes = vault_format::encode(&VaultHeader::new(kdf), &encrypted_vault)?;
+    write_atomically(path, &file_bytes)
+}
+
+/// Reads a file written by `write_encrypted_export` (or a vault file without key file).
+pub fn read_encrypted_export(path: &Path, passphrase: &SecretString) -> Result<Vault, VaultError> {
+    let file_bytes = read_file_to_bytes(path)?;
+    match vault_format::parse(&file_bytes)? {
+        VaultFile::Current { header, .. } if header.requires_key_file() => {
+            Err(VaultError::KeyFileRequired)
+        }
+        VaultFile::Current { ciphertext, .. } => decrypt_vault_json(passphrase.clone(), ciphertext),
+        VaultFile::Legacy { .. } => Err(VaultError::CorruptVault),
+    }
+}
+
+/// Checks for the `.psdb` magic, without needing the passphrase.
+pub fn is_encrypted_export(path: &Path) -> bool {
+    let mut magic = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == vault_format::MAGIC
+}
+
+/// Writes a plaintext export. The file is only readable by the current user, on Unix at least.
+pub fn write_export_file(path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+    // the temporary file behind write_atomically is created with mode 0600
+    write_atomically(path, contents)
+}
+
+/// Writes to a temporary file in the same directory, syncs it and renames it over `path`,
+/// so a crash or a full disk leaves either the old or the new file, never a truncated one.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), VaultError> {
+    let dir = path.parent().ok_or_else(|| {
         VaultError::IoError(std::io::Error::new(
             std::io::ErrorKind::NotFound,
-            "Home directory not found",
+            "Path has no parent directory",
         ))
     })?;
+    // a bare file name like `export.kdbx` has an empty parent
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
 
-    let vaults_dir = proj_dirs.data_dir().join("vaults");
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+    temp_file.write_all(bytes)?;
+    temp_file.as_file().sync_all()?;
+    temp_file
+        .persist(path)
+        .map_err(|e| VaultError::IoError(e.error))?;
+
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+// The vault directory, where new vaults are created, followed by `vault_dirs` of the config
+fn get_vault_dirs() -> Result<Vec<PathBuf>, VaultError> {
+    let mut dirs = vec![get_vaults_dir()?];
+    for dir in config::current().vault_dirs {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    Ok(dirs)
+}
+
+fn get_vaults_dir() -> Result<PathBuf, VaultError> {
+    let config = config::current();
+    let vaults_dir = match (config.vault_dir, config::portable_dir()) {
+        (Some(dir), _) => dir,
+        // a portable installation keeps its vaults next to the binary
+        (None, Some(dir)) => dir.join("vaults"),
+        (None, None) => {
+            let proj_dirs = ProjectDirs::from("", "", "password_manager").ok_or_else(|| {
+                VaultError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Home directory not found",
+                ))
+            })?;
+            proj_dirs.data_dir().join("vaults")
+        }
+    };
 
     // ensure, dir exists
     match fs::create_dir_all(&vaults_dir) {
@@ -143,6 +450,45 @@ fn get_vaults_dir() -> Result<PathBuf, VaultError> {
     }
 }
 
+// Backups are kept in `backups` next to the vault, so they move along with it (e.g. on a USB stick)
+fn get_backups_dir(name: &str) -> Result<PathBuf, VaultError> {
+    let vault_path = get_vault_path(name)?;
+    let backups_dir = vault_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("backups");
+    fs::create_dir_all(&backups_dir)?;
+    Ok(backups_dir)
+}
+
+// Names of the `.psdb` files in `dir`, a directory that does not exist has none
+fn vaults_in_dir(dir: &Path) -> Result<Vec<String>, VaultError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(VaultError::IoError(e)),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry_path = entry?.path();
+        let is_psdb = entry_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext == "psdb")
+            .unwrap_or(false);
+
+        if is_psdb && let Some(file_name) = entry_path.file_stem().and_then(|s| s.to_str()) {
+            names.push(file_name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn lock_vault_files() -> std::sync::MutexGuard<'static, BTreeMap<String, PathBuf>> {
+    VAULT_FILES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 fn read_file_to_bytes(path: &Path) -> Result<Vec<u8>, VaultError> {
     let mut file = File::open(path)?;
     let mut contents = Vec::new();
@@ -151,5 +497,105 @@ fn read_file_to_bytes(path: &Path) -> Result<Vec<u8>, VaultError> {
 }
 
 fn vault_from_json(input: &str) -> Result<Vault, serde_json::Error> {
-    serde_json::from_str(input)
+    let mut vault: Vault = serde_json::from_str(input)?;
+    vault.assign_missing_ids();
+    Ok(vault)
+}
+
+// The vault and whether the file is still in the header-less first format.
+fn decrypt_vault_file(
+    path: &Path,
+    password: &SecretString,
+    key_file: Option<&KeyFile>,
+) -> Result<(Vault, bool), VaultError> {
+    let file_bytes = read_file_to_bytes(path)?;
+    match vault_format::parse(&file_bytes)? {
+        VaultFile::Current { header, ciphertext } => {
+            // a wrong key file fails to decrypt like a wrong password
+            match (header.requires_key_file(), key_file) {
+                (true, None) => return Err(VaultError::KeyFileRequired),
+                (false, Some(_)) => return Err(VaultError::InvalidKeyFile),
+                _ => { /* matches */ }
+            }
+            let secret = keyfile::combine_with_password(password, key_file);
+            let mut vault = decrypt_vault_json(secret, ciphertext)?;
+            // the header describes how the file was actually written, keep using those parameters
+            if header.kdf.validate().is_ok() {
+                vault.settings.kdf = header.kdf;
+            }
+            Ok((vault, false))
+        }
+        VaultFile::Legacy { ciphertext } => {
+            if key_file.is_some() {
+                return Err(VaultError::InvalidKeyFile);
+            }
+            let vault = decrypt_vault_json(password.clone(), ciphertext)?;
+            Ok((vault, true))
+        }
+    }
+}
+
+fn write_vault_file(
+    path: &Path,
+    vault: &Vault,
+    password: &SecretString,
+    key_file: Option<&KeyFile>,
+) -> Result<(), VaultError> {
+    let kdf = vault.settings.kdf;
+    kdf.validate()?;
+    let secret = keyfile::combine_with_password(password, key_file);
+    let encrypted_vault = crypto::encrypt_vault(&secret, vault.to_json(), kdf.to_params())?;
+
+    let mut header = VaultHeader::new(kdf);
+    header.key_file = key_file.is_some();
+    let file_bytes = vault_format::encode(&header, &encrypted_vault)?;
+
+    let backup_count = vault.settings.backup_count;
+    if backup_count > 0 && path.exists() {
+        create_backup(path, vault.get_name())?;
+        prune_backups(vault.get_name(), backup_count)?;
+    }
+    write_atomically(path, &file_bytes)
+}
+
+fn create_backup(path: &Path, name: &str) -> Result<PathBuf, VaultError> {
+    let timestamp = Local::now().format(BACKUP_TIMESTAMP_FORMAT);
+    let backup_path = get_backups_dir(name)?.join(format!("{name}.{timestamp}.bak"));
+    fs::copy(path, &backup_path)?;
+    File::open(&backup_path)?.sync_all()?;
+    Ok(backup_path)
+}
+
+fn prune_backups(name: &str, keep: usize) -> Result<(), VaultError> {
+    for backup in list_backups(name)?.into_iter().skip(keep) {
+        fs::remove_file(backup.path)?;
+    }
+    Ok(())
+}
+
+fn decrypt_vault_json(password: SecretString, ciphertext: &[u8]) -> Result<Vault, VaultError> {
+    let decrypted_json = crypto::decrypt_vault(password, ciphertext).map_err(|e| match e {
+        CryptoError::CouldNotDecrypt => VaultError::InvalidKey,
+        _ => VaultError::CorruptVault,
+    })?;
+    vault_from_json(&decrypted_json).map_err(|_| VaultError::CorruptVault)
+}
+
+// Keeps the old file as `<name>.psdb.v0` and rewrites the vault with a header.
+fn migrate_legacy_vault(
+    path: &Path,
+    vault: &Vault,
+    password: &SecretString,
+) -> Result<(), VaultError> {
+    let legacy_copy = path.with_extension(format!("psdb.v{}", vault_format::LEGACY_FORMAT_VERSION));
+    fs::copy(path, &legacy_copy)?;
+    write_vault_file(path, vault, password, None)?;
+
+    eprintln!(
+        "Vault '{}' was upgraded to format version {} (old file kept as {:?}).",
+        vault.get_name(),
+        vault_format::FORMAT_VERSION,
+        legacy_copy
+    );
+    Ok(())
 }
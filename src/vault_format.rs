@@ -0,0 +1,334 @@
+/*what belongs here:
+- The `.psdb` container header in front of the encrypted vault
+- Telling a current vault from a header-less one of the first format, a future format or a corrupt file
+
+Layout of a `.psdb` file (integers are little endian):
+  8 bytes  magic "RUSTPASS"
+  2 bytes  format version
+  4 bytes  length n of the header
+  n bytes  header as JSON (VaultHeader)
+  rest     ciphertext produced by crypto::encrypt_vault
+
+Vaults written before the header existed (format version 0) consist of the ciphertext only.
+The header is not secret, it only describes how the ciphertext was produced.
+*/
+
+use enc_file::KdfParams;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::crypto;
+use crate::errors::VaultError;
+
+pub const MAGIC: &[u8; 8] = b"RUSTPASS";
+pub const FORMAT_VERSION: u16 = 1;
+pub const LEGACY_FORMAT_VERSION: u16 = 0;
+
+// Upper bound for the header JSON, anything larger is treated as a corrupt file
+const MAX_HEADER_LEN: usize = 64 * 1024;
+// Bounds for the Argon2id parameters, the lower ones are enforced by enc_file as well
+pub const MIN_KDF_MEMORY_KIB: u32 = 64 * 1024;
+pub const MAX_KDF_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+pub const MIN_KDF_ITERATIONS: u32 = 3;
+pub const MAX_KDF_ITERATIONS: u32 = 100;
+pub const MAX_KDF_PARALLELISM: u32 = 64;
+// Magic inside the header enc_file puts in front of its ciphertext
+const ENC_FILE_MAGIC: &[u8; 8] = b"ENCFILE\0";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultHeader {
+    pub cipher: String,
+    pub kdf: KdfSettings,
+    // Whether the vault needs a key file next to the master password (see keyfile.rs)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub key_file: bool,
+    // Written by earlier versions instead of `key_file`, only read to know a key file is needed
+    #[serde(default, skip_serializing)]
+    key_file_check: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KdfSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(Debug)]
+pub enum VaultFile<'a> {
+    Current {
+        header: VaultHeader,
+        ciphertext: &'a [u8],
+    },
+    Legacy {
+        ciphertext: &'a [u8],
+    },
+}
+
+impl VaultHeader {
+    pub fn new(kdf: KdfSettings) -> VaultHeader {
+        VaultHeader {
+            cipher: "xchacha20poly1305".to_string(),
+            kdf,
+            key_file: false,
+            key_file_check: None,
+        }
+    }
+
+    pub fn requires_key_file(&self) -> bool {
+        self.key_file || self.key_file_check.is_some()
+    }
+}
+
+impl KdfSettings {
+    pub fn to_params(self) -> KdfParams {
+        KdfParams {
+            t_cost: self.iterations,
+            mem_kib: self.memory_kib,
+            parallelism: self.parallelism,
+        }
+    }
+
+    /// Checks the parameters before they are used for a vault.
+    pub fn validate(&self) -> Result<(), VaultError> {
+        if !(MIN_KDF_MEMORY_KIB..=MAX_KDF_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(VaultError::InvalidKdfParameters(format!(
+                "memory must be between {} and {} MiB",
+                MIN_KDF_MEMORY_KIB / 1024,
+                MAX_KDF_MEMORY_KIB / 1024
+            )));
+        }
+        if !(MIN_KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(&self.iterations) {
+            return Err(VaultError::InvalidKdfParameters(format!(
+                "iterations must be between {} and {}",
+                MIN_KDF_ITERATIONS, MAX_KDF_ITERATIONS
+            )));
+        }
+        if !(1..=MAX_KDF_PARALLELISM).contains(&self.parallelism) {
+            return Err(VaultError::InvalidKdfParameters(format!(
+                "parallelism must be between 1 and {}",
+                MAX_KDF_PARALLELISM
+            )));
+        }
+        Ok(())
+    }
+
+    /// Keeps memory and parallelism and picks the number of iterations, so that
+    /// unlocking takes about `target` on this machine.
+    pub fn calibrate(self, target: Duration) -> Result<KdfSettings, VaultError> {
+        self.validate()?;
+        let probe = KdfSettings {
+            iterations: MIN_KDF_ITERATIONS,
+            ..self
+        };
+        let elapsed = crypto::measure_kdf(probe.to_params())?;
+
+        // the cost of Argon2id grows linearly with the number of iterations
+        let per_iteration = elapsed.as_secs_f64() / MIN_KDF_ITERATIONS as f64;
+        let iterations = (target.as_secs_f64() / per_iteration.max(f64::EPSILON)).round() as u32;
+        Ok(KdfSettings {
+            iterations: iterations.clamp(MIN_KDF_ITERATIONS, MAX_KDF_ITERATIONS),
+            ..self
+        })
+    }
+}
+
+impl std::fmt::Display for KdfSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Argon2id, {} MiB, {} iterations, parallelism {}",
+            self.memory_kib / 1024,
+            self.iterations,
+            self.parallelism
+        )
+    }
+}
+
+impl Default for KdfSettings {
+    fn default() -> Self {
+        let params = KdfParams::default();
+        KdfSettings {
+            memory_kib: params.mem_kib,
+            iterations: params.t_cost,
+            parallelism: params.parallelism,
+        }
+    }
+}
+
+//----------------------------------------------------------------------------
+// Public functions
+//----------------------------------------------------------------------------
+
+/// Puts the header in front of the ciphertext.
+pub fn encode(header: &VaultHeader, ciphertext: &[u8]) -> Result<Vec<u8>, VaultError> {
+    let header_json = serde_json::to_vec(header)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 6 + header_json.len() + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&(header_json.len() as u32).to_le_bytes());
+    out.extend_from_slice(&header_json);
+    out.extend_from_slice(ciphertext);
+    Ok(out)
+}
+
+/// Splits the contents of a `.psdb` file into header and ciphertext.
+pub fn parse(bytes: &[u8]) -> Result<VaultFile<'_>, VaultError> {
+    if !bytes.starts_with(MAGIC) {
+        if looks_like_legacy(bytes) {
+            return Ok(VaultFile::Legacy { ciphertext: bytes });
+        }
+        return Err(VaultError::CorruptVault);
+    }
+
+    let rest = &bytes[MAGIC.len()..];
+    if rest.len() < 6 {
+        return Err(VaultError::CorruptVault);
+    }
+
+    let version = u16::from_le_bytes([rest[0], rest[1]]);
+    if version > FORMAT_VERSION {
+        return Err(VaultError::UnsupportedFormat(version));
+    }
+
+    let header_len = u32::from_le_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize;
+    if header_len > MAX_HEADER_LEN || rest.len() < 6 + header_len {
+        return Err(VaultError::CorruptVault);
+    }
+
+    let header: VaultHeader =
+        serde_json::from_slice(&rest[6..6 + header_len]).map_err(|_| VaultError::CorruptVault)?;
+    Ok(VaultFile::Current {
+        header,
+        ciphertext: &rest[6 + header_len..],
+    })
+}
+
+//----------------------------------------------------------------------------
+// Internal helper functions (private)
+//----------------------------------------------------------------------------
+
+// enc_file output starts with the length of its CBOR header, which contains its own magic.
+fn looks_like_legacy(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 {
+        return false;
+    }
+    let header_len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    if header_len > MAX_HEADER_LEN || bytes.len() < 4 + header_len {
+        return false;
+    }
+
+    let magic = cbor_encoded_magic();
+    bytes[4..4 + header_len]
+        .windows(magic.len())
+        .any(|window| window == magic.as_slice())
+}
+
+// The magic is a `[u8; 8]`, which CBOR stores as an array of 8 unsigned integers.
+// Integers below 24 take one byte, larger ones are prefixed with 0x18.
+fn cbor_encoded_magic() -> Vec<u8> {
+    let mut encoded = vec![0x80 | ENC_FILE_MAGIC.len() as u8];
+    for &byte in ENC_FILE_MAGIC {
+        if byte >= 24 {
+            encoded.push(0x18);
+        }
+        encoded.push(byte);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_settings() -> KdfSettings {
+        KdfSettings {
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_encode_and_parse() {
+        let header = VaultHeader::new(test_settings());
+        let bytes = encode(&header, b"ciphertext").unwrap();
+        assert!(bytes.starts_with(MAGIC));
+
+        match parse(&bytes).unwrap() {
+            VaultFile::Current {
+                header: parsed,
+                ciphertext,
+            } => {
+                assert_eq!(parsed, header);
+                assert_eq!(ciphertext, b"ciphertext");
+            }
+            VaultFile::Legacy { .. } => panic!("Expected current format"),
+        }
+    }
+
+    #[test]
+    fn test_future_version_is_rejected() {
+        let mut bytes = encode(&VaultHeader::new(test_settings()), b"ct").unwrap();
+        bytes[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            parse(&bytes),
+            Err(VaultError::UnsupportedFormat(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_garbage_is_corrupt() {
+        assert!(matches!(
+            parse(b"definitely not a vault"),
+            Err(VaultError::CorruptVault)
+        ));
+        assert!(matches!(parse(b""), Err(VaultError::CorruptVault)));
+    }
+
+    #[test]
+    fn test_truncated_header_is_corrupt() {
+        let bytes = encode(&VaultHeader::new(test_settings()), b"ct").unwrap();
+        assert!(matches!(parse(&bytes[..20]), Err(VaultError::CorruptVault)));
+    }
+
+    #[test]
+    fn test_kdf_settings_are_validated() {
+        assert!(test_settings().validate().is_ok());
+
+        let too_little_memory = KdfSettings {
+            memory_kib: 1024,
+            ..test_settings()
+        };
+        assert!(matches!(
+            too_little_memory.validate(),
+            Err(VaultError::InvalidKdfParameters(_))
+        ));
+
+        let no_lanes = KdfSettings {
+            parallelism: 0,
+            ..test_settings()
+        };
+        assert!(no_lanes.validate().is_err());
+    }
+
+    #[test]
+    fn test_calibrate_keeps_the_minimum() {
+        let calibrated = test_settings().calibrate(Duration::from_millis(1)).unwrap();
+        assert_eq!(calibrated.iterations, MIN_KDF_ITERATIONS);
+        assert_eq!(calibrated.memory_kib, test_settings().memory_kib);
+    }
+
+    #[test]
+    fn test_legacy_vault_is_detected() {
+        let password = "pw".into();
+        let ciphertext = crate::crypto::encrypt_vault(
+            &password,
+            "{}".to_string(),
+            KdfSettings::default().to_params(),
+        )
+        .unwrap();
+        assert!(matches!(parse(&ciphertext), Ok(VaultFile::Legacy { .. })));
+    }
+}
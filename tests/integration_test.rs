@@ -495,6 +495,368 @@ fn test_duplicate_entry_names_rejected() {
     let _ = delete_vault_file(vault_name);
 }
 
+#[test]
+fn test_custom_fields() {
+    let vault_name = "test_custom_fields";
+    let password = SecretString::new("FieldsTest123!".to_string().into());
+
+    let _ = delete_vault_file(vault_name);
+
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+
+    let mut entry = Entry::new("Bank".to_string(), None, None, None, None);
+    entry.set_field(
+        CustomField::new("PIN".to_string(), FieldType::Hidden, "1234".to_string()).unwrap(),
+    );
+    entry.set_field(
+        CustomField::new(
+            "Recovery codes".to_string(),
+            FieldType::Multiline,
+            "aaaa-bbbb\ncccc-dddd".to_string(),
+        )
+        .unwrap(),
+    );
+    // same name replaces the field
+    entry.set_field(
+        CustomField::new("PIN".to_string(), FieldType::Hidden, "4321".to_string()).unwrap(),
+    );
+    session
+        .opened_vault
+        .as_mut()
+        .unwrap()
+        .add_entry(entry)
+        .unwrap();
+    session.save().unwrap();
+    session.end_session().unwrap();
+
+    let mut session2 = Session::new(vault_name.to_string());
+    session2.start_session(password).unwrap();
+    let entry = session2
+        .opened_vault
+        .as_mut()
+        .unwrap()
+        .get_entry_by_name(&"Bank".to_string())
+        .unwrap();
+
+    assert_eq!(entry.get_fields().len(), 2);
+    let pin = entry.get_field("PIN").unwrap();
+    assert_eq!(pin.value(), "4321");
+    assert!(pin.is_hidden());
+    assert_eq!(
+        entry.get_field("Recovery codes").unwrap().field_type(),
+        FieldType::Multiline
+    );
+
+    entry.remove_field("PIN").unwrap();
+    assert!(entry.remove_field("PIN").is_err());
+
+    session2.end_session().unwrap();
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_custom_field_validation() {
+    let field = |field_type, value: &str| {
+        CustomField::new("field".to_string(), field_type, value.to_string())
+    };
+
+    assert!(field(FieldType::Email, "alice@example.com").is_ok());
+    assert!(field(FieldType::Email, "alice.example.com").is_err());
+    assert!(field(FieldType::Date, "2026-02-28").is_ok());
+    assert!(field(FieldType::Date, "28.02.2026").is_err());
+    assert!(field(FieldType::Url, "example.com/login").is_ok());
+    assert!(field(FieldType::Text, "two\nlines").is_err());
+    assert!(CustomField::new(" ".to_string(), FieldType::Text, "x".to_string()).is_err());
+}
+
+#[test]
+fn test_entries_without_fields_stay_compatible() {
+    // an entry as written before custom fields existed
+    let old = r#"{"entryname":"Mail","username":"bob","password":"pw","url":null,"notes":null}"#;
+    let entry: Entry = serde_json::from_str(old).unwrap();
+    assert!(entry.get_fields().is_empty());
+
+    // and entries without fields are written the same way as before
+    let json = serde_json::to_string(&entry).unwrap();
+    assert!(!json.contains("fields"));
+}
+
+#[test]
+fn test_password_history_and_restore() {
+    let vault_name = "test_history";
+    let password = SecretString::new("HistoryTest123!".to_string().into());
+
+    let _ = delete_vault_file(vault_name);
+
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+
+    let vault = session.opened_vault.as_mut().unwrap();
+    vault.settings.history_count = 2;
+    vault
+        .add_entry(Entry::new(
+            "GitHub".to_string(),
+            Some("user".to_string()),
+            Some("first".to_string()),
+            None,
+            None,
+        ))
+        .unwrap();
+    let limit = vault.settings.history_count;
+    let entry = vault.get_entry_by_name(&"GitHub".to_string()).unwrap();
+
+    for new_password in ["second", "third", "fourth"] {
+        let previous = entry.snapshot();
+        entry.set_password(new_password.to_string());
+        entry.keep_version(previous, limit);
+    }
+    // unchanged values are not recorded
+    let previous = entry.snapshot();
+    entry.keep_version(previous, limit);
+
+    let history: Vec<_> = entry
+        .get_history()
+        .iter()
+        .map(|v| v.password.clone().unwrap())
+        .collect();
+    assert_eq!(history, vec!["third", "second"]);
+    session.save().unwrap();
+    session.end_session().unwrap();
+
+    let mut session2 = Session::new(vault_name.to_string());
+    session2.start_session(password).unwrap();
+    let entry = session2
+        .opened_vault
+        .as_mut()
+        .unwrap()
+        .get_entry_by_name(&"GitHub".to_string())
+        .unwrap();
+    assert_eq!(entry.get_history().len(), 2);
+
+    entry.restore_version(1, 2).unwrap();
+    assert_eq!(entry.get_password(), &Some("second".to_string()));
+    // the replaced value can be restored again
+    assert_eq!(entry.get_history()[0].password, Some("fourth".to_string()));
+    assert!(matches!(
+        entry.restore_version(5, 2),
+        Err(VaultError::VersionNotFound)
+    ));
+
+    session2.end_session().unwrap();
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_entry_timestamps() {
+    let mut entry = Entry::new(
+        "Mail".to_string(),
+        Some("bob".to_string()),
+        Some("first".to_string()),
+        None,
+        None,
+    );
+    let created = entry.created().unwrap();
+    assert_eq!(entry.password_changed(), Some(created));
+    assert!(entry.last_used().is_none());
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    entry.set_username("alice".to_string());
+    assert!(entry.modified().unwrap() > created);
+    assert_eq!(entry.password_changed(), Some(created));
+
+    entry.set_password("second".to_string());
+    assert!(entry.password_changed().unwrap() > created);
+
+    entry.mark_used();
+    assert!(entry.last_used().is_some());
+
+    // entries written before timestamps existed have none
+    let old = r#"{"entryname":"Mail","username":"bob","password":"pw","url":null,"notes":null}"#;
+    let old_entry: Entry = serde_json::from_str(old).unwrap();
+    assert!(old_entry.created().is_none());
+    assert!(!old_entry.is_expired());
+}
+
+#[test]
+fn test_folders_and_tags() {
+    let mut entry = Entry::new("AWS".to_string(), None, None, None, None);
+    entry.set_folder(" /work//aws/prod/ ");
+    assert_eq!(entry.get_folder(), Some("work/aws/prod"));
+    assert!(entry.in_folder("work"));
+    assert!(entry.in_folder("work/aws/"));
+    assert!(entry.in_folder("/"));
+    assert!(!entry.in_folder("work/aw"));
+    assert!(!entry.in_folder("private"));
+
+    entry.add_tag("Team-A");
+    entry.add_tag("team-a");
+    entry.add_tag("ops");
+    assert_eq!(
+        entry.get_tags(),
+        &vec!["Team-A".to_string(), "ops".to_string()]
+    );
+    assert!(entry.has_tag("TEAM-A"));
+    assert!(entry.remove_tag("ops"));
+    assert!(!entry.remove_tag("ops"));
+
+    let json = serde_json::to_string(&entry).unwrap();
+    let parsed: Entry = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, entry);
+
+    entry.set_folder("/");
+    assert_eq!(entry.get_folder(), None);
+}
+
+// ============================================================================
+// IMPORT TESTS
+// ============================================================================
+
+#[test]
+fn test_csv_import_is_saved() {
+    let vault_name = "test_csv_import";
+    let password = SecretString::new("PasswordTest123!".to_string().into());
+    let csv_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        csv_file.path(),
+        "name,url,username,password,note\n\
+         GitHub,https://github.com,alice,secret1,\n\
+         GitHub,https://github.com,bob,secret2,second account\n",
+    )
+    .unwrap();
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+
+    let vault = session.opened_vault.as_mut().unwrap();
+    let imported = import::read_csv_file(csv_file.path(), import::ImportFormat::Auto).unwrap();
+    assert_eq!(imported.format, import::ImportFormat::Chrome);
+    let plan = import::plan_import(vault, imported.entries, import::ConflictPolicy::Rename);
+    assert_eq!(import::apply_import(vault, plan).unwrap(), 2);
+    session.save().unwrap();
+    drop(session);
+
+    let mut session2 = Session::new(vault_name.to_string());
+    session2.start_session(password).unwrap();
+    let vault = session2.opened_vault.as_mut().unwrap();
+    assert_eq!(
+        vault
+            .get_entry_by_name(&"GitHub (2)".to_string())
+            .unwrap()
+            .username(),
+        Some("bob")
+    );
+
+    session2.end_session().unwrap();
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_kdbx_export_can_be_imported() {
+    let vault_name = "test_kdbx_export";
+    let password = SecretString::new("PasswordTest123!".to_string().into());
+    let export_password = SecretString::new("export".to_string().into());
+    let dir = tempfile::tempdir().unwrap();
+    let export_path = dir.path().join("export.kdbx");
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+
+    let vault = session.opened_vault.as_mut().unwrap();
+    let mut entry = Entry::new(
+        "GitHub".to_string(),
+        Some("alice".to_string()),
+        Some("secret1".to_string()),
+        Some("https://github.com".to_string()),
+        None,
+    );
+    entry.set_folder("dev/work");
+    vault.add_entry(entry).unwrap();
+    vault_file_manager::write_kdbx_file(vault, &export_path, &export_password, None).unwrap();
+    assert!(vault_file_manager::is_kdbx_file(&export_path));
+
+    assert!(matches!(
+        vault_file_manager::read_kdbx_file(&export_path, &password, None),
+        Err(VaultError::InvalidKey)
+    ));
+    let database =
+        vault_file_manager::read_kdbx_file(&export_path, &export_password, None).unwrap();
+    assert!(database.warnings.is_empty());
+
+    // importing the export again only adds renamed copies
+    let plan = import::plan_import(
+        vault,
+        database.vault.entries,
+        import::ConflictPolicy::Rename,
+    );
+    assert_eq!(import::apply_import(vault, plan).unwrap(), 1);
+    let copy = vault.get_entry_by_name(&"GitHub (2)".to_string()).unwrap();
+    assert_eq!(copy.username(), Some("alice"));
+    assert_eq!(copy.get_folder(), Some("dev/work"));
+
+    session.end_session().unwrap();
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_encrypted_export_of_selected_entries() {
+    let vault_name = "test_encrypted_export";
+    let password = SecretString::new("PasswordTest123!".to_string().into());
+    let passphrase = SecretString::new("another export passphrase".to_string().into());
+    let dir = tempfile::tempdir().unwrap();
+    let export_path = dir.path().join("export.psdb");
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+
+    let vault = session.opened_vault.as_mut().unwrap();
+    for (name, url) in [
+        ("GitHub", "https://github.com"),
+        ("Gist", "https://gist.github.com"),
+        ("Mail", "https://mail.example.com"),
+    ] {
+        vault
+            .add_entry(Entry::new(
+                name.to_string(),
+                None,
+                Some("secret".to_string()),
+                Some(url.to_string()),
+                None,
+            ))
+            .unwrap();
+    }
+    let selection = export::ExportSelection {
+        domains: vec!["github.com".to_string()],
+        ..Default::default()
+    };
+    let selected = export::exported_vault(vault, &selection);
+    vault_file_manager::write_encrypted_export(&selected, &export_path, &passphrase).unwrap();
+    assert!(vault_file_manager::is_encrypted_export(&export_path));
+
+    assert!(matches!(
+        vault_file_manager::read_encrypted_export(&export_path, &password),
+        Err(VaultError::InvalidKey)
+    ));
+    let exported = vault_file_manager::read_encrypted_export(&export_path, &passphrase).unwrap();
+    let names: Vec<&String> = exported
+        .entries
+        .iter()
+        .map(|e| e.get_entry_name())
+        .collect();
+    assert_eq!(names, vec!["GitHub", "Gist"]);
+
+    session.end_session().unwrap();
+    let _ = delete_vault_file(vault_name);
+}
+
 // ============================================================================
 // SECURITY TESTS
 // ============================================================================
@@ -548,3 +910,322 @@ fn test_tampering_detection() {
 
     let _ = delete_vault_file(vault_name);
 }
+
+// ============================================================================
+// FORMAT TESTS
+// ============================================================================
+
+#[test]
+fn test_legacy_vault_is_migrated() {
+    let vault_name = "test_legacy_migration";
+    let password = SecretString::new("LegacyTest123!".to_string().into());
+
+    let _ = delete_vault_file(vault_name);
+
+    // Vaults of the first format are the plain enc_file ciphertext without a header
+    let legacy = Vault::new(vault_name.to_string());
+    let ciphertext = password_manager::crypto::encrypt_vault(
+        &password,
+        legacy.to_json(),
+        password_manager::vault_format::KdfSettings::default().to_params(),
+    )
+    .unwrap();
+    let path = get_vault_path(vault_name).unwrap();
+    std::fs::write(&path, ciphertext).unwrap();
+
+    let vault = open_vault(vault_name.to_string(), password.clone());
+    assert!(vault.is_ok(), "legacy vault could not be opened");
+
+    let contents = std::fs::read(&path).unwrap();
+    assert!(
+        contents.starts_with(password_manager::vault_format::MAGIC),
+        "vault was not upgraded"
+    );
+    assert!(
+        open_vault(vault_name.to_string(), password).is_ok(),
+        "upgraded vault could not be opened"
+    );
+
+    let _ = delete_vault_file(vault_name);
+    let _ = std::fs::remove_file(path.with_extension("psdb.v0"));
+}
+
+#[test]
+fn test_corrupt_file_is_not_a_wrong_password() {
+    let vault_name = "test_corrupt_file";
+    let password = SecretString::new("CorruptTest123!".to_string().into());
+
+    let path = get_vault_path(vault_name).unwrap();
+    std::fs::write(&path, b"this is not a vault").unwrap();
+
+    let result = open_vault(vault_name.to_string(), password);
+    assert!(matches!(result, Err(VaultError::CorruptVault)));
+
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_save_keeps_rolling_backups() {
+    use password_manager::vault_file_manager::{list_backups, restore_backup};
+
+    let vault_name = "test_rolling_backups";
+    let password = SecretString::new("BackupTest123!".to_string().into());
+
+    let _ = delete_vault_file(vault_name);
+
+    let mut vault = Vault::new(vault_name.to_string());
+    vault.settings.backup_count = 2;
+    close_vault(&vault, password.clone()).unwrap();
+    assert!(list_backups(vault_name).unwrap().is_empty());
+
+    for i in 0..3 {
+        vault
+            .add_entry(Entry::new(format!("entry{i}"), None, None, None, None))
+            .unwrap();
+        close_vault(&vault, password.clone()).unwrap();
+    }
+
+    let backups = list_backups(vault_name).unwrap();
+    assert_eq!(backups.len(), 2, "old backups were not pruned");
+    assert!(backups[0].created >= backups[1].created, "newest first");
+
+    // the newest backup was taken right before the last save, it holds two entries
+    restore_backup(vault_name, &backups[0]).unwrap();
+    let restored = open_vault(vault_name.to_string(), password).unwrap();
+    assert_eq!(restored.get_entries().len(), 2);
+
+    let _ = delete_vault_file(vault_name);
+    assert!(
+        list_backups(vault_name).unwrap().is_empty(),
+        "backups were not deleted with the vault"
+    );
+}
+
+#[test]
+fn test_clipboard_timeout_is_kept_across_saves() {
+    let vault_name = "test_clipboard_timeout";
+    let password = SecretString::new("ClipboardTest123!".to_string().into());
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+    let vault = session.opened_vault.as_mut().unwrap();
+    // without a timeout of its own the vault uses the one of the config
+    assert_eq!(vault.settings.clipboard_timeout, None);
+    assert_eq!(
+        vault.settings.effective_clipboard_timeout(),
+        config::DEFAULT_CLIPBOARD_TIMEOUT
+    );
+    vault.settings.clipboard_timeout = Some(10);
+    session.end_session().unwrap();
+
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password).unwrap();
+    assert_eq!(
+        session
+            .opened_vault
+            .as_ref()
+            .unwrap()
+            .settings
+            .clipboard_timeout,
+        Some(10)
+    );
+    session.end_session().unwrap();
+
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_kdf_settings_are_kept_across_saves() {
+    use password_manager::vault_file_manager::read_vault_header;
+    use password_manager::vault_format::KdfSettings;
+
+    let vault_name = "test_kdf_settings";
+    let password = SecretString::new("KdfTest123!".to_string().into());
+    let kdf = KdfSettings {
+        memory_kib: 64 * 1024,
+        iterations: 4,
+        parallelism: 1,
+    };
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault_with_options(vault_name.to_string(), password.clone(), kdf, None).unwrap();
+    assert_eq!(read_vault_header(vault_name).unwrap().unwrap().kdf, kdf);
+
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+    assert_eq!(session.opened_vault.as_ref().unwrap().settings.kdf, kdf);
+    session.end_session().unwrap();
+    assert_eq!(
+        read_vault_header(vault_name).unwrap().unwrap().kdf,
+        kdf,
+        "saving fell back to the default parameters"
+    );
+
+    let invalid = KdfSettings {
+        iterations: 1,
+        ..kdf
+    };
+    let result =
+        create_new_vault_with_options("test_kdf_invalid".to_string(), password, invalid, None);
+    assert!(matches!(result, Err(VaultError::InvalidKdfParameters(_))));
+    assert!(!get_vault_path("test_kdf_invalid").unwrap().exists());
+
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_vault_with_key_file() {
+    use password_manager::keyfile::KeyFile;
+    use password_manager::vault_file_manager::open_vault_with_key_file;
+    use password_manager::vault_format::KdfSettings;
+
+    let vault_name = "test_key_file";
+    let password = SecretString::new("KeyFileTest123!".to_string().into());
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = KeyFile::generate(&dir.path().join("vault.key")).unwrap();
+    let other_key_file = KeyFile::generate(&dir.path().join("other.key")).unwrap();
+
+    let _ = delete_vault_file(vault_name);
+    create_new_vault_with_options(
+        vault_name.to_string(),
+        password.clone(),
+        KdfSettings::default(),
+        Some(&key_file),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        open_vault(vault_name.to_string(), password.clone()),
+        Err(VaultError::KeyFileRequired)
+    ));
+    // nothing about the key file is stored, a wrong one fails like a wrong password
+    assert!(matches!(
+        open_vault_with_key_file(
+            vault_name.to_string(),
+            password.clone(),
+            Some(&other_key_file)
+        ),
+        Err(VaultError::InvalidKey)
+    ));
+
+    let vault = open_vault_with_key_file(vault_name.to_string(), password.clone(), Some(&key_file))
+        .unwrap();
+    assert!(
+        matches!(
+            close_vault(&vault, password.clone()),
+            Err(VaultError::KeyFileRequired)
+        ),
+        "key file was silently removed"
+    );
+
+    // removing the key file again
+    let mut session = Session::new(vault_name.to_string());
+    session
+        .start_session_with_key_file(password.clone(), Some(key_file))
+        .unwrap();
+    session.change_key_file(None);
+    session.end_session().unwrap();
+    assert!(open_vault(vault_name.to_string(), password).is_ok());
+
+    let _ = delete_vault_file(vault_name);
+}
+
+#[test]
+fn test_merge_copy_of_vault() {
+    let vault_name = "test_merge_original";
+    let password = SecretString::new("MergeTest123!".to_string().into());
+    let _ = delete_vault_file(vault_name);
+    create_new_vault(vault_name.to_string(), password.clone()).unwrap();
+
+    // the file is copied, and the copy changed like on another laptop
+    let dir = tempfile::tempdir().unwrap();
+    let copy_path = dir.path().join("test_merge_copy.psdb");
+    std::fs::copy(get_vault_path(vault_name).unwrap(), &copy_path).unwrap();
+    let copy_name = vault_file_manager::register_vault_file(&copy_path).unwrap();
+    let mut copy = Session::new(copy_name.clone());
+    copy.start_session(password.clone()).unwrap();
+    let entry = Entry::new(
+        "FromCopy".to_string(),
+        Some("copy@example.com".to_string()),
+        Some("password123".to_string()),
+        None,
+        None,
+    );
+    copy.opened_vault
+        .as_mut()
+        .unwrap()
+        .add_entry(entry)
+        .unwrap();
+    copy.end_session().unwrap();
+
+    // the copy is saved to itself, not to the vault it was copied from
+    let mut session = Session::new(vault_name.to_string());
+    session.start_session(password.clone()).unwrap();
+    assert!(session.opened_vault.as_ref().unwrap().entries.is_empty());
+
+    let other = session.read_copy(&copy_path, None).unwrap();
+    let vault = session.opened_vault.as_mut().unwrap();
+    let plan = merge::plan_merge(vault, &other);
+    assert!(plan.changes_vault(vault));
+    merge::apply_merge(vault, plan, &[]);
+    session.end_session().unwrap();
+
+    let mut reopened = Session::new(vault_name.to_string());
+    reopened.start_session(password).unwrap();
+    let vault = reopened.opened_vault.as_mut().unwrap();
+    assert!(vault.get_entry_by_name(&"FromCopy".to_string()).is_some());
+    reopened.end_session().unwrap();
+
+    delete_vault_file(vault_name).unwrap();
+    delete_vault_file(&copy_name).unwrap();
+}
+
+#[test]
+fn test_vault_file_outside_the_vault_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_portable_vault.psdb");
+    let password = SecretString::new("PortableTest123!".to_string().into());
+
+    let vault_name = vault_file_manager::register_vault_file(&path).unwrap();
+    assert_eq!(vault_name, "test_portable_vault");
+    create_new_vault(vault_name.clone(), password.clone()).unwrap();
+    assert!(path.exists(), "vault not created at the given path");
+    assert_eq!(get_vault_path(&vault_name).unwrap(), path);
+
+    let mut session = Session::new(vault_name.clone());
+    session.start_session(password.clone()).unwrap();
+    session.end_session().unwrap();
+    // the backup is kept next to the vault, not in the vault directory
+    assert_eq!(
+        vault_file_manager::list_backups(&vault_name).unwrap().len(),
+        1
+    );
+    assert!(dir.path().join("backups").is_dir());
+    assert!(
+        vault_file_manager::list_vaults()
+            .unwrap()
+            .contains(&vault_name)
+    );
+
+    // the file name has to be a valid vault name, and one not taken by another file
+    assert!(matches!(
+        vault_file_manager::register_vault_file(&dir.path().join("my.vault.psdb")),
+        Err(VaultError::InvalidVaultName)
+    ));
+    let other_dir = tempfile::tempdir().unwrap();
+    assert!(
+        vault_file_manager::register_vault_file(&other_dir.path().join("test_portable_vault.psdb"))
+            .is_err()
+    );
+    assert_eq!(
+        vault_file_manager::register_vault_file(&path).unwrap(),
+        vault_name
+    );
+
+    delete_vault_file(&vault_name).unwrap();
+    assert!(!path.exists());
+    assert!(vault_file_manager::registered_vault_file(&vault_name).is_none());
+}
@@ -39,7 +39,7 @@ browserAPI.storage.onChanged.addListener((changes, area) => {
 browserAPI.runtime.onMessage.addListener((msg, sender, sendResponse) => {
   console.log("Background received message:", msg);
 
-  if (msg.action === "fill" && msg.url) {
+  if ((msg.action === "fill" || msg.action === "totp") && msg.url) {
     getAuthToken()
       .then((token) => {
         if (!token) {
@@ -56,7 +56,7 @@ browserAPI.runtime.onMessage.addListener((msg, sender, sendResponse) => {
             "Content-Type": "application/json"
           },
           body: JSON.stringify({
-            action: "fill",
+            action: msg.action,
             url: msg.url,
             token: token
           })
@@ -22,6 +22,20 @@ function fillFields(username, password) {
   if (passField && password) { passField.value = password; passField.dispatchEvent(new Event('input', { bubbles: true })); }
 }
 
+function findOtpField() {
+  const byAutocomplete = document.querySelector('input[autocomplete="one-time-code"]');
+  if (byAutocomplete) return byAutocomplete;
+  const inputs = Array.from(document.querySelectorAll('input'));
+  return inputs.find(i => i.type !== 'password' && i.type !== 'hidden' &&
+    /otp|totp|2fa|mfa|one.?time|verification|auth.?code|token/i.test(i.name||i.id||i.placeholder||''));
+}
+
+function fillOtp(code) {
+  const field = findOtpField();
+  if (field && code) { field.value = code; field.dispatchEvent(new Event('input', { bubbles: true })); }
+}
+
 browserAPI.runtime.onMessage.addListener((msg) => {
   if (msg && msg.action === 'fill') fillFields(msg.username, msg.password);
+  if (msg && msg.action === 'fillOtp') fillOtp(msg.code);
 });
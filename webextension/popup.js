@@ -10,6 +10,7 @@ document.addEventListener('DOMContentLoaded', async () => {
   console.log('Popup loaded');
   
   const fillBtn = document.getElementById('fillBtn');
+  const fillOtpBtn = document.getElementById('fillOtpBtn');
   const settingsBtn = document.getElementById('settingsBtn');
   const closeErrorBtn = document.getElementById('closeErrorBtn');
   const closeSelectionBtn = document.getElementById('closeSelectionBtn');
@@ -29,6 +30,7 @@ document.addEventListener('DOMContentLoaded', async () => {
   
   // Add event listeners
   if (fillBtn) fillBtn.addEventListener('click', handleFillClick);
+  if (fillOtpBtn) fillOtpBtn.addEventListener('click', handleFillOtpClick);
   if (settingsBtn) settingsBtn.addEventListener('click', openSettingsModal);
   if (closeErrorBtn) closeErrorBtn.addEventListener('click', closeErrorModal);
   if (closeSelectionBtn) closeSelectionBtn.addEventListener('click', closeSelectionModal);
@@ -144,6 +146,61 @@ async function handleFillClick() {
   }
 }
 
+async function handleFillOtpClick() {
+  const [tab] = await browserAPI.tabs.query({ active: true, currentWindow: true });
+  if (!tab || !tab.url) {
+    showError('Error', 'Could not determine current page');
+    return;
+  }
+
+  try {
+    const response = await browserAPI.runtime.sendMessage({
+      action: 'totp',
+      url: tab.url
+    });
+
+    if (!response) {
+      showError('Error', 'No response from password manager');
+      return;
+    }
+    if (response.error) {
+      showError('Error', response.error);
+      return;
+    }
+    if (response.status === 'not_found') {
+      showError('No One-Time Code', `No TOTP secret stored for ${getDomain(tab.url)}`);
+      return;
+    }
+    if (response.status === 'error') {
+      showError('Error', response.message || 'Unknown error');
+      return;
+    }
+
+    if (response.status === 'ok' && response.mode === 'single') {
+      await fillOtp(tab.id, response.code);
+      window.close();
+      return;
+    }
+
+    if (response.status === 'ok' && response.mode === 'multiple' && response.entries) {
+      showSelectionModal(response.entries, tab.id, (entry) => fillOtp(tab.id, entry.code));
+      return;
+    }
+  } catch (error) {
+    console.error('Error:', error);
+    showError('Error', `Failed to get one-time code: ${error.message}`);
+  }
+}
+
+async function fillOtp(tabId, code) {
+  try {
+    await browserAPI.tabs.sendMessage(tabId, { action: 'fillOtp', code: code });
+  } catch (error) {
+    console.error('Failed to fill one-time code:', error);
+    showError('Fill Failed', 'Could not fill the one-time code');
+  }
+}
+
 function getDomain(url) {
   try {
     return new URL(url).hostname;
@@ -162,7 +219,7 @@ function closeErrorModal() {
   document.getElementById('errorModal').classList.remove('show');
 }
 
-function showSelectionModal(entries, tabId) {
+function showSelectionModal(entries, tabId, onSelect) {
   const entryList = document.getElementById('entryList');
   entryList.innerHTML = '';
 
@@ -174,7 +231,11 @@ function showSelectionModal(entries, tabId) {
       <div class="entry-user">${entry.username || 'No username'}</div>
     `;
     div.addEventListener('click', async () => {
-      await fillPage(tabId, entry);
+      if (onSelect) {
+        await onSelect(entry);
+      } else {
+        await fillPage(tabId, entry);
+      }
       resetPopupHeight();
       window.close();
     });